@@ -0,0 +1,2 @@
+ALTER TABLE accounts
+DROP COLUMN initial_balance;
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE accounts
+ADD COLUMN initial_balance FLOAT NOT NULL DEFAULT 0;
+
+UPDATE accounts SET initial_balance = last_calculated_balance;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE transactions
+DROP COLUMN voided;
\ No newline at end of file
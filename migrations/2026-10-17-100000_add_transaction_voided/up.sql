@@ -0,0 +1,2 @@
+ALTER TABLE transactions
+ADD COLUMN voided BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
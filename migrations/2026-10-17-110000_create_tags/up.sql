@@ -0,0 +1,14 @@
+CREATE TABLE tags (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    related_user UUID NOT NULL,
+    name TEXT NOT NULL,
+    color TEXT,
+    time TIMESTAMP NOT NULL,
+    UNIQUE (related_user, name)
+);
+
+CREATE TABLE account_tags (
+    account_id UUID NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (account_id, tag_id)
+);
\ No newline at end of file
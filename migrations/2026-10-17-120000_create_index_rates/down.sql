@@ -0,0 +1,9 @@
+ALTER TABLE accounts
+DROP COLUMN last_accrual_date;
+
+ALTER TABLE transactions
+DROP COLUMN kind;
+
+DROP TYPE transaction_kind_enum;
+
+DROP TABLE index_rates;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE index_rates (
+    earning_index earning_index_enum NOT NULL,
+    date DATE NOT NULL,
+    rate FLOAT NOT NULL,
+    PRIMARY KEY (earning_index, date)
+);
+
+CREATE TYPE transaction_kind_enum AS ENUM ('manual', 'earning');
+
+ALTER TABLE transactions
+ADD COLUMN kind transaction_kind_enum NOT NULL DEFAULT 'manual';
+
+ALTER TABLE accounts
+ADD COLUMN last_accrual_date DATE;
\ No newline at end of file
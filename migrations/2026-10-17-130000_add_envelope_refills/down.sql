@@ -0,0 +1,16 @@
+ALTER TYPE transaction_kind_enum RENAME TO transaction_kind_enum_old;
+
+CREATE TYPE transaction_kind_enum AS ENUM ('manual', 'earning');
+
+ALTER TABLE transactions
+    ALTER COLUMN kind DROP DEFAULT,
+    ALTER COLUMN kind TYPE transaction_kind_enum USING (
+        CASE WHEN kind::TEXT IN ('refill', 'sweep') THEN 'manual' ELSE kind::TEXT END
+    )::transaction_kind_enum,
+    ALTER COLUMN kind SET DEFAULT 'manual';
+
+DROP TYPE transaction_kind_enum_old;
+
+ALTER TABLE accounts
+DROP COLUMN last_refill_date,
+DROP COLUMN pre_allocation_source;
\ No newline at end of file
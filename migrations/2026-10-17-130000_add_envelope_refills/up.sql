@@ -0,0 +1,6 @@
+ALTER TABLE accounts
+ADD COLUMN pre_allocation_source UUID,
+ADD COLUMN last_refill_date DATE;
+
+ALTER TYPE transaction_kind_enum ADD VALUE 'refill';
+ALTER TYPE transaction_kind_enum ADD VALUE 'sweep';
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE user_integrations
+DROP COLUMN state,
+DROP COLUMN sent_to,
+DROP COLUMN status;
+
+DROP TYPE integration_status_enum;
\ No newline at end of file
@@ -0,0 +1,6 @@
+CREATE TYPE integration_status_enum AS ENUM ('created', 'pending', 'connected');
+
+ALTER TABLE user_integrations
+ADD COLUMN status integration_status_enum NOT NULL DEFAULT 'created',
+ADD COLUMN sent_to TEXT,
+ADD COLUMN state BYTEA;
\ No newline at end of file
@@ -0,0 +1,20 @@
+ALTER TABLE user_integrations
+DROP COLUMN account_id;
+
+DROP INDEX transactions_related_user_external_id_key;
+
+ALTER TABLE transactions
+DROP COLUMN external_id;
+
+ALTER TYPE transaction_kind_enum RENAME TO transaction_kind_enum_old;
+
+CREATE TYPE transaction_kind_enum AS ENUM ('manual', 'earning', 'refill', 'sweep');
+
+ALTER TABLE transactions
+    ALTER COLUMN kind DROP DEFAULT,
+    ALTER COLUMN kind TYPE transaction_kind_enum USING (
+        CASE WHEN kind::TEXT = 'import' THEN 'manual' ELSE kind::TEXT END
+    )::transaction_kind_enum,
+    ALTER COLUMN kind SET DEFAULT 'manual';
+
+DROP TYPE transaction_kind_enum_old;
\ No newline at end of file
@@ -0,0 +1,10 @@
+ALTER TYPE transaction_kind_enum ADD VALUE 'import';
+
+ALTER TABLE transactions
+ADD COLUMN external_id TEXT;
+
+CREATE UNIQUE INDEX transactions_related_user_external_id_key
+ON transactions (related_user, external_id);
+
+ALTER TABLE user_integrations
+ADD COLUMN account_id UUID;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE sessions (
+    id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+    related_user UUID NOT NULL,
+    refresh_token_hash TEXT NOT NULL UNIQUE,
+    device_name TEXT,
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX sessions_related_user_idx ON sessions (related_user);
\ No newline at end of file
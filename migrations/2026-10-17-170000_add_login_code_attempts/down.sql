@@ -0,0 +1,3 @@
+ALTER TABLE users
+DROP COLUMN login_code_attempts,
+DROP COLUMN login_locked_until;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE users
+ADD COLUMN login_code_attempts INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN login_locked_until TIMESTAMP;
\ No newline at end of file
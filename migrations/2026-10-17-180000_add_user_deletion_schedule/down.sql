@@ -0,0 +1,2 @@
+ALTER TABLE users
+DROP COLUMN deletion_scheduled_for;
\ No newline at end of file
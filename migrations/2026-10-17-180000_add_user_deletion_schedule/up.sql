@@ -0,0 +1,2 @@
+ALTER TABLE users
+ADD COLUMN deletion_scheduled_for TIMESTAMP;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE users
+DROP COLUMN pending_email,
+DROP COLUMN email_change_code,
+DROP COLUMN email_change_requested_at,
+DROP COLUMN email_change_attempts;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE users
+ADD COLUMN pending_email TEXT,
+ADD COLUMN email_change_code INTEGER,
+ADD COLUMN email_change_requested_at TIMESTAMP,
+ADD COLUMN email_change_attempts INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,4 @@
+DROP INDEX accounts_related_user_name_key;
+
+ALTER TABLE accounts
+ADD CONSTRAINT accounts_name_key UNIQUE (name);
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE accounts
+DROP CONSTRAINT accounts_name_key;
+
+-- Trashed accounts don't hold on to their names.
+CREATE UNIQUE INDEX accounts_related_user_name_key ON accounts (related_user, name)
+WHERE NOT in_trash;
\ No newline at end of file
@@ -0,0 +1,9 @@
+ALTER TABLE transactions
+ALTER COLUMN amount TYPE FLOAT USING amount / 100.0;
+
+ALTER TABLE accounts
+ALTER COLUMN last_calculated_balance TYPE FLOAT USING last_calculated_balance / 100.0,
+ALTER COLUMN pre_allocation_amount TYPE FLOAT USING pre_allocation_amount / 100.0,
+ALTER COLUMN initial_balance DROP DEFAULT,
+ALTER COLUMN initial_balance TYPE FLOAT USING initial_balance / 100.0,
+ALTER COLUMN initial_balance SET DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Amounts are kept as whole cents so balances never pick up float error.
+ALTER TABLE transactions
+ALTER COLUMN amount TYPE BIGINT USING ROUND((amount * 100)::NUMERIC)::BIGINT;
+
+ALTER TABLE accounts
+ALTER COLUMN last_calculated_balance TYPE BIGINT
+    USING ROUND((last_calculated_balance * 100)::NUMERIC)::BIGINT,
+ALTER COLUMN pre_allocation_amount TYPE BIGINT
+    USING ROUND((pre_allocation_amount * 100)::NUMERIC)::BIGINT,
+ALTER COLUMN initial_balance DROP DEFAULT,
+ALTER COLUMN initial_balance TYPE BIGINT
+    USING ROUND((initial_balance * 100)::NUMERIC)::BIGINT,
+ALTER COLUMN initial_balance SET DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE rotated_refresh_tokens;
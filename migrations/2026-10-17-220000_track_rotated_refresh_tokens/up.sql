@@ -0,0 +1,9 @@
+-- Refresh tokens that were already exchanged for a new one, so using one
+-- again can be told apart from using a token that never existed.
+CREATE TABLE rotated_refresh_tokens (
+    token_hash TEXT PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+    rotated_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX rotated_refresh_tokens_session_id_idx ON rotated_refresh_tokens (session_id);
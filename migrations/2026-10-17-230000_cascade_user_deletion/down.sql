@@ -0,0 +1,5 @@
+ALTER TABLE accounts DROP CONSTRAINT accounts_related_user_fkey;
+ALTER TABLE sessions DROP CONSTRAINT sessions_related_user_fkey;
+ALTER TABLE tags DROP CONSTRAINT tags_related_user_fkey;
+ALTER TABLE transactions DROP CONSTRAINT transactions_related_user_fkey;
+ALTER TABLE user_integrations DROP CONSTRAINT user_integrations_related_user_fkey;
@@ -0,0 +1,18 @@
+-- Deleting a user deletes everything they own. Rows left behind by users
+-- deleted before this have nobody to belong to anymore.
+DELETE FROM accounts WHERE related_user NOT IN (SELECT id FROM users);
+DELETE FROM sessions WHERE related_user NOT IN (SELECT id FROM users);
+DELETE FROM tags WHERE related_user NOT IN (SELECT id FROM users);
+DELETE FROM transactions WHERE related_user NOT IN (SELECT id FROM users);
+DELETE FROM user_integrations WHERE related_user NOT IN (SELECT id FROM users);
+
+ALTER TABLE accounts ADD CONSTRAINT accounts_related_user_fkey
+    FOREIGN KEY (related_user) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE sessions ADD CONSTRAINT sessions_related_user_fkey
+    FOREIGN KEY (related_user) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE tags ADD CONSTRAINT tags_related_user_fkey
+    FOREIGN KEY (related_user) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE transactions ADD CONSTRAINT transactions_related_user_fkey
+    FOREIGN KEY (related_user) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE user_integrations ADD CONSTRAINT user_integrations_related_user_fkey
+    FOREIGN KEY (related_user) REFERENCES users (id) ON DELETE CASCADE;
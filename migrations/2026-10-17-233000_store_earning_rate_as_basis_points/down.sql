@@ -0,0 +1,2 @@
+ALTER TABLE accounts
+ALTER COLUMN earning_rate TYPE FLOAT USING earning_rate / 100.0;
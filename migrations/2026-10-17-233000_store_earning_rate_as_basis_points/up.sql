@@ -0,0 +1,4 @@
+-- Earning rates are kept as whole basis points (hundredths of a percent),
+-- so 110% of the CDI is 11000.
+ALTER TABLE accounts
+ALTER COLUMN earning_rate TYPE INTEGER USING ROUND((earning_rate * 100)::NUMERIC)::INTEGER;
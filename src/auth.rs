@@ -0,0 +1,240 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::{entities::session, jwt};
+
+#[derive(Debug)]
+pub enum AuthError {
+    NotAuthenticated,
+    InvalidToken(jwt::JwtError),
+    InvalidAuthorizationHeader,
+    RevokedSession,
+    SessionModelFailed(session::SessionModelError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// The token sent in the `Authorization: Bearer <token>` header, if any.
+pub struct BearerToken(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        Outcome::Success(BearerToken(token))
+    }
+}
+
+// A valid signature is not enough for access tokens: the session they were
+// issued to must still be active.
+pub fn verify_token<T: session::SessionModel>(
+    database: &T,
+    now: NaiveDateTime,
+    token: &str,
+    jwt_secret: &str,
+) -> Result<jwt::Claims, AuthError> {
+    let claims = jwt::verify_token(now, token, jwt_secret).map_err(AuthError::InvalidToken)?;
+    check_session(database, &claims)?;
+    Ok(claims)
+}
+
+// Whether the session of already verified claims is still active, for
+// connections that outlive the request that authenticated them.
+pub fn check_session<T: session::SessionModel>(
+    database: &T,
+    claims: &jwt::Claims,
+) -> Result<(), AuthError> {
+    match database.get_session(&claims.session) {
+        Ok(s) if s.is_active() && s.related_user == claims.user_id => Ok(()),
+        Ok(_) | Err(session::SessionModelError::SessionNotFound) => Err(AuthError::RevokedSession),
+        Err(err) => Err(AuthError::SessionModelFailed(err)),
+    }
+}
+
+// Who is making the request, decided once when it arrives. A bad header is
+// kept instead of rejecting the request so fields that need no user, like
+// logging in, still work.
+#[derive(Clone, Debug)]
+pub enum Authentication {
+    Anonymous,
+    User(jwt::Claims),
+    Invalid,
+}
+
+impl Authentication {
+    pub fn from_token<T: session::SessionModel>(
+        database: &T,
+        now: NaiveDateTime,
+        token: Option<&str>,
+        jwt_secret: &str,
+    ) -> Self {
+        match token {
+            None => Authentication::Anonymous,
+            Some(token) => match verify_token(database, now, token, jwt_secret) {
+                Ok(claims) => Authentication::User(claims),
+                Err(err) => {
+                    log::debug!("Rejected Authorization header: {:?}", err);
+                    Authentication::Invalid
+                }
+            },
+        }
+    }
+
+    // The header always wins. The token argument of the GraphQL fields is
+    // only looked at for requests without one.
+    pub fn claims<T: session::SessionModel>(
+        &self,
+        database: &T,
+        now: NaiveDateTime,
+        fallback_token: Option<&str>,
+        jwt_secret: &str,
+    ) -> Result<jwt::Claims, AuthError> {
+        match self {
+            Authentication::User(claims) => Ok(*claims),
+            Authentication::Invalid => Err(AuthError::InvalidAuthorizationHeader),
+            Authentication::Anonymous => match fallback_token {
+                Some(token) => verify_token(database, now, token, jwt_secret),
+                None => Err(AuthError::NotAuthenticated),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use super::*;
+    use crate::entities::session::SessionModel;
+    use crate::models::memory::InMemoryStore;
+    use uuid::Uuid;
+
+    const SECRET: &str = "1234567890987654321";
+    const NOW: i64 = 1665366563127;
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(NOW, 0)
+    }
+
+    fn new_session(db: &InMemoryStore, user_id: Uuid) -> session::Session {
+        db.create_session(session::NewSession {
+            related_user: user_id,
+            refresh_token_hash: Uuid::new_v4().to_string(),
+            device_name: None,
+            time: now(),
+        })
+        .unwrap()
+    }
+
+    // An access token of a new active session of the user.
+    fn token_for(db: &InMemoryStore, user_id: Uuid) -> String {
+        let active = new_session(db, user_id);
+        jwt::generate_access_token(now(), &user_id, &active.id, SECRET).unwrap()
+    }
+
+    #[test]
+    fn header_user_wins_over_token_argument() {
+        let db = InMemoryStore::default();
+        let header_user = Uuid::new_v4();
+        let header = token_for(&db, header_user);
+        let argument = token_for(&db, Uuid::new_v4());
+        let auth = Authentication::from_token(&db, now(), Some(&header), SECRET);
+        assert_eq!(
+            auth.claims(&db, now(), Some(&argument), SECRET)
+                .unwrap()
+                .user_id,
+            header_user
+        );
+    }
+
+    #[test]
+    fn token_argument_is_a_fallback_without_header() {
+        let db = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let argument = token_for(&db, user_id);
+        let auth = Authentication::from_token(&db, now(), None, SECRET);
+        assert_eq!(
+            auth.claims(&db, now(), Some(&argument), SECRET)
+                .unwrap()
+                .user_id,
+            user_id
+        );
+        assert!(matches!(
+            auth.claims(&db, now(), None, SECRET),
+            Err(AuthError::NotAuthenticated)
+        ));
+    }
+
+    #[test]
+    fn invalid_header_is_not_replaced_by_token_argument() {
+        let db = InMemoryStore::default();
+        let argument = token_for(&db, Uuid::new_v4());
+        let auth = Authentication::from_token(&db, now(), Some("not a token"), SECRET);
+        assert!(matches!(
+            auth.claims(&db, now(), Some(&argument), SECRET),
+            Err(AuthError::InvalidAuthorizationHeader)
+        ));
+    }
+
+    #[test]
+    fn access_token_is_rejected_once_its_session_is_revoked() {
+        let db = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let token = token_for(&db, user_id);
+        assert!(verify_token(&db, now(), &token, SECRET).is_ok());
+        db.revoke_user_sessions(&user_id, now()).unwrap();
+        assert!(matches!(
+            verify_token(&db, now(), &token, SECRET),
+            Err(AuthError::RevokedSession)
+        ));
+    }
+
+    #[test]
+    fn access_token_of_active_session_is_accepted() {
+        let db = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let active = new_session(&db, user_id);
+        let token = jwt::generate_access_token(now(), &user_id, &active.id, SECRET).unwrap();
+        let claims = verify_token(&db, now(), &token, SECRET).unwrap();
+        assert_eq!(claims.user_id, user_id);
+        assert_eq!(claims.session, active.id);
+    }
+
+    #[test]
+    fn access_token_of_revoked_or_unknown_session_is_rejected() {
+        let db = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let revoked = new_session(&db, user_id);
+        db.revoke_session(&revoked.id, &user_id, now()).unwrap();
+        let token = jwt::generate_access_token(now(), &user_id, &revoked.id, SECRET).unwrap();
+        assert!(matches!(
+            verify_token(&db, now(), &token, SECRET),
+            Err(AuthError::RevokedSession)
+        ));
+        assert!(matches!(
+            verify_token(&InMemoryStore::default(), now(), &token, SECRET),
+            Err(AuthError::RevokedSession)
+        ));
+    }
+
+    #[test]
+    fn access_token_of_someone_elses_session_is_rejected() {
+        let db = InMemoryStore::default();
+        let other = new_session(&db, Uuid::new_v4());
+        let token = jwt::generate_access_token(now(), &Uuid::new_v4(), &other.id, SECRET).unwrap();
+        assert!(matches!(
+            verify_token(&db, now(), &token, SECRET),
+            Err(AuthError::RevokedSession)
+        ));
+    }
+}
@@ -1,14 +1,50 @@
+use std::time::Duration;
+
 use diesel::{
-    r2d2::{ConnectionManager, Pool},
-    PgConnection,
+    r2d2::{ConnectionManager, CustomizeConnection, Error as PoolError, Pool},
+    PgConnection, RunQueryDsl,
 };
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
-pub fn establish_pooled_connection(database_url: String) -> DbPool {
+#[derive(Clone, Copy, Debug)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    // How long to wait for a free connection before giving up.
+    pub connection_timeout: Duration,
+    // Queries running longer than this are cancelled by Postgres.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 10,
+            connection_timeout: Duration::from_secs(30),
+            statement_timeout: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StatementTimeout(Duration);
+
+impl CustomizeConnection<PgConnection, PoolError> for StatementTimeout {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), PoolError> {
+        diesel::sql_query(format!("SET statement_timeout = {}", self.0.as_millis()))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(PoolError::QueryError)
+    }
+}
+
+pub fn establish_pooled_connection(database_url: String, config: &PoolConfig) -> DbPool {
     let manager = ConnectionManager::<PgConnection>::new(&database_url);
-    let pool = Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool.");
-    pool
+    let mut builder = Pool::builder()
+        .max_size(config.max_size)
+        .connection_timeout(config.connection_timeout);
+    if let Some(timeout) = config.statement_timeout {
+        builder = builder.connection_customizer(Box::new(StatementTimeout(timeout)));
+    }
+    builder.build(manager).expect("Failed to create pool.")
 }
@@ -0,0 +1,101 @@
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    FailedToGenerateNonce(openssl::error::ErrorStack),
+    FailedToEncrypt(openssl::error::ErrorStack),
+    FailedToDecrypt(openssl::error::ErrorStack),
+    CiphertextTooShort(usize),
+}
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// Keys are derived from the application secret, with a per-purpose label so
+// the same secret never encrypts two kinds of data with the same key.
+pub fn derive_key(secret: &str, purpose: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(purpose.as_bytes());
+    hasher.update(b":");
+    hasher.update(secret.as_bytes());
+    hasher.finalize().into()
+}
+
+// AES-256-GCM. The output is nonce || ciphertext || tag.
+pub fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand_bytes(&mut nonce).map_err(EncryptionError::FailedToGenerateNonce)?;
+    let mut tag = [0u8; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        &[],
+        plaintext,
+        &mut tag,
+    )
+    .map_err(EncryptionError::FailedToEncrypt)?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len() + TAG_LEN);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed.extend_from_slice(&tag);
+    Ok(sealed)
+}
+
+pub fn decrypt(key: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err(EncryptionError::CiphertextTooShort(sealed.len()));
+    }
+    let (nonce, rest) = sealed.split_at(NONCE_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        &[],
+        ciphertext,
+        tag,
+    )
+    .map_err(EncryptionError::FailedToDecrypt)
+}
+
+#[cfg(test)]
+mod encryption_tests {
+    use super::*;
+
+    const SECRET: &str = "1234567890987654321";
+
+    #[test]
+    fn can_decrypt_what_was_encrypted() {
+        let key = derive_key(SECRET, "test");
+        let sealed = encrypt(&key, b"some state").unwrap();
+        assert_eq!(decrypt(&key, &sealed).unwrap(), b"some state");
+    }
+
+    #[test]
+    fn same_plaintext_encrypts_differently() {
+        let key = derive_key(SECRET, "test");
+        assert_ne!(
+            encrypt(&key, b"some state").unwrap(),
+            encrypt(&key, b"some state").unwrap()
+        );
+    }
+
+    #[test]
+    fn cant_decrypt_with_another_purpose_key() {
+        let sealed = encrypt(&derive_key(SECRET, "test"), b"some state").unwrap();
+        assert!(decrypt(&derive_key(SECRET, "other"), &sealed).is_err());
+    }
+
+    #[test]
+    fn cant_decrypt_tampered_ciphertext() {
+        let key = derive_key(SECRET, "test");
+        let mut sealed = encrypt(&key, b"some state").unwrap();
+        sealed[NONCE_LEN] ^= 1;
+        assert!(decrypt(&key, &sealed).is_err());
+        assert!(decrypt(&key, &sealed[..4]).is_err());
+    }
+}
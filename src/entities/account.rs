@@ -1,7 +1,12 @@
-use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
 use uuid::Uuid;
 
-#[derive(Copy, Clone, Debug)]
+use crate::entities::money::Money;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum EarningIndex {
     CDI,
     FIXED,
@@ -10,10 +15,14 @@ pub enum EarningIndex {
 
 #[derive(Copy, Clone, Debug)]
 pub struct PreAllocation {
-    pub amount: f64,
+    pub amount: Money,
     pub accumulative: bool,
+    // Account the envelope is refilled from on every cycle.
+    pub source: Option<Uuid>,
 }
 
+// The rate is a percentage kept to the hundredth: the annual rate when FIXED,
+// the share of the CDI when CDI and the annual rate above inflation when IPCA.
 #[derive(Copy, Clone, Debug)]
 pub struct Earning {
     pub rate: f64,
@@ -26,11 +35,13 @@ pub struct Account {
     pub time: NaiveDateTime,
     pub name: String,
     pub description: Option<String>,
-    pub balance: f64,
+    pub balance: Money,
     pub pre_allocation: Option<PreAllocation>,
     pub earning: Option<Earning>,
     pub is_available: bool,
     pub in_trash: bool,
+    pub last_accrual_date: Option<NaiveDate>,
+    pub last_refill_date: Option<NaiveDate>,
 }
 
 #[derive(Debug)]
@@ -46,7 +57,7 @@ pub struct UpdatedAccount {
 #[derive(Debug)]
 pub struct NewAccount {
     pub time: NaiveDateTime,
-    pub initial_balance: f64,
+    pub initial_balance: Money,
     pub name: String,
     pub description: Option<String>,
     pub pre_allocation: Option<PreAllocation>,
@@ -62,9 +73,13 @@ pub enum AccountModelError {
     FailedToGetAccount(diesel::result::Error),
     AccountNotFound,
     MultipleAccountWithSameId,
+    // The user has another account, not in the trash, with the same name.
+    NameAlreadyTaken,
     FailedToCreateAccount(diesel::result::Error),
     FailedToDeleteAccount(Box<AccountModelError>),
     FailedToUpdateAccount(diesel::result::Error),
+    FailedToRecalculateBalances(diesel::result::Error),
+    DatabaseTransactionFailed(diesel::result::Error),
 }
 
 impl From<r2d2::Error> for AccountModelError {
@@ -73,12 +88,21 @@ impl From<r2d2::Error> for AccountModelError {
     }
 }
 
+impl From<diesel::result::Error> for AccountModelError {
+    fn from(error: diesel::result::Error) -> Self {
+        AccountModelError::DatabaseTransactionFailed(error)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, AccountModelError>;
 
 pub trait AccountModel {
     fn create_account(&self, user_id: Uuid, new_account: NewAccount) -> Result<Account>;
     fn get_account(&self, id: &Uuid, user_id: &Uuid) -> Result<Account>;
     fn get_accounts(&self, user_id: &Uuid) -> Result<Vec<Account>>;
+    // Names of the given accounts in a single lookup. Ids the user does not
+    // own are left out.
+    fn get_account_names(&self, ids: &[Uuid], user_id: &Uuid) -> Result<HashMap<Uuid, String>>;
     fn delete_account(&self, id: &Uuid, user_id: &Uuid) -> Result<()>;
     fn edit_account(
         &self,
@@ -86,4 +110,7 @@ pub trait AccountModel {
         user_id: &Uuid,
         updated_account: UpdatedAccount,
     ) -> Result<Account>;
+    fn recalculate_balances(&self, user_id: &Uuid) -> Result<Vec<Account>>;
+    fn list_users_with_envelopes(&self) -> Result<Vec<Uuid>>;
+    fn list_users_with_earnings(&self) -> Result<Vec<Uuid>>;
 }
@@ -0,0 +1,21 @@
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use crate::entities::money::Money;
+
+#[derive(Clone, Debug)]
+pub struct EnvelopeStatus {
+    pub account_id: Uuid,
+    pub name: String,
+    pub accumulative: bool,
+    pub allocated: Money,
+    pub spent: Money,
+    pub remaining: Money,
+}
+
+#[derive(Clone, Debug)]
+pub struct BudgetStatus {
+    pub cycle_start: NaiveDate,
+    pub cycle_end: NaiveDate,
+    pub envelopes: Vec<EnvelopeStatus>,
+}
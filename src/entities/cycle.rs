@@ -0,0 +1,133 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+// A financial cycle, from one payday to the day before the next one. Both
+// ends are inclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+fn month_start(year: i32, month: u32) -> NaiveDate {
+    NaiveDate::from_ymd(year, month, 1)
+}
+
+fn previous_month(date: NaiveDate) -> NaiveDate {
+    (month_start(date.year(), date.month()) - Duration::days(1))
+        .with_day(1)
+        .unwrap()
+}
+
+fn next_month(date: NaiveDate) -> NaiveDate {
+    (month_start(date.year(), date.month()) + Duration::days(31))
+        .with_day(1)
+        .unwrap()
+}
+
+fn last_day_of_month(date: NaiveDate) -> NaiveDate {
+    next_month(date) - Duration::days(1)
+}
+
+// The day the salary is paid in the month of `month`. Paydays past the end of
+// a short month fall on its last day, and paydays on weekends are brought
+// forward to the previous Friday. Without a payday, cycles are calendar
+// months.
+pub fn payday_in_month(month: NaiveDate, payday: Option<i32>) -> NaiveDate {
+    let payday = match payday {
+        Some(payday) => payday.clamp(1, 31) as u32,
+        None => return month.with_day(1).unwrap(),
+    };
+    let last_day = last_day_of_month(month);
+    let date = last_day.with_day(payday.min(last_day.day())).unwrap();
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date - Duration::days(2),
+        _ => date,
+    }
+}
+
+// The cycle containing `date`. Weekend adjustments can move a payday into
+// the previous month, so the neighbouring months are checked as well.
+pub fn cycle_of(date: NaiveDate, payday: Option<i32>) -> Cycle {
+    let paydays = [
+        payday_in_month(previous_month(date), payday),
+        payday_in_month(date, payday),
+        payday_in_month(next_month(date), payday),
+        payday_in_month(next_month(next_month(date)), payday),
+    ];
+    let start_index = paydays.iter().rposition(|d| *d <= date).unwrap();
+    Cycle {
+        start: paydays[start_index],
+        end: paydays[start_index + 1] - Duration::days(1),
+    }
+}
+
+// Every cycle that overlaps the [from, to] interval, in chronological order.
+pub fn cycles_between(from: NaiveDate, to: NaiveDate, payday: Option<i32>) -> Vec<Cycle> {
+    let mut cycles = Vec::new();
+    let mut date = from;
+    while date <= to {
+        let cycle = cycle_of(date, payday);
+        date = cycle.end + Duration::days(1);
+        cycles.push(cycle);
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod cycle_tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn without_payday_cycles_are_calendar_months() {
+        let cycle = cycle_of(day(2022, 2, 14), None);
+        assert_eq!(cycle.start, day(2022, 2, 1));
+        assert_eq!(cycle.end, day(2022, 2, 28));
+    }
+
+    #[test]
+    fn cycle_goes_from_payday_to_the_day_before_the_next() {
+        // 2022-03-10 and 2022-04-08 are weekdays, 2022-04-10 is a Sunday.
+        let cycle = cycle_of(day(2022, 3, 20), Some(10));
+        assert_eq!(cycle.start, day(2022, 3, 10));
+        assert_eq!(cycle.end, day(2022, 4, 7));
+
+        let cycle = cycle_of(day(2022, 3, 9), Some(10));
+        assert_eq!(cycle.start, day(2022, 2, 10));
+        assert_eq!(cycle.end, day(2022, 3, 9));
+    }
+
+    #[test]
+    fn payday_past_the_end_of_the_month_falls_on_its_last_day() {
+        // 2022-02-28 is a Monday.
+        assert_eq!(payday_in_month(day(2022, 2, 1), Some(31)), day(2022, 2, 28));
+        // 2022-04-30 is a Saturday.
+        assert_eq!(payday_in_month(day(2022, 4, 1), Some(31)), day(2022, 4, 29));
+    }
+
+    #[test]
+    fn weekend_payday_is_brought_forward_to_friday() {
+        // 2022-05-01 is a Sunday, so May's payday is paid on April 29th.
+        assert_eq!(payday_in_month(day(2022, 5, 1), Some(1)), day(2022, 4, 29));
+        let cycle = cycle_of(day(2022, 4, 30), Some(1));
+        assert_eq!(cycle.start, day(2022, 4, 29));
+        assert_eq!(cycle.end, day(2022, 5, 31));
+    }
+
+    #[test]
+    fn cycles_between_covers_the_interval() {
+        let cycles = cycles_between(day(2022, 1, 15), day(2022, 3, 15), Some(10));
+        let starts: Vec<NaiveDate> = cycles.iter().map(|c| c.start).collect();
+        assert_eq!(
+            starts,
+            vec![day(2022, 1, 10), day(2022, 2, 10), day(2022, 3, 10)]
+        );
+        for pair in cycles.windows(2) {
+            assert_eq!(pair[0].end + Duration::days(1), pair[1].start);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use crate::entities::{account::EarningIndex, money::Money};
+
+// A published index value. CDI rates are annual percentages for a single
+// business day; IPCA rates are monthly percentages dated on the first day of
+// their month.
+#[derive(Copy, Clone, Debug)]
+pub struct IndexRate {
+    pub index: EarningIndex,
+    pub date: NaiveDate,
+    pub rate: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct EarningProjection {
+    pub account_id: Uuid,
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+    pub balance: Money,
+    pub projected_balance: Money,
+    pub projected_earnings: Money,
+}
+
+// Model-related things
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum IndexRateModelError {
+    FailedToGetConn(r2d2::Error),
+    FailedToSaveIndexRates(diesel::result::Error),
+    FailedToListIndexRates(diesel::result::Error),
+}
+
+impl From<r2d2::Error> for IndexRateModelError {
+    fn from(error: r2d2::Error) -> Self {
+        IndexRateModelError::FailedToGetConn(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, IndexRateModelError>;
+
+pub trait IndexRateModel {
+    // Inserts the rates, replacing any rate already stored for the same day.
+    fn save_index_rates(&self, rates: Vec<IndexRate>) -> Result<usize>;
+    fn list_index_rates(&self, index: EarningIndex) -> Result<Vec<IndexRate>>;
+}
@@ -0,0 +1,14 @@
+use chrono::NaiveDateTime;
+
+// A link to download everything the user has, valid until `expires_at`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportLink {
+    pub url: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportArchive {
+    pub file_name: String,
+    pub content: Vec<u8>,
+}
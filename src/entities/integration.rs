@@ -1,12 +1,29 @@
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
+// Where an integration is in its onboarding. Plain integrations stay
+// CREATED; Nubank ones wait in PENDING until the user submits the code
+// they received and become CONNECTED once a certificate is issued.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum IntegrationStatus {
+    CREATED,
+    PENDING,
+    CONNECTED,
+}
+
 #[derive(Clone)]
 pub struct UserIntegration {
     pub id: Uuid,
     pub related_user: Uuid,
     pub name: String,
     pub time: NaiveDateTime,
+    pub status: IntegrationStatus,
+    pub sent_to: Option<String>,
+    // Encrypted provider state, only readable by the service that wrote it.
+    pub state: Option<Vec<u8>>,
+    // Account that receives the imported transactions.
+    pub account_id: Option<Uuid>,
 }
 
 #[derive(Clone)]
@@ -14,16 +31,46 @@ pub struct NewUserIntegration {
     pub related_user: Uuid,
     pub name: String,
     pub time: NaiveDateTime,
+    pub status: IntegrationStatus,
+    pub sent_to: Option<String>,
+    pub state: Option<Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct UpdatedIntegrationState {
+    pub status: IntegrationStatus,
+    pub sent_to: Option<String>,
+    pub state: Option<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub imported: i32,
+    pub skipped: i32,
+    pub failed: i32,
+}
+
+// How far a sync got through the events it fetched.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub integration_id: Uuid,
+    pub total: i32,
+    pub report: SyncReport,
+    pub done: bool,
 }
 
 // Model-related things
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum IntegrationModelError {
     FailedToGetConn(r2d2::Error),
     FailedToCreateIntegration(diesel::result::Error),
     FailedToListIntegrations(diesel::result::Error),
     FailedToDeleteIntegration(diesel::result::Error),
+    FailedToGetIntegration(diesel::result::Error),
+    FailedToUpdateIntegration(diesel::result::Error),
+    IntegrationNotFound,
 }
 
 impl From<r2d2::Error> for IntegrationModelError {
@@ -36,7 +83,19 @@ pub type Result<T> = std::result::Result<T, IntegrationModelError>;
 
 pub trait IntegrationModel {
     fn create_integration(&self, t: NewUserIntegration) -> Result<UserIntegration>;
+    fn get_integration(&self, id: &Uuid, user_id: &Uuid) -> Result<UserIntegration>;
     fn list_user_integrations(&self, user_id: &Uuid) -> Result<Vec<UserIntegration>>;
-    fn delete_integration_by_user_id(&self, user_id: &Uuid) -> Result<Vec<UserIntegration>>;
+    fn update_integration_state(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_state: UpdatedIntegrationState,
+    ) -> Result<UserIntegration>;
+    fn link_integration_account(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        account_id: Option<Uuid>,
+    ) -> Result<UserIntegration>;
     fn delete_integration(&self, id: &Uuid) -> Result<UserIntegration>;
 }
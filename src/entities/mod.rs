@@ -1,9 +1,35 @@
 pub mod account;
+pub mod budget;
+pub mod cycle;
+pub mod earning;
+pub mod export;
 pub mod integration;
+pub mod money;
+pub mod session;
+pub mod tag;
 pub mod transaction;
 pub mod user;
 
+#[derive(Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Env {
     DEV,
+    // Login codes are always TEST_LOGIN_CODE, so integration tests can log in.
+    // The server refuses to deliver emails in it.
+    TEST,
     PROD,
 }
+
+// Language of what is sent to the user, like emails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Language {
+    PT,
+    EN,
+}
+
+#[derive(Copy, Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Order {
+    ASC,
+    DESC,
+}
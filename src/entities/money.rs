@@ -0,0 +1,196 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer};
+
+// An amount of money in cents. Amounts are only added and subtracted as
+// whole cents, so sums over many transactions never drift. The arithmetic
+// saturates instead of overflowing on absurd amounts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseMoneyError(String);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Money {
+        Money(cents)
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.0
+    }
+
+    // Rounds to the nearest cent. Only meant for the results of floating
+    // point math, like compounding earnings.
+    pub fn from_f64(value: f64) -> Money {
+        Money((value * 100.0).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+}
+
+// Written as "-1234.50": the sign, the units and always two decimals.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl fmt::Display for ParseMoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid amount of money: {}", self.0)
+    }
+}
+
+// Reads "1234", "1234.5" or "-1234.50". More than two decimals is an error
+// rather than being rounded away.
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    fn from_str(value: &str) -> Result<Money, ParseMoneyError> {
+        let invalid = || ParseMoneyError(value.to_string());
+        let (negative, unsigned) = match value.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, value),
+        };
+        let (units, decimals) = unsigned.split_once('.').unwrap_or((unsigned, "00"));
+        let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        if !is_digits(units) || !is_digits(decimals) || decimals.len() > 2 {
+            return Err(invalid());
+        }
+        let units: i64 = units.parse().map_err(|_| invalid())?;
+        let decimals: i64 = format!("{:0<2}", decimals).parse().map_err(|_| invalid())?;
+        let cents = units
+            .checked_mul(100)
+            .and_then(|cents| cents.checked_add(decimals))
+            .ok_or_else(invalid)?;
+        Ok(Money(if negative { -cents } else { cents }))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(self.0.saturating_neg())
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+#[cfg(test)]
+mod money_tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_decimal_amounts() {
+        for (text, cents, formatted) in [
+            ("0", 0, "0.00"),
+            ("12", 1200, "12.00"),
+            ("12.3", 1230, "12.30"),
+            ("12.34", 1234, "12.34"),
+            ("-0.05", -5, "-0.05"),
+            ("-1234.50", -123450, "-1234.50"),
+        ] {
+            let money: Money = text.parse().unwrap();
+            assert_eq!(money.cents(), cents);
+            assert_eq!(money.to_string(), formatted);
+            assert_eq!(money.to_string().parse(), Ok(money));
+        }
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_exact_amount() {
+        for text in [
+            "",
+            "-",
+            ".5",
+            "1.",
+            "1.234",
+            "1,00",
+            "1e3",
+            "+1",
+            "--1",
+            "99999999999999999999",
+        ] {
+            assert!(text.parse::<Money>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sums_do_not_drift() {
+        let dime = Money::from_cents(10);
+        let total: Money = std::iter::repeat(dime).take(1000).sum();
+        assert_eq!(total, Money::from_cents(10000));
+        assert_eq!(total - dime + -dime, Money::from_cents(9980));
+    }
+
+    #[test]
+    fn arithmetic_saturates_instead_of_overflowing() {
+        let max = Money::from_cents(i64::MAX);
+        let min = Money::from_cents(i64::MIN);
+        let cent = Money::from_cents(1);
+        assert_eq!(max + cent, max);
+        assert_eq!(min - cent, min);
+        assert_eq!(-min, max);
+        assert_eq!([max, max, -cent].into_iter().sum::<Money>(), max - cent);
+        let mut total = min;
+        total -= cent;
+        assert_eq!(total, min);
+    }
+
+    #[test]
+    fn floats_are_rounded_to_the_nearest_cent() {
+        assert_eq!(Money::from_f64(0.1 + 0.2), Money::from_cents(30));
+        assert_eq!(Money::from_f64(-1.005), Money::from_cents(-100));
+        assert_eq!(Money::from_f64(19.99), Money::from_cents(1999));
+        assert_eq!(Money::from_cents(-250).to_f64(), -2.5);
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+// A logged-in device. Access tokens carry its id, so revoking it logs the
+// device out even before they expire.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: Uuid,
+    pub related_user: Uuid,
+    pub device_name: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+// Only the hash of the refresh token is stored.
+#[derive(Clone, Debug)]
+pub struct NewSession {
+    pub related_user: Uuid,
+    pub refresh_token_hash: String,
+    pub device_name: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+// What a client gets when logging in or refreshing. The refresh token is
+// only ever shown here.
+#[derive(Clone, Debug)]
+pub struct AuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub session: Session,
+}
+
+impl Session {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+}
+
+// Model-related things
+
+#[derive(Debug)]
+pub enum SessionModelError {
+    FailedToGetConn(r2d2::Error),
+    FailedToCreateSession(diesel::result::Error),
+    FailedToGetSession(diesel::result::Error),
+    FailedToUpdateSession(diesel::result::Error),
+    FailedToListSessions(diesel::result::Error),
+    SessionNotFound,
+    // The refresh token was already rotated. The session holding it is
+    // revoked by then.
+    RefreshTokenReused(Uuid),
+    DatabaseTransactionFailed(diesel::result::Error),
+}
+
+impl From<r2d2::Error> for SessionModelError {
+    fn from(error: r2d2::Error) -> Self {
+        SessionModelError::FailedToGetConn(error)
+    }
+}
+
+impl From<diesel::result::Error> for SessionModelError {
+    fn from(error: diesel::result::Error) -> Self {
+        SessionModelError::DatabaseTransactionFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SessionModelError>;
+
+pub trait SessionModel {
+    fn create_session(&self, new_session: NewSession) -> Result<Session>;
+    fn get_session(&self, id: &Uuid) -> Result<Session>;
+    // Replaces the refresh token of the active session holding `old_hash`,
+    // unless it was last used before `used_since`. A token that was already
+    // replaced revokes its session and fails with RefreshTokenReused.
+    fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        used_since: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> Result<Session>;
+    fn revoke_session(&self, id: &Uuid, user_id: &Uuid, now: NaiveDateTime) -> Result<Session>;
+    fn revoke_user_sessions(&self, user_id: &Uuid, now: NaiveDateTime) -> Result<Vec<Session>>;
+    fn list_active_sessions(&self, user_id: &Uuid) -> Result<Vec<Session>>;
+    // Revoked sessions too, oldest first.
+    fn list_user_sessions(&self, user_id: &Uuid) -> Result<Vec<Session>>;
+}
@@ -0,0 +1,79 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub id: Uuid,
+    pub related_user: Uuid,
+    pub name: String,
+    pub color: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewTag {
+    pub name: String,
+    pub color: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdatedTag {
+    pub name: Option<String>,
+    pub color: Option<String>,
+}
+
+// Whether an account must have any or all of the requested tags.
+#[derive(Copy, Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TagMatch {
+    ANY,
+    ALL,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AccountTag {
+    pub account_id: Uuid,
+    pub tag_id: Uuid,
+}
+
+// Model-related things
+
+#[derive(Debug)]
+pub enum TagModelError {
+    FailedToGetConn(r2d2::Error),
+    FailedToCreateTag(diesel::result::Error),
+    FailedToListTags(diesel::result::Error),
+    FailedToUpdateTag(diesel::result::Error),
+    FailedToDeleteTag(diesel::result::Error),
+    FailedToTagAccount(diesel::result::Error),
+    FailedToUntagAccount(diesel::result::Error),
+    TagNameAlreadyTaken,
+    TagNotFound,
+    AccountNotFound,
+    DatabaseTransactionFailed(diesel::result::Error),
+}
+
+impl From<r2d2::Error> for TagModelError {
+    fn from(error: r2d2::Error) -> Self {
+        TagModelError::FailedToGetConn(error)
+    }
+}
+
+impl From<diesel::result::Error> for TagModelError {
+    fn from(error: diesel::result::Error) -> Self {
+        TagModelError::DatabaseTransactionFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TagModelError>;
+
+pub trait TagModel {
+    fn create_tag(&self, user_id: &Uuid, new_tag: NewTag) -> Result<Tag>;
+    fn list_user_tags(&self, user_id: &Uuid) -> Result<Vec<Tag>>;
+    fn edit_tag(&self, id: &Uuid, user_id: &Uuid, updated_tag: UpdatedTag) -> Result<Tag>;
+    fn delete_tag(&self, id: &Uuid, user_id: &Uuid) -> Result<Tag>;
+    fn tag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> Result<()>;
+    fn untag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> Result<()>;
+    fn list_account_tags(&self, user_id: &Uuid) -> Result<Vec<AccountTag>>;
+}
@@ -1,16 +1,34 @@
 use chrono::NaiveDate;
 use uuid::Uuid;
 
+use crate::entities::{money::Money, Order};
+
+// Who created a transaction: the user or one of the engines that post on
+// their behalf.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum TransactionKind {
+    MANUAL,
+    EARNING,
+    REFILL,
+    SWEEP,
+    IMPORT,
+}
+
+#[derive(Clone, Debug)]
 pub struct Transaction {
     pub id: Uuid,
     pub related_user: Uuid,
     pub entry_date: NaiveDate,
     pub entry_account_code: Option<Uuid>,
     pub exit_account_code: Option<Uuid>,
-    pub amount: f64,
+    pub amount: Money,
     pub description: Option<String>,
+    pub voided: bool,
+    pub kind: TransactionKind,
 }
 
+#[derive(Clone, Debug)]
 pub struct TransactionWithNames {
     pub id: Uuid,
     pub related_user: Uuid,
@@ -19,16 +37,94 @@ pub struct TransactionWithNames {
     pub entry_account_name: Option<String>,
     pub exit_account_code: Option<Uuid>,
     pub exit_account_name: Option<String>,
-    pub amount: f64,
+    pub amount: Money,
     pub description: Option<String>,
+    pub voided: bool,
+    pub kind: TransactionKind,
 }
 
 pub struct NewTransaction {
     pub entry_date: NaiveDate,
     pub entry_account_code: Option<Uuid>,
     pub exit_account_code: Option<Uuid>,
-    pub amount: f64,
+    pub amount: Money,
     pub description: Option<String>,
+    pub kind: TransactionKind,
+}
+
+// Fields left as None keep their value, and `Some(None)` clears the
+// accounts or the description.
+pub struct UpdatedTransaction {
+    pub entry_date: Option<NaiveDate>,
+    pub entry_account_code: Option<Option<Uuid>>,
+    pub exit_account_code: Option<Option<Uuid>>,
+    pub amount: Option<Money>,
+    pub description: Option<Option<String>>,
+}
+
+impl UpdatedTransaction {
+    pub fn is_empty(&self) -> bool {
+        self.entry_date.is_none()
+            && self.entry_account_code.is_none()
+            && self.exit_account_code.is_none()
+            && self.amount.is_none()
+            && self.description.is_none()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TransactionFilter {
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub account: Option<Uuid>,
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub description: Option<String>,
+}
+
+// Position of a transaction in the (entry_date, id) ordering used for paging.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransactionCursor {
+    pub entry_date: NaiveDate,
+    pub id: Uuid,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TransactionPageRequest {
+    pub first: i64,
+    pub after: Option<TransactionCursor>,
+    pub order: Order,
+}
+
+// The account names are left to the caller, which can batch them with
+// those of other pages.
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+}
+
+impl TransactionCursor {
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.entry_date, self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Option<TransactionCursor> {
+        let (entry_date, id) = cursor.split_once('_')?;
+        Some(TransactionCursor {
+            entry_date: entry_date.parse().ok()?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+impl Transaction {
+    pub fn cursor(&self) -> TransactionCursor {
+        TransactionCursor {
+            entry_date: self.entry_date,
+            id: self.id,
+        }
+    }
 }
 
 impl Transaction {
@@ -43,8 +139,38 @@ impl Transaction {
             exit_account_name: exit,
             amount: self.amount,
             description: self.description.clone(),
+            voided: self.voided,
+            kind: self.kind,
         }
     }
+
+    // Voided transactions are kept for history but no longer move money.
+    pub fn balance_effects(&self) -> Vec<(Uuid, Money)> {
+        if self.voided {
+            Vec::new()
+        } else {
+            balance_effects(self.entry_account_code, self.exit_account_code, self.amount)
+        }
+    }
+
+    pub fn reversed_balance_effects(&self) -> Vec<(Uuid, Money)> {
+        self.balance_effects()
+            .into_iter()
+            .map(|(id, delta)| (id, -delta))
+            .collect()
+    }
+}
+
+// A transaction moves `amount` into its entry account and out of its exit
+// account. Returns the change it causes on each account balance.
+pub fn balance_effects(
+    entry_account_code: Option<Uuid>,
+    exit_account_code: Option<Uuid>,
+    amount: Money,
+) -> Vec<(Uuid, Money)> {
+    let entry = entry_account_code.map(|id| (id, amount));
+    let exit = exit_account_code.map(|id| (id, -amount));
+    entry.into_iter().chain(exit).collect()
 }
 
 // Model-related things
@@ -55,6 +181,13 @@ pub enum TransactionModelError {
     FailedToCreateTransaction(diesel::result::Error),
     FailedToListTransactions(diesel::result::Error),
     FailedToDeleteTransaction(diesel::result::Error),
+    FailedToUpdateBalances(diesel::result::Error),
+    FailedToGetTransaction(diesel::result::Error),
+    FailedToUpdateTransaction(diesel::result::Error),
+    AccountNotFound,
+    TransactionNotFound,
+    TransactionIsVoided,
+    DatabaseTransactionFailed(diesel::result::Error),
 }
 
 impl From<r2d2::Error> for TransactionModelError {
@@ -63,6 +196,12 @@ impl From<r2d2::Error> for TransactionModelError {
     }
 }
 
+impl From<diesel::result::Error> for TransactionModelError {
+    fn from(error: diesel::result::Error) -> Self {
+        TransactionModelError::DatabaseTransactionFailed(error)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, TransactionModelError>;
 
 pub trait TransactionModel {
@@ -72,5 +211,118 @@ pub trait TransactionModel {
         new_transaction: NewTransaction,
     ) -> Result<Transaction>;
     fn list_user_transactions(&self, user_id: &Uuid) -> Result<Vec<Transaction>>;
-    fn delete_transaction_by_user_id(&self, user_id: &Uuid) -> Result<()>;
+    fn list_user_transactions_between(
+        &self,
+        user_id: &Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Transaction>>;
+    // Returns up to `page.first + 1` transactions so callers can tell whether
+    // there is a next page.
+    fn filter_user_transactions(
+        &self,
+        user_id: &Uuid,
+        filter: &TransactionFilter,
+        page: &TransactionPageRequest,
+    ) -> Result<Vec<Transaction>>;
+    fn edit_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_transaction: UpdatedTransaction,
+    ) -> Result<Transaction>;
+    fn delete_transaction(&self, id: &Uuid, user_id: &Uuid) -> Result<Transaction>;
+    fn void_transaction(&self, id: &Uuid, user_id: &Uuid) -> Result<Transaction>;
+    // Posts earnings into an account and records how far they were accrued, so
+    // the same days are never paid twice.
+    fn create_accrual_transaction(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        accrued_until: NaiveDate,
+        new_transaction: NewTransaction,
+    ) -> Result<Transaction>;
+    // Posts the transactions that refill an envelope and records the refill
+    // date. Nothing is posted when the envelope was already refilled on or
+    // after `cycle_start`.
+    fn create_refill_transactions(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        cycle_start: NaiveDate,
+        refilled_on: NaiveDate,
+        new_transactions: Vec<NewTransaction>,
+    ) -> Result<Vec<Transaction>>;
+    // Creates a transaction that mirrors an event from an external source.
+    // Returns None when a transaction with the same external id was already
+    // imported for the user.
+    fn import_transaction(
+        &self,
+        user_id: &Uuid,
+        external_id: &str,
+        new_transaction: NewTransaction,
+    ) -> Result<Option<Transaction>>;
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use super::*;
+
+    const ACCOUNT_A: Uuid = Uuid::from_u128(1);
+    const ACCOUNT_B: Uuid = Uuid::from_u128(2);
+    const TEN: Money = Money::from_cents(1000);
+
+    #[test]
+    fn transfer_moves_money_between_accounts() {
+        let effects = balance_effects(Some(ACCOUNT_A), Some(ACCOUNT_B), TEN);
+        assert_eq!(effects, vec![(ACCOUNT_A, TEN), (ACCOUNT_B, -TEN)]);
+    }
+
+    #[test]
+    fn income_only_credits_entry_account() {
+        let effects = balance_effects(Some(ACCOUNT_A), None, TEN);
+        assert_eq!(effects, vec![(ACCOUNT_A, TEN)]);
+    }
+
+    #[test]
+    fn expense_only_debits_exit_account() {
+        let effects = balance_effects(None, Some(ACCOUNT_B), TEN);
+        assert_eq!(effects, vec![(ACCOUNT_B, -TEN)]);
+    }
+
+    #[test]
+    fn voided_transaction_has_no_effect() {
+        let transaction = Transaction {
+            id: Uuid::from_u128(3),
+            related_user: Uuid::from_u128(4),
+            entry_date: NaiveDate::from_ymd(2023, 1, 1),
+            entry_account_code: Some(ACCOUNT_A),
+            exit_account_code: Some(ACCOUNT_B),
+            amount: TEN,
+            description: None,
+            voided: true,
+            kind: TransactionKind::MANUAL,
+        };
+        assert!(transaction.balance_effects().is_empty());
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = TransactionCursor {
+            entry_date: NaiveDate::from_ymd(2023, 3, 12),
+            id: Uuid::from_u128(5),
+        };
+        assert_eq!(TransactionCursor::decode(&cursor.encode()), Some(cursor));
+    }
+
+    #[test]
+    fn invalid_cursor_is_rejected() {
+        assert_eq!(TransactionCursor::decode("not a cursor"), None);
+        assert_eq!(TransactionCursor::decode("2023-03-12_nope"), None);
+    }
+
+    #[test]
+    fn transaction_without_accounts_has_no_effect() {
+        assert!(balance_effects(None, None, TEN).is_empty());
+    }
 }
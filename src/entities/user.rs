@@ -15,6 +15,9 @@ pub struct User {
     pub login_code: Option<i32>,
     pub is_registered: bool,
     pub payday: Option<i32>,
+    pub deletion_scheduled_for: Option<NaiveDateTime>,
+    // Address the user is moving to, until they confirm the code sent to it.
+    pub pending_email: Option<String>,
 }
 
 pub struct UserWithIntegrations {
@@ -27,6 +30,8 @@ pub struct UserWithIntegrations {
     pub login_code: Option<i32>,
     pub is_registered: bool,
     pub payday: Option<i32>,
+    pub deletion_scheduled_for: Option<NaiveDateTime>,
+    pub pending_email: Option<String>,
     pub integrations: Vec<UserIntegration>,
 }
 
@@ -42,11 +47,68 @@ impl User {
             login_code: self.login_code,
             is_registered: self.is_registered,
             payday: self.payday,
+            deletion_scheduled_for: self.deletion_scheduled_for,
+            pending_email: self.pending_email.clone(),
             integrations,
         }
     }
 }
 
+// Everything needed to check a login code sent to an email.
+#[derive(Clone, Debug)]
+pub struct LoginCodeState {
+    pub login_code: Option<i32>,
+    pub generated_at: Option<NaiveDateTime>,
+    pub locked_until: Option<NaiveDateTime>,
+    // Wrong codes tried since this one was sent.
+    pub attempts: i32,
+}
+
+// What to do with the code after a login attempt was checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoginCodeAttempt {
+    // Burns the code and lifts any lock.
+    Accept,
+    // Counts one more wrong guess.
+    Fail,
+    // Burns the code and refuses any other until the given time.
+    Lock(NaiveDateTime),
+    // Leaves everything as it is.
+    Keep,
+}
+
+// A change of email waiting for the code sent to the new address.
+#[derive(Clone, Debug)]
+pub struct EmailChange {
+    pub email: Option<String>,
+    pub code: Option<i32>,
+    pub requested_at: Option<NaiveDateTime>,
+    pub attempts: i32,
+}
+
+// Profile fields to change, the ones left as None are kept and
+// `Some(None)` clears the payday.
+#[derive(Clone, Debug)]
+pub struct UpdatedProfile {
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub payday: Option<Option<i32>>,
+}
+
+impl UpdatedProfile {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.username.is_none() && self.payday.is_none()
+    }
+}
+
+// A new email waiting for `code`, which was sent to it at `time`.
+#[derive(Clone, Debug)]
+pub struct NewEmailChange {
+    pub email: String,
+    pub code: i32,
+    pub time: NaiveDateTime,
+}
+
 // Essential information for create a new user in the database
 #[derive(Clone)]
 pub struct NewUser {
@@ -67,9 +129,13 @@ pub enum UserModelError {
     FailedToGetLoginCode(diesel::result::Error),
     FailedToGetIdByEmail(diesel::result::Error),
     FailedToUpdateLoginCode(diesel::result::Error),
+    FailedToUpdatePayday(diesel::result::Error),
+    FailedToScheduleDeletion(diesel::result::Error),
+    FailedToUpdateProfile(diesel::result::Error),
+    FailedToUpdateEmailChange(diesel::result::Error),
+    DatabaseTransactionFailed(diesel::result::Error),
     UserAlreadyExists,
     UserDoesNotExists,
-    UserWithoutLoginCode,
     MoreThanOneEmailError,
     MoreThanOneIdError,
 }
@@ -80,15 +146,51 @@ impl From<r2d2::Error> for UserModelError {
     }
 }
 
+impl From<diesel::result::Error> for UserModelError {
+    fn from(error: diesel::result::Error) -> Self {
+        UserModelError::DatabaseTransactionFailed(error)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, UserModelError>;
 
 pub trait UserModel {
     fn create_user(&self, user: NewUser) -> Result<User>;
-    fn delete_user(&self, id: &Uuid) -> Result<User>;
+    // Deletes the user with everything they own, all or nothing. Returns None
+    // when there was no such user.
+    fn delete_user(&self, id: &Uuid) -> Result<Option<User>>;
+    fn schedule_user_deletion(&self, id: &Uuid, at: NaiveDateTime) -> Result<User>;
+    fn cancel_user_deletion(&self, id: &Uuid) -> Result<User>;
+    fn list_users_due_for_deletion(&self, now: NaiveDateTime) -> Result<Vec<Uuid>>;
     fn get_user(&self, id: Uuid) -> Result<User>;
     fn check_if_username_available(&self, username: &str) -> Result<bool>;
     fn check_if_email_available(&self, email: &str) -> Result<bool>;
+    // Keeps the failed attempts at the previous codes, so resending doesn't
+    // lift the limit. They are forgotten on login or when a lockout starts.
     fn refresh_login_code(&self, email: &str, login_code: i32, time: NaiveDateTime) -> Result<()>;
-    fn get_login_code(&self, email: &str) -> Result<i32>;
+    fn get_login_code_state(&self, email: &str) -> Result<LoginCodeState>;
+    // Hands the state of the code to `check` while no other attempt can touch
+    // it and applies what `check` decided, so concurrent guesses can neither
+    // reuse a code nor slip past the attempt limit.
+    fn attempt_login_code<R, F>(&self, email: &str, check: F) -> Result<R>
+    where
+        F: FnOnce(&LoginCodeState) -> (LoginCodeAttempt, R);
     fn get_id_by_email(&self, email: &str) -> Result<Uuid>;
+    fn set_payday(&self, id: &Uuid, payday: Option<i32>) -> Result<User>;
+    // Saves the profile and the email change together, or neither. Fails
+    // with UserAlreadyExists when the username is taken. An email change
+    // replaces any change already pending and forgets its failed attempts.
+    fn update_profile(
+        &self,
+        id: &Uuid,
+        profile: UpdatedProfile,
+        email_change: Option<NewEmailChange>,
+    ) -> Result<User>;
+    fn get_email_change(&self, id: &Uuid) -> Result<EmailChange>;
+    // Returns how many wrong codes were tried for the pending change.
+    fn register_failed_email_change_attempt(&self, id: &Uuid) -> Result<i32>;
+    // Switches to `email` only if it is still the pending one. Fails with
+    // UserAlreadyExists when another user took the address meanwhile.
+    fn confirm_email_change(&self, id: &Uuid, email: &str) -> Result<User>;
+    fn cancel_email_change(&self, id: &Uuid) -> Result<()>;
 }
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use uuid::Uuid;
+
+use crate::entities::{account, integration, transaction};
+
+// Subscribers that fall further behind than this miss the oldest events.
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    TransactionCreated(transaction::TransactionWithNames),
+    AccountUpdated(account::Account),
+    IntegrationSyncProgress(integration::SyncProgress),
+    // Subscriptions made with a revoked session end on these.
+    SessionRevoked(Uuid),
+    UserDeleted,
+}
+
+// How many events a subscriber missed by falling behind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+// Hands what changed to the live subscriptions of the user it belongs to.
+// Events are not stored, whoever is not subscribed when one happens never
+// sees it. Each user with subscriptions has a channel of their own, dropped
+// with their last subscription.
+#[derive(Clone, Default)]
+pub struct EventBus(Arc<Mutex<HashMap<Uuid, broadcast::Sender<Event>>>>);
+
+impl EventBus {
+    pub fn publish(&self, user_id: Uuid, event: Event) {
+        let mut channels = self.0.lock().unwrap();
+        if let Some(sender) = channels.get(&user_id) {
+            // Failing only means the last subscriber is gone.
+            if sender.send(event).is_err() {
+                channels.remove(&user_id);
+            }
+        }
+    }
+
+    pub fn subscribe(
+        &self,
+        user_id: Uuid,
+    ) -> impl Stream<Item = Result<Event, Lagged>> + Send + 'static {
+        let receiver = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe();
+        Subscribed {
+            events: BroadcastStream::new(receiver),
+            _leave: Leave {
+                bus: self.clone(),
+                user_id,
+            },
+        }
+    }
+}
+
+// Fields drop in order, so the receiver is gone by the time `Leave` checks
+// whether it was the last one.
+struct Subscribed {
+    events: BroadcastStream<Event>,
+    _leave: Leave,
+}
+
+impl Stream for Subscribed {
+    type Item = Result<Event, Lagged>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(cx).map(|received| {
+            received.map(|received| {
+                received.map_err(|err| match err {
+                    BroadcastStreamRecvError::Lagged(missed) => Lagged(missed),
+                })
+            })
+        })
+    }
+}
+
+struct Leave {
+    bus: EventBus,
+    user_id: Uuid,
+}
+
+impl Drop for Leave {
+    fn drop(&mut self) {
+        if let Ok(mut channels) = self.bus.0.lock() {
+            let unused = channels
+                .get(&self.user_id)
+                .map_or(false, |sender| sender.receiver_count() == 0);
+            if unused {
+                channels.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod events_tests {
+    use super::*;
+
+    fn progress(integration_id: Uuid, done: bool) -> Event {
+        Event::IntegrationSyncProgress(integration::SyncProgress {
+            integration_id,
+            total: 1,
+            report: integration::SyncReport::default(),
+            done,
+        })
+    }
+
+    fn integration_of(event: Event) -> Uuid {
+        match event {
+            Event::IntegrationSyncProgress(progress) => progress.integration_id,
+            other => panic!("Unexpected event {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_only_see_their_own_events() {
+        let bus = EventBus::default();
+        let (user_id, other_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (mine, theirs) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut events = Box::pin(bus.subscribe(user_id));
+
+        bus.publish(other_id, progress(theirs, false));
+        bus.publish(user_id, progress(mine, false));
+        bus.publish(other_id, progress(theirs, true));
+        bus.publish(user_id, progress(mine, true));
+
+        assert_eq!(integration_of(events.next().await.unwrap().unwrap()), mine);
+        assert_eq!(integration_of(events.next().await.unwrap().unwrap()), mine);
+    }
+
+    #[tokio::test]
+    async fn events_before_subscribing_are_not_seen() {
+        let bus = EventBus::default();
+        let user_id = Uuid::new_v4();
+        let (before, after) = (Uuid::new_v4(), Uuid::new_v4());
+
+        bus.publish(user_id, progress(before, true));
+        let mut events = Box::pin(bus.subscribe(user_id));
+        bus.publish(user_id, progress(after, true));
+
+        assert_eq!(integration_of(events.next().await.unwrap().unwrap()), after);
+    }
+
+    #[tokio::test]
+    async fn channels_are_dropped_with_their_last_subscriber() {
+        let bus = EventBus::default();
+        let user_id = Uuid::new_v4();
+        let first = bus.subscribe(user_id);
+        let second = bus.subscribe(user_id);
+        assert_eq!(bus.0.lock().unwrap().len(), 1);
+
+        drop(first);
+        assert_eq!(bus.0.lock().unwrap().len(), 1);
+        drop(second);
+        assert!(bus.0.lock().unwrap().is_empty());
+
+        bus.publish(user_id, progress(Uuid::new_v4(), true));
+        assert!(bus.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_told_how_many_events_they_missed() {
+        let bus = EventBus::default();
+        let user_id = Uuid::new_v4();
+        let integration_id = Uuid::new_v4();
+        let mut events = Box::pin(bus.subscribe(user_id));
+
+        for _ in 0..CAPACITY + 2 {
+            bus.publish(user_id, progress(integration_id, false));
+        }
+
+        assert_eq!(events.next().await.unwrap().unwrap_err(), Lagged(2));
+        assert_eq!(
+            integration_of(events.next().await.unwrap().unwrap()),
+            integration_id
+        );
+    }
+}
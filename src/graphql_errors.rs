@@ -0,0 +1,422 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use juniper::{graphql_value, FieldError};
+
+use crate::{
+    auth,
+    entities::{account, earning, integration, session, tag, transaction, user},
+    jwt,
+    nubank::NubankError,
+    services::{
+        account::AccountServiceError, budget::BudgetServiceError, cycle::CycleServiceError,
+        earning::EarningServiceError, export::ExportServiceError, nubank::NubankServiceError,
+        session::SessionServiceError, tag::TagServiceError, transaction::TransactionServiceError,
+        user::UserServiceError,
+    },
+};
+
+// What clients can tell errors apart by. The codes are sent as
+// `extensions.code` and must not change once released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    Unauthenticated,
+    TokenExpired,
+    NotFound,
+    NameTaken,
+    EmailTaken,
+    AlreadyExists,
+    CodeInvalid,
+    CodeExpired,
+    LoginLocked,
+    TooManyRequests,
+    BadRequest,
+    Conflict,
+    NubankFailed,
+    EventsMissed,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::TokenExpired => "TOKEN_EXPIRED",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::NameTaken => "NAME_TAKEN",
+            ErrorCode::EmailTaken => "EMAIL_TAKEN",
+            ErrorCode::AlreadyExists => "ALREADY_EXISTS",
+            ErrorCode::CodeInvalid => "CODE_INVALID",
+            ErrorCode::CodeExpired => "CODE_EXPIRED",
+            ErrorCode::LoginLocked => "LOGIN_LOCKED",
+            ErrorCode::TooManyRequests => "TOO_MANY_REQUESTS",
+            ErrorCode::BadRequest => "BAD_REQUEST",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::NubankFailed => "NUBANK_FAILED",
+            ErrorCode::EventsMissed => "EVENTS_MISSED",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::Unauthenticated => "You need to log in",
+            ErrorCode::TokenExpired => "Your session token expired, refresh it or log in again",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::NameTaken => "This name is already taken",
+            ErrorCode::EmailTaken => "This email is already in use",
+            ErrorCode::AlreadyExists => "It already exists",
+            ErrorCode::CodeInvalid => "The code is not valid",
+            ErrorCode::CodeExpired => "The code has expired, request a new one",
+            ErrorCode::LoginLocked => "Too many wrong codes, try again later",
+            ErrorCode::TooManyRequests => "Too many requests, try again in a moment",
+            ErrorCode::BadRequest => "The request is not valid",
+            ErrorCode::Conflict => "This can't be done in the current state",
+            ErrorCode::NubankFailed => "Nubank refused the request",
+            ErrorCode::EventsMissed => "Some events were missed, refetch to catch up",
+            ErrorCode::Internal => "Something went wrong on our side",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+pub trait ToErrorCode: fmt::Debug {
+    fn code(&self) -> ErrorCode;
+
+    // When the request can be made again, sent as `extensions.retryAt` in the
+    // same unix timestamp the NaiveDateTime fields use.
+    fn retry_at(&self) -> Option<NaiveDateTime> {
+        None
+    }
+}
+
+// The details stay in the server logs; clients only get the code and its
+// message.
+pub fn field_error<E: ToErrorCode>(error: E) -> FieldError {
+    let code = error.code();
+    match code {
+        ErrorCode::Internal => log::error!("{:?}", error),
+        _ => log::debug!("{}: {:?}", code, error),
+    }
+    match error.retry_at() {
+        Some(retry_at) => FieldError::new(
+            code.message(),
+            graphql_value!({
+                "code": (code.as_str()),
+                "retryAt": (retry_at.timestamp() as f64),
+            }),
+        ),
+        None => code_error(code),
+    }
+}
+
+pub fn code_error(code: ErrorCode) -> FieldError {
+    FieldError::new(code.message(), graphql_value!({ "code": (code.as_str()) }))
+}
+
+impl ToErrorCode for account::AccountModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            account::AccountModelError::AccountNotFound => ErrorCode::NotFound,
+            account::AccountModelError::NameAlreadyTaken => ErrorCode::NameTaken,
+            account::AccountModelError::FailedToDeleteAccount(error) => error.code(),
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for transaction::TransactionModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            transaction::TransactionModelError::AccountNotFound
+            | transaction::TransactionModelError::TransactionNotFound => ErrorCode::NotFound,
+            transaction::TransactionModelError::TransactionIsVoided => ErrorCode::Conflict,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for tag::TagModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            tag::TagModelError::TagNotFound | tag::TagModelError::AccountNotFound => {
+                ErrorCode::NotFound
+            }
+            tag::TagModelError::TagNameAlreadyTaken => ErrorCode::NameTaken,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for user::UserModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            user::UserModelError::UserDoesNotExists => ErrorCode::NotFound,
+            user::UserModelError::UserAlreadyExists => ErrorCode::AlreadyExists,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for integration::IntegrationModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            integration::IntegrationModelError::IntegrationNotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for session::SessionModelError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            session::SessionModelError::SessionNotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for earning::IndexRateModelError {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::Internal
+    }
+}
+
+impl ToErrorCode for jwt::JwtError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            jwt::JwtError::FailedToVerifyJwtToken(_) => ErrorCode::Unauthenticated,
+            jwt::JwtError::ExpiredAccessToken(_) => ErrorCode::TokenExpired,
+            jwt::JwtError::FailedToGenerateJwtKey(_)
+            | jwt::JwtError::FailedToGenerateJwtToken(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for auth::AuthError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            auth::AuthError::InvalidToken(error) => error.code(),
+            auth::AuthError::SessionModelFailed(error) => error.code(),
+            auth::AuthError::NotAuthenticated
+            | auth::AuthError::InvalidAuthorizationHeader
+            | auth::AuthError::RevokedSession => ErrorCode::Unauthenticated,
+        }
+    }
+}
+
+impl ToErrorCode for AccountServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AccountServiceError::AccountModelFailed(error) => error.code(),
+            AccountServiceError::TransactionModelFailed(error) => error.code(),
+            AccountServiceError::TagModelFailed(error) => error.code(),
+            AccountServiceError::UserModelFailed(error) => error.code(),
+            AccountServiceError::NameAlreadyTaken => ErrorCode::NameTaken,
+        }
+    }
+}
+
+impl ToErrorCode for TransactionServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TransactionServiceError::TransactionModelFailed(error) => error.code(),
+            TransactionServiceError::UserModelFailed(error) => error.code(),
+            TransactionServiceError::AccountModelFailed(error) => error.code(),
+            TransactionServiceError::InvalidCursor(_) => ErrorCode::BadRequest,
+        }
+    }
+}
+
+impl ToErrorCode for UserServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            UserServiceError::UserModelFailed(error) => error.code(),
+            UserServiceError::TransactionModelFailed(error) => error.code(),
+            UserServiceError::UserIntegrationModelFailed(error) => error.code(),
+            UserServiceError::SessionModelFailed(error) => error.code(),
+            UserServiceError::JwtError(error) => error.code(),
+            UserServiceError::MailerFailed(_) => ErrorCode::Internal,
+            UserServiceError::LoginCodeNotMatching => ErrorCode::CodeInvalid,
+            UserServiceError::LoginCodeExpired => ErrorCode::CodeExpired,
+            UserServiceError::LoginLocked(_) => ErrorCode::LoginLocked,
+            UserServiceError::LoginCodeResendCooldown(_) => ErrorCode::TooManyRequests,
+            UserServiceError::InvalidPayday(_) | UserServiceError::InvalidGracePeriod(_) => {
+                ErrorCode::BadRequest
+            }
+            UserServiceError::UsernameNotAvailable(_) => ErrorCode::NameTaken,
+            UserServiceError::EmailNotAvailable(_) => ErrorCode::EmailTaken,
+            UserServiceError::NoPendingEmailChange => ErrorCode::NotFound,
+        }
+    }
+
+    fn retry_at(&self) -> Option<NaiveDateTime> {
+        match self {
+            UserServiceError::LoginLocked(until)
+            | UserServiceError::LoginCodeResendCooldown(until) => Some(*until),
+            _ => None,
+        }
+    }
+}
+
+impl ToErrorCode for SessionServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SessionServiceError::SessionModelFailed(error) => error.code(),
+            SessionServiceError::UserServiceFailed(error) => error.code(),
+            SessionServiceError::JwtError(error) => error.code(),
+            SessionServiceError::FailedToGenerateRefreshToken(_) => ErrorCode::Internal,
+            SessionServiceError::InvalidRefreshToken => ErrorCode::Unauthenticated,
+        }
+    }
+
+    fn retry_at(&self) -> Option<NaiveDateTime> {
+        match self {
+            SessionServiceError::UserServiceFailed(error) => error.retry_at(),
+            _ => None,
+        }
+    }
+}
+
+impl ToErrorCode for TagServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TagServiceError::TagModelFailed(error) => error.code(),
+            TagServiceError::InvalidColor(_) => ErrorCode::BadRequest,
+        }
+    }
+}
+
+impl ToErrorCode for BudgetServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            BudgetServiceError::AccountModelFailed(error) => error.code(),
+            BudgetServiceError::TransactionModelFailed(error) => error.code(),
+            BudgetServiceError::UserModelFailed(error) => error.code(),
+        }
+    }
+}
+
+impl ToErrorCode for CycleServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CycleServiceError::UserModelFailed(error) => error.code(),
+            CycleServiceError::InvalidCycleRange(_, _) => ErrorCode::BadRequest,
+        }
+    }
+}
+
+impl ToErrorCode for EarningServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            EarningServiceError::AccountModelFailed(error) => error.code(),
+            EarningServiceError::TransactionModelFailed(error) => error.code(),
+            EarningServiceError::IndexRateModelFailed(error) => error.code(),
+            EarningServiceError::AccountIsNotEarning => ErrorCode::Conflict,
+            EarningServiceError::CannotReadIndexRates(_)
+            | EarningServiceError::InvalidIndexRateLine(_, _) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for ExportServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExportServiceError::UserModelFailed(error) => error.code(),
+            ExportServiceError::AccountModelFailed(error) => error.code(),
+            ExportServiceError::TransactionModelFailed(error) => error.code(),
+            ExportServiceError::IntegrationModelFailed(error) => error.code(),
+            ExportServiceError::TagModelFailed(error) => error.code(),
+            ExportServiceError::SessionModelFailed(error) => error.code(),
+            ExportServiceError::InvalidSignature | ExportServiceError::ExpiredLink => {
+                ErrorCode::Unauthenticated
+            }
+            ExportServiceError::FailedToGenerateSignature(_)
+            | ExportServiceError::FailedToWriteJson(_)
+            | ExportServiceError::FailedToWriteCsv(_)
+            | ExportServiceError::FailedToWriteArchive(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ToErrorCode for NubankServiceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            NubankServiceError::IntegrationModelFailed(error) => error.code(),
+            NubankServiceError::AccountModelFailed(error) => error.code(),
+            NubankServiceError::NubankFailed(NubankError::AuthError(_))
+            | NubankServiceError::NubankFailed(NubankError::GenCertError(_))
+            | NubankServiceError::NubankFailed(NubankError::FeedError(_)) => {
+                ErrorCode::NubankFailed
+            }
+            NubankServiceError::IntegrationIsNotNubank
+            | NubankServiceError::IntegrationIsNotPending
+            | NubankServiceError::IntegrationIsNotConnected
+            | NubankServiceError::IntegrationWithoutAccount => ErrorCode::Conflict,
+            NubankServiceError::NubankFailed(_)
+            | NubankServiceError::EncryptionFailed(_)
+            | NubankServiceError::CannotSerializeState(_)
+            | NubankServiceError::IntegrationWithoutState
+            | NubankServiceError::DatabaseTaskFailed(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod graphql_errors_tests {
+    use super::*;
+
+    #[test]
+    fn internal_details_are_not_sent() {
+        let error = AccountServiceError::AccountModelFailed(
+            account::AccountModelError::FailedToGetAccount(diesel::result::Error::NotFound),
+        );
+        assert_eq!(error.code(), ErrorCode::Internal);
+        let field_error = field_error(error);
+        assert_eq!(field_error.message(), ErrorCode::Internal.message());
+        assert_eq!(
+            field_error.extensions(),
+            &graphql_value!({ "code": "INTERNAL" })
+        );
+    }
+
+    #[test]
+    fn wrapped_errors_keep_their_code() {
+        assert_eq!(
+            AccountServiceError::AccountModelFailed(account::AccountModelError::AccountNotFound)
+                .code(),
+            ErrorCode::NotFound
+        );
+        assert_eq!(
+            SessionServiceError::UserServiceFailed(UserServiceError::LoginCodeExpired).code(),
+            ErrorCode::CodeExpired
+        );
+        assert_eq!(
+            auth::AuthError::InvalidToken(jwt::JwtError::ExpiredAccessToken(0)).code(),
+            ErrorCode::TokenExpired
+        );
+        assert_eq!(
+            TransactionServiceError::InvalidCursor("x".to_string()).code(),
+            ErrorCode::BadRequest
+        );
+    }
+
+    #[test]
+    fn lockouts_tell_when_to_retry() {
+        let until = NaiveDateTime::from_timestamp(1665366563, 0);
+        let error = SessionServiceError::UserServiceFailed(UserServiceError::LoginLocked(until));
+        assert_eq!(
+            field_error(error).extensions(),
+            &graphql_value!({ "code": "LOGIN_LOCKED", "retryAt": 1665366563.0 })
+        );
+        assert_eq!(
+            field_error(UserServiceError::LoginCodeResendCooldown(until)).extensions(),
+            &graphql_value!({ "code": "TOO_MANY_REQUESTS", "retryAt": 1665366563.0 })
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use juniper::FieldResult;
+use uuid::Uuid;
+
+use crate::database;
+use crate::entities::account::{self, AccountModel};
+use crate::graphql_errors::{code_error, field_error, ErrorCode};
+
+// (user, account) pairs, since names are only given to the account owner.
+type Key = (Uuid, Uuid);
+
+// Collects the account names the fields of a request ask for and looks them
+// up together, like a DataLoader: every field queues its account and yields,
+// so all the transactions of a page cost a single query. Names are kept for
+// the rest of the request, and None stands for an account the user doesn't
+// have, like one that was deleted.
+#[derive(Default)]
+pub struct AccountNames {
+    state: Mutex<State>,
+    fetching: tokio::sync::Mutex<()>,
+}
+
+#[derive(Default)]
+struct State {
+    pending: HashSet<Key>,
+    loaded: HashMap<Key, Option<String>>,
+}
+
+impl AccountNames {
+    pub async fn load(
+        &self,
+        pool: &database::DbPool,
+        user_id: Uuid,
+        account_id: Uuid,
+    ) -> FieldResult<Option<String>> {
+        let key = (user_id, account_id);
+        if let Some(name) = self.loaded(&key) {
+            return Ok(name);
+        }
+        self.state.lock().unwrap().pending.insert(key);
+        tokio::task::yield_now().await;
+
+        // Only one batch runs at a time, and it may already have brought the
+        // name while this one waited.
+        let _fetching = self.fetching.lock().await;
+        if let Some(name) = self.loaded(&key) {
+            return Ok(name);
+        }
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            state.pending.insert(key);
+            state.pending.drain().collect::<Vec<_>>()
+        };
+        let pool = pool.clone();
+        let names = match tokio::task::spawn_blocking(move || fetch(&pool, &pending)).await {
+            Ok(names) => names.map_err(field_error)?,
+            Err(err) => {
+                log::error!("Account name lookup panicked: {}", err);
+                return Err(code_error(ErrorCode::Internal));
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        state.loaded.extend(names);
+        Ok(state.loaded.get(&key).cloned().flatten())
+    }
+
+    fn loaded(&self, key: &Key) -> Option<Option<String>> {
+        self.state.lock().unwrap().loaded.get(key).cloned()
+    }
+}
+
+fn fetch(pool: &database::DbPool, keys: &[Key]) -> account::Result<HashMap<Key, Option<String>>> {
+    let mut by_user: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (user_id, account_id) in keys {
+        by_user.entry(*user_id).or_default().push(*account_id);
+    }
+    let mut loaded = HashMap::new();
+    for (user_id, ids) in by_user {
+        let names = pool.get_account_names(&ids, &user_id)?;
+        for id in ids {
+            loaded.insert((user_id, id), names.get(&id).cloned());
+        }
+    }
+    Ok(loaded)
+}
@@ -1,25 +1,118 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use futures::{future, Stream, StreamExt};
 use juniper::{
-    graphql_object, EmptySubscription, FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject,
+    graphql_object, graphql_scalar, graphql_subscription, parser::ScalarToken, FieldResult,
+    GraphQLEnum, GraphQLInputObject, GraphQLObject, Nullable, ParseScalarResult, ParseScalarValue,
+    Value,
 };
-use log;
 use uuid::Uuid;
 
+use crate::auth;
 use crate::database;
 use crate::entities;
+use crate::events::{Event, EventBus, Lagged};
+use crate::graphql_errors::{code_error, field_error, ErrorCode, ToErrorCode};
+use crate::graphql_loaders::AccountNames;
+use crate::jwt;
+use crate::nubank;
+use crate::sendemail;
 use crate::services;
 
-#[derive(GraphQLEnum, Clone)]
+// Amounts travel as decimal strings like "-1234.50" so they round-trip
+// exactly. Inputs may also be numbers, as long as they have at most two
+// decimals.
+#[derive(Clone, Copy, Debug)]
+struct Money(entities::money::Money);
+
+#[graphql_scalar(
+    name = "Money",
+    description = "An exact amount of money, like \"-1234.50\""
+)]
+impl<S> GraphQLScalar for Money
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.to_string())
+    }
+
+    fn from_input_value(value: &InputValue) -> Option<Money> {
+        if let Some(text) = value.as_string_value() {
+            return text.parse().ok().map(Money);
+        }
+        if let Some(units) = value.as_int_value() {
+            return Some(Money(entities::money::Money::from_cents(
+                units as i64 * 100,
+            )));
+        }
+        let float = value.as_float_value()?;
+        let money = entities::money::Money::from_f64(float);
+        // Anything that is not the closest float to a whole number of cents
+        // had more than two decimals.
+        (money.to_f64() == float).then_some(Money(money))
+    }
+
+    fn from_str<'a>(value: ScalarToken<'a>) -> ParseScalarResult<'a, S> {
+        match value {
+            ScalarToken::String(_) => <String as ParseScalarValue<S>>::from_str(value),
+            ScalarToken::Int(_) => <i32 as ParseScalarValue<S>>::from_str(value),
+            ScalarToken::Float(_) => <f64 as ParseScalarValue<S>>::from_str(value),
+        }
+    }
+}
+
+#[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Order {
     ASC,
     DESC,
 }
 
+#[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum Language {
+    PT,
+    EN,
+}
+
+#[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum IntegrationStatus {
+    CREATED,
+    PENDING,
+    CONNECTED,
+}
+
 #[derive(GraphQLObject, Clone, Debug)]
 pub struct Integration {
     id: Uuid,
     name: String,
     time: NaiveDateTime,
+    status: IntegrationStatus,
+    // Where the confirmation code was sent, while the onboarding is pending.
+    sent_to: Option<String>,
+    account_id: Option<Uuid>,
+}
+
+// What happened to the events fetched by a sync.
+#[derive(GraphQLObject, Clone, Debug)]
+struct SyncReport {
+    imported: i32,
+    skipped: i32,
+    failed: i32,
+}
+
+// Sent while a sync goes through the `total` events it fetched, and once
+// more with `done` when it is over.
+#[derive(GraphQLObject, Clone, Debug)]
+struct IntegrationSyncProgress {
+    integration_id: Uuid,
+    total: i32,
+    report: SyncReport,
+    done: bool,
 }
 
 // A Cashtools user.
@@ -31,20 +124,137 @@ pub struct User {
     email: String,
     integrations: Vec<Integration>,
     payday: Option<i32>,
+    // When the account will be deleted, unless the user logs in before.
+    deletion_scheduled_for: Option<NaiveDateTime>,
+    // New email waiting for confirmation with `confirmEmailChange`.
+    pending_email: Option<String>,
 }
 
-// A simple transaction.
-#[derive(GraphQLObject, Clone, Debug)]
+#[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum TransactionKind {
+    MANUAL,
+    EARNING,
+    REFILL,
+    SWEEP,
+    IMPORT,
+}
+
+// A simple transaction. The account names come with it when the service
+// already looked them up, and are batched through the request's
+// AccountNames loader otherwise.
+#[derive(Clone, Debug)]
 struct Transaction {
     id: Uuid,
     related_user: Uuid,
     entry_date: NaiveDate,
     entry_account_code: Option<Uuid>,
-    entry_account_name: Option<String>,
     exit_account_code: Option<Uuid>,
-    exit_account_name: Option<String>,
-    amount: f64,
+    // The entry and exit account names, when already known.
+    names: Option<(Option<String>, Option<String>)>,
+    amount: Money,
+    description: Option<String>,
+    voided: bool,
+    kind: TransactionKind,
+}
+
+#[graphql_object(context = Context)]
+impl Transaction {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn related_user(&self) -> Uuid {
+        self.related_user
+    }
+
+    fn entry_date(&self) -> NaiveDate {
+        self.entry_date
+    }
+
+    fn entry_account_code(&self) -> Option<Uuid> {
+        self.entry_account_code
+    }
+
+    // Null when there is no entry account or it no longer exists.
+    async fn entry_account_name(&self, context: &Context) -> FieldResult<Option<String>> {
+        match &self.names {
+            Some((entry, _)) => Ok(entry.clone()),
+            None => {
+                context
+                    .account_name(self.related_user, self.entry_account_code)
+                    .await
+            }
+        }
+    }
+
+    fn exit_account_code(&self) -> Option<Uuid> {
+        self.exit_account_code
+    }
+
+    // Null when there is no exit account or it no longer exists.
+    async fn exit_account_name(&self, context: &Context) -> FieldResult<Option<String>> {
+        match &self.names {
+            Some((_, exit)) => Ok(exit.clone()),
+            None => {
+                context
+                    .account_name(self.related_user, self.exit_account_code)
+                    .await
+            }
+        }
+    }
+
+    fn amount(&self) -> Money {
+        self.amount
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn voided(&self) -> bool {
+        self.voided
+    }
+
+    fn kind(&self) -> TransactionKind {
+        self.kind
+    }
+}
+
+// Filters that can be applied when listing transactions.
+#[derive(GraphQLInputObject, Clone, Debug)]
+struct TransactionFilter {
+    from_date: Option<NaiveDate>,
+    to_date: Option<NaiveDate>,
+    account_id: Option<Uuid>,
+    min_amount: Option<Money>,
+    max_amount: Option<Money>,
     description: Option<String>,
+    // Only transactions in the cycle containing this date.
+    cycle_containing: Option<NaiveDate>,
+}
+
+#[derive(GraphQLObject, Clone, Debug)]
+struct PageInfo {
+    has_next_page: bool,
+    has_previous_page: bool,
+    start_cursor: Option<String>,
+    end_cursor: Option<String>,
+}
+
+#[derive(GraphQLObject, Clone, Debug)]
+#[graphql(context = Context)]
+struct TransactionEdge {
+    cursor: String,
+    node: Transaction,
+}
+
+// A page of transactions, following the Relay connection spec.
+#[derive(GraphQLObject, Clone, Debug)]
+#[graphql(context = Context)]
+struct TransactionConnection {
+    edges: Vec<TransactionEdge>,
+    page_info: PageInfo,
 }
 
 // An input transaction.
@@ -53,11 +263,23 @@ struct NewTransaction {
     entry_date: NaiveDate,
     entry_account_code: Option<Uuid>,
     exit_account_code: Option<Uuid>,
-    amount: f64,
+    amount: Money,
     description: Option<String>,
 }
 
+// Transaction fields that can be updated. Accounts and description are
+// cleared by an explicit null, and kept when left out.
+#[derive(GraphQLInputObject, Clone, Debug)]
+struct UpdatedTransaction {
+    entry_date: Option<NaiveDate>,
+    entry_account_code: Nullable<Uuid>,
+    exit_account_code: Nullable<Uuid>,
+    amount: Option<Money>,
+    description: Nullable<String>,
+}
+
 #[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 enum EarningIndex {
     CDI,
     FIXED,
@@ -78,14 +300,16 @@ struct EarningInput {
 
 #[derive(GraphQLObject, Clone, Debug)]
 struct PreAllocation {
-    amount: f64,
+    amount: Money,
     accumulative: bool,
+    source: Option<Uuid>,
 }
 
 #[derive(GraphQLInputObject, Clone, Copy, Debug)]
 struct PreAllocationInput {
-    amount: Option<f64>,
+    amount: Option<Money>,
     accumulative: Option<bool>,
+    source: Option<Uuid>,
 }
 
 // Account fields that can be updated.
@@ -99,11 +323,21 @@ struct UpdatedAccount {
     in_trash: Option<bool>,
 }
 
+// Profile fields that can be updated. A new email is only used once the
+// code sent to it is confirmed. The payday is cleared by an explicit null.
+#[derive(GraphQLInputObject, Clone, Debug)]
+struct UpdatedProfile {
+    name: Option<String>,
+    username: Option<String>,
+    email: Option<String>,
+    payday: Nullable<i32>,
+}
+
 // An input account.
 #[derive(GraphQLInputObject, Clone, Debug)]
 struct NewAccount {
     time: NaiveDateTime,
-    initial_balance: f64,
+    initial_balance: Money,
     name: String,
     description: Option<String>,
     pre_allocation: Option<PreAllocationInput>,
@@ -111,6 +345,58 @@ struct NewAccount {
     is_available: bool,
 }
 
+#[derive(GraphQLEnum, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+enum TagMatch {
+    ANY,
+    ALL,
+}
+
+// A user-defined label that can be attached to accounts.
+#[derive(GraphQLObject, Clone, Debug)]
+struct Tag {
+    id: Uuid,
+    name: String,
+    color: Option<String>,
+    time: NaiveDateTime,
+}
+
+// How an earning account is expected to evolve until a given date.
+#[derive(GraphQLObject, Clone, Debug)]
+struct EarningProjection {
+    account_id: Uuid,
+    from: NaiveDate,
+    until: NaiveDate,
+    balance: Money,
+    projected_balance: Money,
+    projected_earnings: Money,
+}
+
+// From one payday to the day before the next one.
+#[derive(GraphQLObject, Clone, Debug)]
+struct Cycle {
+    start: NaiveDate,
+    end: NaiveDate,
+}
+
+// How an envelope is doing in the current budget cycle.
+#[derive(GraphQLObject, Clone, Debug)]
+struct EnvelopeStatus {
+    account_id: Uuid,
+    name: String,
+    accumulative: bool,
+    allocated: Money,
+    spent: Money,
+    remaining: Money,
+}
+
+#[derive(GraphQLObject, Clone, Debug)]
+struct BudgetStatus {
+    cycle_start: NaiveDate,
+    cycle_end: NaiveDate,
+    envelopes: Vec<EnvelopeStatus>,
+}
+
 // A simple account.
 #[derive(GraphQLObject, Clone, Debug)]
 struct Account {
@@ -118,11 +404,39 @@ struct Account {
     time: NaiveDateTime,
     name: String,
     description: Option<String>,
-    balance: f64,
+    balance: Money,
     pre_allocation: Option<PreAllocation>,
     earning: Option<Earning>,
     is_available: bool,
     in_trash: bool,
+    last_refill_date: Option<NaiveDate>,
+}
+
+// A device the user is logged in on.
+#[derive(GraphQLObject, Clone, Debug)]
+struct Session {
+    id: Uuid,
+    device_name: Option<String>,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>,
+}
+
+// The access token goes in the Authorization header and lasts minutes; the
+// refresh token gets a new pair once it expires.
+#[derive(GraphQLObject, Clone, Debug)]
+struct AuthPayload {
+    access_token: String,
+    refresh_token: String,
+    session: Session,
+}
+
+// Where to download everything Cashtools keeps about the user. The url is
+// relative to the API and works without the access token until it expires.
+#[derive(GraphQLObject, Clone, Debug)]
+struct DataExport {
+    url: String,
+    expires_at: NaiveDateTime,
 }
 
 impl entities::integration::UserIntegration {
@@ -131,6 +445,40 @@ impl entities::integration::UserIntegration {
             id: self.id,
             name: self.name.clone(),
             time: self.time,
+            status: self.status.to_graphql(),
+            sent_to: self.sent_to.clone(),
+            account_id: self.account_id,
+        }
+    }
+}
+
+impl entities::integration::SyncReport {
+    fn to_graphql(self) -> SyncReport {
+        SyncReport {
+            imported: self.imported,
+            skipped: self.skipped,
+            failed: self.failed,
+        }
+    }
+}
+
+impl entities::integration::SyncProgress {
+    fn to_graphql(self) -> IntegrationSyncProgress {
+        IntegrationSyncProgress {
+            integration_id: self.integration_id,
+            total: self.total,
+            report: self.report.to_graphql(),
+            done: self.done,
+        }
+    }
+}
+
+impl entities::integration::IntegrationStatus {
+    fn to_graphql(self) -> IntegrationStatus {
+        match self {
+            entities::integration::IntegrationStatus::CREATED => IntegrationStatus::CREATED,
+            entities::integration::IntegrationStatus::PENDING => IntegrationStatus::PENDING,
+            entities::integration::IntegrationStatus::CONNECTED => IntegrationStatus::CONNECTED,
         }
     }
 }
@@ -144,10 +492,55 @@ impl entities::user::UserWithIntegrations {
             email: self.email.clone(),
             integrations: self.integrations.iter().map(|t| t.to_graphql()).collect(),
             payday: self.payday,
+            deletion_scheduled_for: self.deletion_scheduled_for,
+            pending_email: self.pending_email.clone(),
         }
     }
 }
 
+impl entities::session::Session {
+    fn to_graphql(&self) -> Session {
+        Session {
+            id: self.id,
+            device_name: self.device_name.clone(),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            revoked_at: self.revoked_at,
+        }
+    }
+}
+
+impl entities::session::AuthTokens {
+    fn to_graphql(&self) -> AuthPayload {
+        AuthPayload {
+            access_token: self.access_token.clone(),
+            refresh_token: self.refresh_token.clone(),
+            session: self.session.to_graphql(),
+        }
+    }
+}
+
+impl entities::export::ExportLink {
+    fn to_graphql(&self) -> DataExport {
+        DataExport {
+            url: self.url.clone(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+impl entities::money::Money {
+    fn to_graphql(self) -> Money {
+        Money(self)
+    }
+}
+
+impl Money {
+    fn to_entity(self) -> entities::money::Money {
+        self.0
+    }
+}
+
 impl entities::transaction::TransactionWithNames {
     fn to_graphql(&self) -> Transaction {
         Transaction {
@@ -155,17 +548,128 @@ impl entities::transaction::TransactionWithNames {
             related_user: self.related_user,
             entry_date: self.entry_date,
             entry_account_code: self.entry_account_code,
-            entry_account_name: self.entry_account_name.clone(),
             exit_account_code: self.exit_account_code,
-            exit_account_name: self.exit_account_name.clone(),
-            amount: self.amount,
+            names: Some((
+                self.entry_account_name.clone(),
+                self.exit_account_name.clone(),
+            )),
+            amount: self.amount.to_graphql(),
             description: self.description.clone(),
+            voided: self.voided,
+            kind: self.kind.to_graphql(),
+        }
+    }
+}
+
+impl entities::transaction::Transaction {
+    fn to_graphql(&self) -> Transaction {
+        Transaction {
+            id: self.id,
+            related_user: self.related_user,
+            entry_date: self.entry_date,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            names: None,
+            amount: self.amount.to_graphql(),
+            description: self.description.clone(),
+            voided: self.voided,
+            kind: self.kind.to_graphql(),
+        }
+    }
+}
+
+impl entities::transaction::TransactionPage {
+    fn to_graphql(&self) -> TransactionConnection {
+        let edges: Vec<TransactionEdge> = self
+            .transactions
+            .iter()
+            .map(|t| TransactionEdge {
+                cursor: t.cursor().encode(),
+                node: t.to_graphql(),
+            })
+            .collect();
+        TransactionConnection {
+            page_info: PageInfo {
+                has_next_page: self.has_next_page,
+                has_previous_page: self.has_previous_page,
+                start_cursor: edges.first().map(|e| e.cursor.clone()),
+                end_cursor: edges.last().map(|e| e.cursor.clone()),
+            },
+            edges,
+        }
+    }
+}
+
+impl entities::tag::Tag {
+    fn to_graphql(&self) -> Tag {
+        Tag {
+            id: self.id,
+            name: self.name.clone(),
+            color: self.color.clone(),
+            time: self.time,
+        }
+    }
+}
+
+impl entities::transaction::TransactionKind {
+    fn to_graphql(self) -> TransactionKind {
+        match self {
+            entities::transaction::TransactionKind::MANUAL => TransactionKind::MANUAL,
+            entities::transaction::TransactionKind::EARNING => TransactionKind::EARNING,
+            entities::transaction::TransactionKind::REFILL => TransactionKind::REFILL,
+            entities::transaction::TransactionKind::SWEEP => TransactionKind::SWEEP,
+            entities::transaction::TransactionKind::IMPORT => TransactionKind::IMPORT,
+        }
+    }
+}
+
+impl entities::earning::EarningProjection {
+    fn to_graphql(&self) -> EarningProjection {
+        EarningProjection {
+            account_id: self.account_id,
+            from: self.from,
+            until: self.until,
+            balance: self.balance.to_graphql(),
+            projected_balance: self.projected_balance.to_graphql(),
+            projected_earnings: self.projected_earnings.to_graphql(),
+        }
+    }
+}
+
+impl entities::cycle::Cycle {
+    fn to_graphql(self) -> Cycle {
+        Cycle {
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+impl entities::budget::EnvelopeStatus {
+    fn to_graphql(&self) -> EnvelopeStatus {
+        EnvelopeStatus {
+            account_id: self.account_id,
+            name: self.name.clone(),
+            accumulative: self.accumulative,
+            allocated: self.allocated.to_graphql(),
+            spent: self.spent.to_graphql(),
+            remaining: self.remaining.to_graphql(),
+        }
+    }
+}
+
+impl entities::budget::BudgetStatus {
+    fn to_graphql(&self) -> BudgetStatus {
+        BudgetStatus {
+            cycle_start: self.cycle_start,
+            cycle_end: self.cycle_end,
+            envelopes: self.envelopes.iter().map(|e| e.to_graphql()).collect(),
         }
     }
 }
 
 impl entities::account::EarningIndex {
-    fn to_graphql(&self) -> EarningIndex {
+    fn to_graphql(self) -> EarningIndex {
         match self {
             entities::account::EarningIndex::CDI => EarningIndex::CDI,
             entities::account::EarningIndex::FIXED => EarningIndex::FIXED,
@@ -175,16 +679,17 @@ impl entities::account::EarningIndex {
 }
 
 impl entities::account::PreAllocation {
-    fn to_graphql(&self) -> PreAllocation {
+    fn to_graphql(self) -> PreAllocation {
         PreAllocation {
-            amount: self.amount,
+            amount: self.amount.to_graphql(),
             accumulative: self.accumulative,
+            source: self.source,
         }
     }
 }
 
 impl entities::account::Earning {
-    fn to_graphql(&self) -> Earning {
+    fn to_graphql(self) -> Earning {
         Earning {
             rate: self.rate,
             index: self.index.to_graphql(),
@@ -199,11 +704,12 @@ impl entities::account::Account {
             time: self.time,
             name: self.name.clone(),
             description: self.description.clone(),
-            balance: self.balance,
+            balance: self.balance.to_graphql(),
             pre_allocation: self.pre_allocation.map(|x| x.to_graphql()),
             earning: self.earning.map(|x| x.to_graphql()),
             is_available: self.is_available,
             in_trash: self.in_trash,
+            last_refill_date: self.last_refill_date,
         }
     }
 }
@@ -221,24 +727,88 @@ impl UpdatedAccount {
     }
 }
 
+impl UpdatedProfile {
+    fn to_entity(&self) -> entities::user::UpdatedProfile {
+        entities::user::UpdatedProfile {
+            name: self.name.clone(),
+            username: self.username.clone(),
+            payday: self.payday.explicit(),
+        }
+    }
+}
+
 impl NewTransaction {
     fn to_entity(&self) -> entities::transaction::NewTransaction {
         entities::transaction::NewTransaction {
             entry_date: self.entry_date,
-            entry_account_code: self.entry_account_code.clone(),
-            exit_account_code: self.exit_account_code.clone(),
-            amount: self.amount,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            amount: self.amount.to_entity(),
+            description: self.description.clone(),
+            kind: entities::transaction::TransactionKind::MANUAL,
+        }
+    }
+}
+
+impl TagMatch {
+    fn to_entity(self) -> entities::tag::TagMatch {
+        match self {
+            TagMatch::ANY => entities::tag::TagMatch::ANY,
+            TagMatch::ALL => entities::tag::TagMatch::ALL,
+        }
+    }
+}
+
+impl Language {
+    fn to_entity(self) -> entities::Language {
+        match self {
+            Language::PT => entities::Language::PT,
+            Language::EN => entities::Language::EN,
+        }
+    }
+}
+
+impl Order {
+    fn to_entity(self) -> entities::Order {
+        match self {
+            Order::ASC => entities::Order::ASC,
+            Order::DESC => entities::Order::DESC,
+        }
+    }
+}
+
+impl TransactionFilter {
+    fn to_entity(&self) -> entities::transaction::TransactionFilter {
+        entities::transaction::TransactionFilter {
+            from_date: self.from_date,
+            to_date: self.to_date,
+            account: self.account_id,
+            min_amount: self.min_amount.map(|x| x.to_entity()),
+            max_amount: self.max_amount.map(|x| x.to_entity()),
             description: self.description.clone(),
         }
     }
 }
 
+impl UpdatedTransaction {
+    fn to_entity(&self) -> entities::transaction::UpdatedTransaction {
+        entities::transaction::UpdatedTransaction {
+            entry_date: self.entry_date,
+            entry_account_code: self.entry_account_code.explicit(),
+            exit_account_code: self.exit_account_code.explicit(),
+            amount: self.amount.map(|x| x.to_entity()),
+            description: self.description.clone().explicit(),
+        }
+    }
+}
+
 impl PreAllocationInput {
-    fn to_entity(&self) -> Option<entities::account::PreAllocation> {
+    fn to_entity(self) -> Option<entities::account::PreAllocation> {
         match (self.amount, self.accumulative) {
             (Some(amount), Some(accumulative)) => Some(entities::account::PreAllocation {
-                amount,
+                amount: amount.to_entity(),
                 accumulative,
+                source: self.source,
             }),
             _ => None,
         }
@@ -246,7 +816,7 @@ impl PreAllocationInput {
 }
 
 impl EarningIndex {
-    fn to_entity(&self) -> entities::account::EarningIndex {
+    fn to_entity(self) -> entities::account::EarningIndex {
         match self {
             EarningIndex::CDI => entities::account::EarningIndex::CDI,
             EarningIndex::FIXED => entities::account::EarningIndex::FIXED,
@@ -256,7 +826,7 @@ impl EarningIndex {
 }
 
 impl EarningInput {
-    fn to_entity(&self) -> entities::account::Earning {
+    fn to_entity(self) -> entities::account::Earning {
         entities::account::Earning {
             rate: self.rate,
             index: self.index.to_entity(),
@@ -268,7 +838,7 @@ impl NewAccount {
     fn to_entity(&self) -> entities::account::NewAccount {
         entities::account::NewAccount {
             time: self.time,
-            initial_balance: self.initial_balance,
+            initial_balance: self.initial_balance.to_entity(),
             name: self.name.clone(),
             description: self.description.clone(),
             pre_allocation: self.pre_allocation.and_then(|x| x.to_entity()),
@@ -278,13 +848,270 @@ impl NewAccount {
     }
 }
 
+#[derive(Clone)]
 pub struct Context {
     pub pool: database::DbPool,
     pub jwt_secret: String,
+    pub integration_encryption_key: String,
     pub env: entities::Env,
+    pub magic_login_code: Option<i32>,
+    pub mailer: Arc<dyn sendemail::Mailer>,
+    pub nubank: nubank::NubankTransport,
+    pub events: EventBus,
+    pub auth: auth::Authentication,
+    // Batches the account name lookups of one request. A subscription keeps
+    // its context for the whole connection, so it has none and looks the
+    // names up afresh for each event instead of serving stale ones.
+    pub account_names: Option<Arc<AccountNames>>,
 }
 impl juniper::Context for Context {}
 
+impl Context {
+    // The context of a single request, authenticated once by its
+    // Authorization header.
+    pub async fn for_request(&self, token: Option<String>) -> Context {
+        Context {
+            account_names: Some(Arc::default()),
+            ..self.for_connection(token).await
+        }
+    }
+
+    // The context of a WebSocket connection, authenticated once by the
+    // token of `connection_init`.
+    pub async fn for_connection(&self, token: Option<String>) -> Context {
+        let auth = match token {
+            None => auth::Authentication::Anonymous,
+            Some(token) => {
+                let pool = self.pool.clone();
+                let jwt_secret = self.jwt_secret.clone();
+                tokio::task::spawn_blocking(move || {
+                    auth::Authentication::from_token(
+                        &pool,
+                        Utc::now().naive_utc(),
+                        Some(&token),
+                        &jwt_secret,
+                    )
+                })
+                .await
+                .unwrap_or_else(|err| {
+                    log::error!("Authorization header check panicked: {}", err);
+                    auth::Authentication::Invalid
+                })
+            }
+        };
+        Context {
+            auth,
+            account_names: None,
+            ..self.clone()
+        }
+    }
+
+    async fn account_name(&self, user_id: Uuid, id: Option<Uuid>) -> FieldResult<Option<String>> {
+        match id {
+            Some(id) => match &self.account_names {
+                Some(names) => names.load(&self.pool, user_id, id).await,
+                None => AccountNames::default().load(&self.pool, user_id, id).await,
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Diesel is synchronous, so database work goes to the blocking thread
+    // pool instead of holding up the async workers.
+    async fn run<T, E, F>(&self, f: F) -> FieldResult<T>
+    where
+        F: FnOnce(&database::DbPool) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: ToErrorCode + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        match tokio::task::spawn_blocking(move || f(&pool)).await {
+            Ok(result) => result.map_err(field_error),
+            Err(err) => {
+                log::error!("Database task panicked: {}", err);
+                Err(code_error(ErrorCode::Internal))
+            }
+        }
+    }
+
+    // `token` is the deprecated argument every authenticated field still
+    // accepts while clients move to the Authorization header. Only that
+    // argument needs the database, the header was checked on arrival.
+    async fn claims(&self, token: Option<String>) -> FieldResult<jwt::Claims> {
+        let now = Utc::now().naive_utc();
+        match (&self.auth, token) {
+            (auth::Authentication::Anonymous, Some(token)) => {
+                let jwt_secret = self.jwt_secret.clone();
+                self.run(move |pool| auth::verify_token(pool, now, &token, &jwt_secret))
+                    .await
+            }
+            (authentication, token) => authentication
+                .claims(&self.pool, now, token.as_deref(), &self.jwt_secret)
+                .map_err(field_error),
+        }
+    }
+
+    async fn user_id(&self, token: Option<String>) -> FieldResult<Uuid> {
+        self.claims(token).await.map(|claims| claims.user_id)
+    }
+
+    // What mutations change is published to the user's subscriptions, so
+    // their other devices see it too.
+    fn publish_transactions(
+        &self,
+        user_id: Uuid,
+        transactions: &[entities::transaction::TransactionWithNames],
+    ) {
+        for transaction in transactions {
+            self.events
+                .publish(user_id, Event::TransactionCreated(transaction.clone()));
+        }
+    }
+
+    fn publish_account(&self, user_id: Uuid, account: &entities::account::Account) {
+        self.events
+            .publish(user_id, Event::AccountUpdated(account.clone()));
+    }
+
+    // Loads and publishes the accounts in `ids`, or every account of the
+    // user for changes that may have moved balances of accounts no longer
+    // known. The mutation is done by then, so a failure is only logged.
+    async fn publish_accounts(&self, user_id: Uuid, ids: Option<Vec<Uuid>>) {
+        let pool = self.pool.clone();
+        let accounts = tokio::task::spawn_blocking(move || {
+            services::account::get_accounts(
+                &pool,
+                &user_id,
+                None,
+                None,
+                None,
+                entities::tag::TagMatch::ANY,
+            )
+        })
+        .await;
+        match accounts {
+            Ok(Ok(accounts)) => accounts
+                .iter()
+                .filter(|account| ids.as_ref().map_or(true, |ids| ids.contains(&account.id)))
+                .for_each(|account| self.publish_account(user_id, account)),
+            Ok(Err(err)) => log::error!("Failed to load the accounts to publish: {}", err),
+            Err(err) => log::error!("Database task panicked: {}", err),
+        }
+    }
+
+    // The accounts whose balances the transactions moved.
+    async fn publish_accounts_of(
+        &self,
+        user_id: Uuid,
+        transactions: &[entities::transaction::TransactionWithNames],
+    ) {
+        let ids = transactions
+            .iter()
+            .flat_map(|t| [t.entry_account_code, t.exit_account_code])
+            .flatten()
+            .collect();
+        self.publish_accounts(user_id, Some(ids)).await
+    }
+
+    // Streams end with TOKEN_EXPIRED when the token they were started with
+    // does, and with UNAUTHENTICATED once its session is revoked.
+    async fn subscribe<T, F>(&self, token: Option<String>, pick: F) -> FieldResult<EventStream<T>>
+    where
+        F: Fn(Event) -> Option<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let claims = self.claims(token).await?;
+        let expires_in = (claims.expires_at - Utc::now().naive_utc())
+            .to_std()
+            .unwrap_or_default();
+        let live = LiveEvents {
+            events: Box::pin(self.events.subscribe(claims.user_id)),
+            expiry: Box::pin(tokio::time::sleep(expires_in)),
+            checks: tokio::time::interval_at(
+                tokio::time::Instant::now() + SESSION_CHECK_INTERVAL,
+                SESSION_CHECK_INTERVAL,
+            ),
+            pool: self.pool.clone(),
+            claims,
+            ended: false,
+        };
+        let stream = futures::stream::unfold(live, LiveEvents::next).filter_map(move |item| {
+            future::ready(match item {
+                Ok(event) => pick(event).map(Ok),
+                Err(err) => Some(Err(err)),
+            })
+        });
+        Ok(Box::pin(stream))
+    }
+}
+
+// Revocations publish an event, this catches the ones that don't, like a
+// purged user.
+const SESSION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+// The events of a subscription while its token and session are good.
+struct LiveEvents {
+    events: Pin<Box<dyn Stream<Item = Result<Event, Lagged>> + Send>>,
+    expiry: Pin<Box<tokio::time::Sleep>>,
+    checks: tokio::time::Interval,
+    pool: database::DbPool,
+    claims: jwt::Claims,
+    ended: bool,
+}
+
+impl LiveEvents {
+    async fn next(mut self) -> Option<(FieldResult<Event>, Self)> {
+        if self.ended {
+            return None;
+        }
+        let error = loop {
+            tokio::select! {
+                received = self.events.next() => match received {
+                    Some(Ok(Event::SessionRevoked(id))) if id == self.claims.session => {
+                        break code_error(ErrorCode::Unauthenticated)
+                    }
+                    Some(Ok(Event::UserDeleted)) => break code_error(ErrorCode::Unauthenticated),
+                    Some(Ok(event)) => return Some((Ok(event), self)),
+                    Some(Err(Lagged(missed))) => {
+                        let user_id = self.claims.user_id;
+                        log::warn!("A subscriber of {} missed {} events", user_id, missed);
+                        return Some((Err(code_error(ErrorCode::EventsMissed)), self));
+                    }
+                    None => return None,
+                },
+                _ = &mut self.expiry => break code_error(ErrorCode::TokenExpired),
+                _ = self.checks.tick() => {
+                    if !session_is_active(self.pool.clone(), self.claims).await {
+                        break code_error(ErrorCode::Unauthenticated);
+                    }
+                }
+            }
+        };
+        self.ended = true;
+        Some((Err(error), self))
+    }
+}
+
+// Database failures keep the subscription, the next check tries again.
+async fn session_is_active(pool: database::DbPool, claims: jwt::Claims) -> bool {
+    match tokio::task::spawn_blocking(move || auth::check_session(&pool, &claims)).await {
+        Ok(Ok(())) => true,
+        Ok(Err(auth::AuthError::SessionModelFailed(err))) => {
+            log::error!("Failed to check a subscription's session: {:?}", err);
+            true
+        }
+        Ok(Err(_)) => false,
+        Err(err) => {
+            log::error!("Session check panicked: {}", err);
+            true
+        }
+    }
+}
+
+fn today() -> NaiveDate {
+    Utc::now().naive_utc().date()
+}
+
 pub struct Query;
 
 #[graphql_object(context = Context)]
@@ -293,62 +1120,174 @@ impl Query {
         "1.0"
     }
 
-    async fn account(context: &Context, token: String, id: Uuid) -> FieldResult<Account> {
-        let account = services::account::auth_and_get_account(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            &id,
-        )?;
+    async fn account(context: &Context, token: Option<String>, id: Uuid) -> FieldResult<Account> {
+        let user_id = context.user_id(token).await?;
+        let account = context
+            .run(move |pool| services::account::get_account(pool, &user_id, &id))
+            .await?;
         Ok(account.to_graphql())
     }
     async fn accounts(
         context: &Context,
-        token: String,
+        token: Option<String>,
         is_pre_allocation: Option<bool>,
         in_trash: Option<bool>,
         tags: Option<Vec<Uuid>>,
+        tag_match: Option<TagMatch>,
     ) -> FieldResult<Vec<Account>> {
-        let accounts = services::account::auth_and_get_accounts(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            is_pre_allocation,
-            in_trash,
-            tags,
-        )?
-        .iter()
-        .map(|t| t.to_graphql())
-        .collect();
+        let user_id = context.user_id(token).await?;
+        let tag_match = tag_match.unwrap_or(TagMatch::ANY).to_entity();
+        let accounts = context
+            .run(move |pool| {
+                services::account::get_accounts(
+                    pool,
+                    &user_id,
+                    is_pre_allocation,
+                    in_trash,
+                    tags,
+                    tag_match,
+                )
+            })
+            .await?
+            .iter()
+            .map(|t| t.to_graphql())
+            .collect();
         Ok(accounts)
     }
 
-    async fn transactions(context: &Context, token: String) -> FieldResult<Vec<Transaction>> {
-        let transactions = services::transaction::auth_and_list_user_transactions(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-        )?
-        .iter()
-        .map(|t| t.to_graphql())
-        .collect();
-        Ok(transactions)
+    async fn transactions(
+        context: &Context,
+        token: Option<String>,
+        filter: Option<TransactionFilter>,
+        order: Option<Order>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<TransactionConnection> {
+        let user_id = context.user_id(token).await?;
+        let after = after
+            .map(|cursor| services::transaction::parse_cursor(&cursor))
+            .transpose()
+            .map_err(field_error)?;
+        let page = entities::transaction::TransactionPageRequest {
+            first: first.map_or(services::transaction::DEFAULT_PAGE_SIZE, i64::from),
+            after,
+            order: order.unwrap_or(Order::DESC).to_entity(),
+        };
+        let cycle_containing = filter.as_ref().and_then(|f| f.cycle_containing);
+        let filter = filter.map(|f| f.to_entity()).unwrap_or_default();
+        let transactions = context
+            .run(move |pool| {
+                services::transaction::list_user_transactions(
+                    pool,
+                    &user_id,
+                    filter,
+                    cycle_containing,
+                    page,
+                )
+            })
+            .await?;
+        Ok(transactions.to_graphql())
+    }
+
+    async fn tags(context: &Context, token: Option<String>) -> FieldResult<Vec<Tag>> {
+        let user_id = context.user_id(token).await?;
+        let tags = context
+            .run(move |pool| services::tag::list_tags(pool, &user_id))
+            .await?
+            .iter()
+            .map(|t| t.to_graphql())
+            .collect();
+        Ok(tags)
+    }
+
+    async fn project_earnings(
+        context: &Context,
+        token: Option<String>,
+        account_id: Uuid,
+        until: NaiveDate,
+    ) -> FieldResult<EarningProjection> {
+        let user_id = context.user_id(token).await?;
+        let projection = context
+            .run(move |pool| {
+                services::earning::project_earnings(pool, &user_id, &account_id, until)
+            })
+            .await?;
+        Ok(projection.to_graphql())
+    }
+
+    async fn current_cycle(context: &Context, token: Option<String>) -> FieldResult<Cycle> {
+        let user_id = context.user_id(token).await?;
+        let cycle = context
+            .run(move |pool| services::cycle::current_cycle(pool, &user_id, today()))
+            .await?;
+        Ok(cycle.to_graphql())
+    }
+
+    async fn cycles(
+        context: &Context,
+        token: Option<String>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> FieldResult<Vec<Cycle>> {
+        let user_id = context.user_id(token).await?;
+        let cycles = context
+            .run(move |pool| services::cycle::list_cycles(pool, &user_id, from, to))
+            .await?
+            .iter()
+            .map(|c| c.to_graphql())
+            .collect();
+        Ok(cycles)
+    }
+
+    async fn budget_status(context: &Context, token: Option<String>) -> FieldResult<BudgetStatus> {
+        let user_id = context.user_id(token).await?;
+        let status = context
+            .run(move |pool| services::budget::budget_status(pool, &user_id, today()))
+            .await?;
+        Ok(status.to_graphql())
     }
 
-    async fn me(context: &Context, token: String) -> FieldResult<User> {
-        let user = services::user::auth_and_get_user(&context.pool, &token, &context.jwt_secret)?;
+    async fn me(context: &Context, token: Option<String>) -> FieldResult<User> {
+        let user_id = context.user_id(token).await?;
+        let user = context
+            .run(move |pool| services::user::get_user(pool, user_id))
+            .await?;
         Ok(user.to_graphql())
     }
 
+    async fn sessions(context: &Context, token: Option<String>) -> FieldResult<Vec<Session>> {
+        let user_id = context.user_id(token).await?;
+        let sessions = context
+            .run(move |pool| services::session::list_sessions(pool, &user_id))
+            .await?
+            .iter()
+            .map(|s| s.to_graphql())
+            .collect();
+        Ok(sessions)
+    }
+
+    // Logs in like the login mutation, but only hands out the access token,
+    // so the session has to log in again once it expires.
+    #[graphql(deprecated = "Use the login mutation, whose tokens can be refreshed and revoked")]
     async fn token(context: &Context, email: String, login_code: i32) -> FieldResult<String> {
-        let token = services::user::validate_and_generate_token(
-            &context.pool,
-            email,
-            login_code,
-            &context.jwt_secret,
-            &context.env,
-        )?;
-        Ok(token)
+        let jwt_secret = context.jwt_secret.clone();
+        let env = context.env;
+        let magic_login_code = context.magic_login_code;
+        let tokens = context
+            .run(move |pool| {
+                services::session::login(
+                    pool,
+                    &email,
+                    login_code,
+                    None,
+                    &jwt_secret,
+                    &env,
+                    magic_login_code,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        Ok(tokens.access_token)
     }
 }
 
@@ -358,132 +1297,625 @@ pub struct Mutations;
 impl Mutations {
     async fn create_account(
         context: &Context,
-        token: String,
+        token: Option<String>,
         account: NewAccount,
     ) -> FieldResult<Account> {
+        let user_id = context.user_id(token).await?;
         println!("Received new account");
         log::debug!("New account: {:?}", account);
-        let account = services::account::auth_and_create_account(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            account.to_entity(),
-        )?;
+        let account = account.to_entity();
+        let account = context
+            .run(move |pool| services::account::create_account(pool, &user_id, account))
+            .await?;
+        context.publish_account(user_id, &account);
         Ok(account.to_graphql())
     }
 
     async fn edit_account(
         context: &Context,
-        token: String,
+        token: Option<String>,
         id: Uuid,
         updated_account: UpdatedAccount,
     ) -> FieldResult<Account> {
-        let account = services::account::auth_and_edit_account(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            &id,
-            updated_account.to_entity(),
-        )?;
+        let user_id = context.user_id(token).await?;
+        let updated_account = updated_account.to_entity();
+        let account = context
+            .run(move |pool| services::account::edit_account(pool, &user_id, &id, updated_account))
+            .await?;
+        context.publish_account(user_id, &account);
         Ok(account.to_graphql())
     }
 
-    async fn delete_account(context: &Context, token: String, id: Uuid) -> FieldResult<Uuid> {
-        let _ = services::account::auth_and_delete_account(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            &id,
-        )?;
+    async fn delete_account(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+    ) -> FieldResult<Uuid> {
+        let user_id = context.user_id(token).await?;
+        context
+            .run(move |pool| services::account::delete_account(pool, &user_id, &id))
+            .await?;
+        context.publish_accounts(user_id, Some(vec![id])).await;
         Ok(id)
     }
 
     async fn pre_allocate(
         context: &Context,
-        token: String,
+        token: Option<String>,
         time: NaiveDate,
         from: Uuid,
         to: Uuid,
-        amount: f64,
+        amount: Money,
         accumulative: bool,
     ) -> FieldResult<PreAllocation> {
-        let pre_allocation_obj = services::account::auth_and_preallocate(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            time,
-            &from,
-            &to,
-            amount,
-            accumulative,
-        )?;
+        let user_id = context.user_id(token).await?;
+        let amount = amount.to_entity();
+        let pre_allocation_obj = context
+            .run(move |pool| {
+                services::account::preallocate(
+                    pool,
+                    &user_id,
+                    time,
+                    &from,
+                    &to,
+                    amount,
+                    accumulative,
+                )
+            })
+            .await?;
+        context
+            .publish_accounts(user_id, Some(vec![from, to]))
+            .await;
         Ok(pre_allocation_obj.to_graphql())
     }
 
+    async fn refill_envelopes(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<Vec<Transaction>> {
+        let user_id = context.user_id(token).await?;
+        let transactions = context
+            .run(move |pool| services::budget::refill_envelopes(pool, &user_id, today()))
+            .await?;
+        context.publish_transactions(user_id, &transactions);
+        context.publish_accounts_of(user_id, &transactions).await;
+        Ok(transactions.iter().map(|t| t.to_graphql()).collect())
+    }
+
+    async fn recalculate_balances(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<Vec<Account>> {
+        let user_id = context.user_id(token).await?;
+        let accounts = context
+            .run(move |pool| services::account::recalculate_balances(pool, &user_id))
+            .await?;
+        for account in &accounts {
+            context.publish_account(user_id, account);
+        }
+        Ok(accounts.iter().map(|t| t.to_graphql()).collect())
+    }
+
+    async fn accrue_earnings(
+        context: &Context,
+        token: Option<String>,
+        until: Option<NaiveDate>,
+    ) -> FieldResult<Vec<Transaction>> {
+        let user_id = context.user_id(token).await?;
+        let until = until.unwrap_or_else(today);
+        let transactions = context
+            .run(move |pool| services::earning::accrue_earnings(pool, &user_id, until, today()))
+            .await?;
+        context.publish_transactions(user_id, &transactions);
+        context.publish_accounts_of(user_id, &transactions).await;
+        Ok(transactions.iter().map(|t| t.to_graphql()).collect())
+    }
+
+    async fn create_tag(
+        context: &Context,
+        token: Option<String>,
+        name: String,
+        color: Option<String>,
+    ) -> FieldResult<Tag> {
+        let user_id = context.user_id(token).await?;
+        let tag = context
+            .run(move |pool| services::tag::create_tag(pool, &user_id, name, color))
+            .await?;
+        Ok(tag.to_graphql())
+    }
+
+    async fn rename_tag(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+        name: String,
+    ) -> FieldResult<Tag> {
+        let user_id = context.user_id(token).await?;
+        let tag = context
+            .run(move |pool| services::tag::rename_tag(pool, &user_id, &id, name))
+            .await?;
+        Ok(tag.to_graphql())
+    }
+
+    async fn recolor_tag(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+        color: String,
+    ) -> FieldResult<Tag> {
+        let user_id = context.user_id(token).await?;
+        let tag = context
+            .run(move |pool| services::tag::recolor_tag(pool, &user_id, &id, color))
+            .await?;
+        Ok(tag.to_graphql())
+    }
+
+    async fn delete_tag(context: &Context, token: Option<String>, id: Uuid) -> FieldResult<Uuid> {
+        let user_id = context.user_id(token).await?;
+        context
+            .run(move |pool| services::tag::delete_tag(pool, &user_id, &id))
+            .await?;
+        Ok(id)
+    }
+
+    async fn tag_account(
+        context: &Context,
+        token: Option<String>,
+        account_id: Uuid,
+        tag_id: Uuid,
+    ) -> FieldResult<Uuid> {
+        let user_id = context.user_id(token).await?;
+        context
+            .run(move |pool| services::tag::tag_account(pool, &user_id, &account_id, &tag_id))
+            .await?;
+        Ok(account_id)
+    }
+
+    async fn untag_account(
+        context: &Context,
+        token: Option<String>,
+        account_id: Uuid,
+        tag_id: Uuid,
+    ) -> FieldResult<Uuid> {
+        let user_id = context.user_id(token).await?;
+        context
+            .run(move |pool| services::tag::untag_account(pool, &user_id, &account_id, &tag_id))
+            .await?;
+        Ok(account_id)
+    }
+
     async fn create_user(
         context: &Context,
         username: String,
         name: String,
         email: String,
+        language: Option<Language>,
     ) -> FieldResult<User> {
-        let created_user = services::user::create_user(&context.pool, &username, &name, &email)?;
+        let mailer = context.mailer.clone();
+        let language = language.unwrap_or(Language::PT).to_entity();
+        let env = context.env;
+        let created_user = context
+            .run(move |pool| {
+                services::user::create_user(
+                    pool,
+                    mailer.as_ref(),
+                    &username,
+                    &name,
+                    &email,
+                    language,
+                    &env,
+                )
+            })
+            .await?;
         Ok(created_user.to_graphql())
     }
 
-    async fn send_login_code(context: &Context, email: String) -> FieldResult<String> {
-        services::user::refresh_login_code(&context.pool, &email)?;
-        Ok(email)
+    async fn send_login_code(
+        context: &Context,
+        email: String,
+        language: Option<Language>,
+    ) -> FieldResult<String> {
+        let mailer = context.mailer.clone();
+        let language = language.unwrap_or(Language::PT).to_entity();
+        let env = context.env;
+        context
+            .run(move |pool| {
+                services::user::refresh_login_code(pool, mailer.as_ref(), &email, language, &env)
+                    .map(|_| email)
+            })
+            .await
+    }
+
+    async fn login(
+        context: &Context,
+        email: String,
+        login_code: i32,
+        device_name: Option<String>,
+    ) -> FieldResult<AuthPayload> {
+        let jwt_secret = context.jwt_secret.clone();
+        let env = context.env;
+        let magic_login_code = context.magic_login_code;
+        let tokens = context
+            .run(move |pool| {
+                services::session::login(
+                    pool,
+                    &email,
+                    login_code,
+                    device_name,
+                    &jwt_secret,
+                    &env,
+                    magic_login_code,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        Ok(tokens.to_graphql())
+    }
+
+    async fn refresh_token(context: &Context, refresh_token: String) -> FieldResult<AuthPayload> {
+        let jwt_secret = context.jwt_secret.clone();
+        let tokens = context
+            .run(move |pool| {
+                services::session::refresh(
+                    pool,
+                    &refresh_token,
+                    &jwt_secret,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        Ok(tokens.to_graphql())
+    }
+
+    // Revokes the given session, or the one making the request.
+    async fn logout(
+        context: &Context,
+        token: Option<String>,
+        session_id: Option<Uuid>,
+    ) -> FieldResult<Session> {
+        let claims = context.claims(token).await?;
+        let session = context
+            .run(move |pool| {
+                services::session::logout(
+                    pool,
+                    &claims.user_id,
+                    &session_id.unwrap_or(claims.session),
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        context
+            .events
+            .publish(claims.user_id, Event::SessionRevoked(session.id));
+        Ok(session.to_graphql())
+    }
+
+    async fn logout_everywhere(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<Vec<Session>> {
+        let user_id = context.user_id(token).await?;
+        let sessions = context
+            .run(move |pool| {
+                services::session::logout_everywhere(pool, &user_id, Utc::now().naive_utc())
+            })
+            .await?;
+        for session in &sessions {
+            context
+                .events
+                .publish(user_id, Event::SessionRevoked(session.id));
+        }
+        Ok(sessions.iter().map(|s| s.to_graphql()).collect())
+    }
+
+    #[graphql(deprecated = "Use updateProfile")]
+    async fn set_payday(
+        context: &Context,
+        token: Option<String>,
+        payday: Option<i32>,
+    ) -> FieldResult<User> {
+        let user_id = context.user_id(token).await?;
+        let user = context
+            .run(move |pool| services::user::set_payday(pool, user_id, payday))
+            .await?;
+        Ok(user.to_graphql())
+    }
+
+    async fn update_profile(
+        context: &Context,
+        token: Option<String>,
+        profile: UpdatedProfile,
+        language: Option<Language>,
+    ) -> FieldResult<User> {
+        let user_id = context.user_id(token).await?;
+        let mailer = context.mailer.clone();
+        let language = language.unwrap_or(Language::PT).to_entity();
+        let env = context.env;
+        let user = context
+            .run(move |pool| {
+                services::user::update_profile(
+                    pool,
+                    mailer.as_ref(),
+                    user_id,
+                    profile.to_entity(),
+                    profile.email,
+                    language,
+                    &env,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        Ok(user.to_graphql())
     }
 
-    async fn delete_user(context: &Context, token: String) -> FieldResult<User> {
-        let user =
-            services::user::auth_and_delete_user(&context.pool, &token, &context.jwt_secret)?;
+    async fn confirm_email_change(
+        context: &Context,
+        token: Option<String>,
+        login_code: i32,
+    ) -> FieldResult<User> {
+        let user_id = context.user_id(token).await?;
+        let user = context
+            .run(move |pool| {
+                services::user::confirm_email_change(
+                    pool,
+                    user_id,
+                    login_code,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
         Ok(user.to_graphql())
     }
+
+    async fn delete_user(
+        context: &Context,
+        token: Option<String>,
+        grace_period_days: Option<i32>,
+    ) -> FieldResult<User> {
+        let user_id = context.user_id(token).await?;
+        let user = context
+            .run(move |pool| {
+                services::user::delete_user(
+                    pool,
+                    user_id,
+                    grace_period_days,
+                    Utc::now().naive_utc(),
+                )
+            })
+            .await?;
+        context.events.publish(user_id, Event::UserDeleted);
+        Ok(user.to_graphql())
+    }
+
+    async fn export_my_data(context: &Context, token: Option<String>) -> FieldResult<DataExport> {
+        let user_id = context.user_id(token).await?;
+        let link = services::export::create_export_link(
+            &user_id,
+            &context.jwt_secret,
+            Utc::now().naive_utc(),
+        )
+        .map_err(field_error)?;
+        Ok(link.to_graphql())
+    }
+
     async fn create_transaction(
         context: &Context,
-        token: String,
+        token: Option<String>,
         transaction: NewTransaction,
     ) -> FieldResult<Transaction> {
-        let created_transaction = services::transaction::auth_and_create_transaction(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            transaction.to_entity(),
-        )?;
-        Ok(created_transaction.to_graphql())
+        let user_id = context.user_id(token).await?;
+        let transaction = transaction.to_entity();
+        let created_transaction = context
+            .run(move |pool| services::transaction::create_transaction(pool, &user_id, transaction))
+            .await?;
+        let created = [created_transaction];
+        context.publish_transactions(user_id, &created);
+        context.publish_accounts_of(user_id, &created).await;
+        Ok(created[0].to_graphql())
+    }
+
+    async fn edit_transaction(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+        updated_transaction: UpdatedTransaction,
+    ) -> FieldResult<Transaction> {
+        let user_id = context.user_id(token).await?;
+        let updated_transaction = updated_transaction.to_entity();
+        let edited_transaction = context
+            .run(move |pool| {
+                services::transaction::edit_transaction(pool, &user_id, &id, updated_transaction)
+            })
+            .await?;
+        // The accounts it may have been moved away from are not known anymore.
+        context.publish_accounts(user_id, None).await;
+        Ok(edited_transaction.to_graphql())
+    }
+
+    async fn delete_transaction(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+    ) -> FieldResult<Uuid> {
+        let user_id = context.user_id(token).await?;
+        context
+            .run(move |pool| services::transaction::delete_transaction(pool, &user_id, &id))
+            .await?;
+        context.publish_accounts(user_id, None).await;
+        Ok(id)
+    }
+
+    async fn void_transaction(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+    ) -> FieldResult<Transaction> {
+        let user_id = context.user_id(token).await?;
+        let voided_transaction = context
+            .run(move |pool| services::transaction::void_transaction(pool, &user_id, &id))
+            .await?;
+        let voided = [voided_transaction];
+        context.publish_accounts_of(user_id, &voided).await;
+        Ok(voided[0].to_graphql())
     }
 
     async fn create_integration(
         context: &Context,
-        token: String,
+        token: Option<String>,
         name: String,
         time: NaiveDateTime,
     ) -> FieldResult<Integration> {
-        let created_integration = services::user::auth_and_create_integration(
-            &context.pool,
-            &token,
-            &context.jwt_secret,
-            name,
-            time,
-        )?;
+        let user_id = context.user_id(token).await?;
+        let created_integration = context
+            .run(move |pool| services::user::create_integration(pool, user_id, name, time))
+            .await?;
         Ok(created_integration.to_graphql())
     }
 
-    async fn delete_integration(
+    async fn start_nubank_integration(
         context: &Context,
-        token: String,
+        token: Option<String>,
+        cpf: String,
+        password: String,
+    ) -> FieldResult<Integration> {
+        let user_id = context.user_id(token).await?;
+        let integration = services::nubank::start_nubank_integration(
+            &context.pool,
+            &context.nubank,
+            &user_id,
+            &context.integration_encryption_key,
+            Utc::now().naive_utc(),
+            cpf,
+            password,
+        )
+        .await
+        .map_err(field_error)?;
+        Ok(integration.to_graphql())
+    }
+
+    // The credentials are asked for again since they are never stored.
+    async fn confirm_nubank_integration(
+        context: &Context,
+        token: Option<String>,
         id: Uuid,
+        cpf: String,
+        password: String,
+        code: String,
     ) -> FieldResult<Integration> {
-        let integration = services::user::auth_and_delete_integration(
+        let user_id = context.user_id(token).await?;
+        let integration = services::nubank::confirm_nubank_integration(
             &context.pool,
-            &token,
-            &context.jwt_secret,
-            id,
-        )?;
+            &context.nubank,
+            &user_id,
+            &context.integration_encryption_key,
+            &id,
+            cpf,
+            password,
+            code,
+        )
+        .await
+        .map_err(field_error)?;
+        Ok(integration.to_graphql())
+    }
 
+    async fn link_integration_account(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+        account_id: Option<Uuid>,
+    ) -> FieldResult<Integration> {
+        let user_id = context.user_id(token).await?;
+        let integration = context
+            .run(move |pool| {
+                services::nubank::link_integration_account(pool, &user_id, &id, account_id)
+            })
+            .await?;
         Ok(integration.to_graphql())
     }
+
+    async fn sync_integration(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+    ) -> FieldResult<SyncReport> {
+        let user_id = context.user_id(token).await?;
+        let report = services::nubank::sync_integration(
+            &context.pool,
+            &context.nubank,
+            &context.events,
+            &user_id,
+            &context.integration_encryption_key,
+            &id,
+        )
+        .await
+        .map_err(field_error)?;
+        Ok(report.to_graphql())
+    }
+
+    async fn delete_integration(
+        context: &Context,
+        token: Option<String>,
+        id: Uuid,
+    ) -> FieldResult<Integration> {
+        let user_id = context.user_id(token).await?;
+        let integration = context
+            .run(move |pool| services::user::delete_integration(pool, user_id, id))
+            .await?;
+
+        Ok(integration.to_graphql())
+    }
+}
+
+type EventStream<T> = Pin<Box<dyn Stream<Item = FieldResult<T>> + Send>>;
+
+// Served over WebSocket. Each stream only carries the events of the user the
+// connection was authenticated as, from the moment it starts.
+pub struct Subscription;
+
+#[graphql_subscription(context = Context)]
+impl Subscription {
+    // Created from any device, posted by earnings and refills, or imported
+    // by a bank sync.
+    async fn transaction_created(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<EventStream<Transaction>> {
+        context
+            .subscribe(token, |event| match event {
+                Event::TransactionCreated(transaction) => Some(transaction.to_graphql()),
+                _ => None,
+            })
+            .await
+    }
+
+    // Sent whenever an account or its balance changes.
+    async fn account_updated(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<EventStream<Account>> {
+        context
+            .subscribe(token, |event| match event {
+                Event::AccountUpdated(account) => Some(account.to_graphql()),
+                _ => None,
+            })
+            .await
+    }
+
+    async fn integration_sync_progress(
+        context: &Context,
+        token: Option<String>,
+    ) -> FieldResult<EventStream<IntegrationSyncProgress>> {
+        context
+            .subscribe(token, |event| match event {
+                Event::IntegrationSyncProgress(progress) => Some(progress.to_graphql()),
+                _ => None,
+            })
+            .await
+    }
 }
 
-pub type Schema = juniper::RootNode<'static, Query, Mutations, EmptySubscription<Context>>;
+pub type Schema = juniper::RootNode<'static, Query, Mutations, Subscription>;
+
+pub fn schema() -> Schema {
+    Schema::new(Query, Mutations, Subscription)
+}
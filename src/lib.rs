@@ -1,9 +1,16 @@
+pub mod auth;
 pub mod database;
+pub mod encryption;
 pub mod entities;
+pub mod events;
+pub mod graphql_errors;
+pub mod graphql_loaders;
 pub mod graphql_resolvers;
 pub mod jwt;
 pub mod models;
+pub mod nubank;
 pub mod schema;
 pub mod sendemail;
 pub mod services;
+pub mod subscriptions;
 pub mod utils;
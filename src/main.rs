@@ -1,21 +1,36 @@
-use juniper::EmptySubscription;
-use rocket::{response::content, State};
+use rocket::{
+    http::{Header, Status},
+    response::content,
+    State,
+};
 
+use chrono::Utc;
 use dotenvy::dotenv;
-use env_logger;
+use std::collections::HashMap;
 use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[macro_use]
 extern crate rocket;
 
+mod auth;
 mod database;
+mod encryption;
 mod entities;
+mod events;
+mod graphql_errors;
+mod graphql_loaders;
 mod graphql_resolvers;
 mod jwt;
 mod models;
+mod nubank;
 mod schema;
 mod sendemail;
 mod services;
+mod subscriptions;
 mod utils;
 
 #[get("/")]
@@ -26,19 +41,256 @@ fn graphiql() -> content::RawHtml<String> {
 #[rocket::get("/graphql?<request>")]
 async fn get_graphql_handler(
     context: &State<graphql_resolvers::Context>,
+    token: auth::BearerToken,
     request: juniper_rocket::GraphQLRequest,
     schema: &State<graphql_resolvers::Schema>,
 ) -> juniper_rocket::GraphQLResponse {
-    request.execute(&*schema, &*context).await
+    let context = context.for_request(token.0).await;
+    request.execute(schema, &context).await
 }
 
 #[rocket::post("/graphql", data = "<request>")]
 async fn post_graphql_handler(
     context: &State<graphql_resolvers::Context>,
+    token: auth::BearerToken,
     request: juniper_rocket::GraphQLRequest,
     schema: &State<graphql_resolvers::Schema>,
 ) -> juniper_rocket::GraphQLResponse {
-    request.execute(&*schema, &*context).await
+    let context = context.for_request(token.0).await;
+    request.execute(schema, &context).await
+}
+
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+struct ExportResponse(Vec<u8>, Header<'static>);
+
+// Links to it are handed out by the `exportMyData` mutation.
+#[rocket::get("/export/<user_id>?<expires>&<signature>")]
+async fn export_handler(
+    context: &State<graphql_resolvers::Context>,
+    user_id: &str,
+    expires: i64,
+    signature: &str,
+) -> Result<ExportResponse, Status> {
+    let user_id = uuid::Uuid::parse_str(user_id).map_err(|_| Status::NotFound)?;
+    let pool = context.pool.clone();
+    let secret = context.jwt_secret.clone();
+    let signature = signature.to_string();
+    let exported = tokio::task::spawn_blocking(move || {
+        services::export::download_export(
+            &pool,
+            &user_id,
+            expires,
+            &signature,
+            &secret,
+            Utc::now().naive_utc(),
+        )
+    })
+    .await;
+    match exported {
+        Ok(Ok(archive)) => Ok(ExportResponse(
+            archive.content,
+            Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", archive.file_name),
+            ),
+        )),
+        Ok(Err(
+            services::export::ExportServiceError::InvalidSignature
+            | services::export::ExportServiceError::ExpiredLink,
+        )) => Err(Status::Forbidden),
+        Ok(Err(services::export::ExportServiceError::UserModelFailed(
+            entities::user::UserModelError::UserDoesNotExists,
+        ))) => Err(Status::NotFound),
+        Ok(Err(err)) => {
+            log::error!("Failed to export user data: {}", err);
+            Err(Status::InternalServerError)
+        }
+        Err(err) => {
+            log::error!("Export task panicked: {}", err);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+// Envelopes are refilled as soon as a new cycle starts, so the check runs
+// every hour instead of once a day.
+const ENVELOPE_REFILL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn spawn_envelope_refills(pool: database::DbPool, events: events::EventBus) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ENVELOPE_REFILL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pool = pool.clone();
+            let refilled = tokio::task::spawn_blocking(move || {
+                services::budget::refill_all_envelopes(&pool, Utc::now().naive_utc().date())
+                    .map(|posted| {
+                        let accounts = accounts_moved_by(&pool, &posted);
+                        (posted, accounts)
+                    })
+            })
+            .await;
+            match refilled {
+                Ok(Ok((posted, accounts))) => {
+                    if !posted.is_empty() {
+                        log::info!("Posted {} envelope refills", posted.len());
+                    }
+                    publish_posted(&events, posted, accounts);
+                }
+                Ok(Err(err)) => log::error!("Failed to refill envelopes: {}", err),
+                Err(err) => log::error!("Envelope refill task panicked: {}", err),
+            }
+        }
+    });
+}
+
+// Loads the accounts that the posted transactions moved, grouped by user, so
+// they can be published with them.
+fn accounts_moved_by(
+    pool: &database::DbPool,
+    transactions: &[entities::transaction::TransactionWithNames],
+) -> Vec<(uuid::Uuid, entities::account::Account)> {
+    use entities::account::AccountModel;
+
+    let mut moved: HashMap<uuid::Uuid, Vec<uuid::Uuid>> = HashMap::new();
+    for t in transactions {
+        let ids = moved.entry(t.related_user).or_default();
+        ids.extend(t.entry_account_code);
+        ids.extend(t.exit_account_code);
+    }
+    let mut accounts = Vec::new();
+    for (user_id, ids) in moved {
+        match pool.get_accounts(&user_id) {
+            Ok(all) => accounts.extend(
+                all.into_iter()
+                    .filter(|account| ids.contains(&account.id))
+                    .map(|account| (user_id, account)),
+            ),
+            Err(err) => log::error!("Failed to load the accounts of {}: {:?}", user_id, err),
+        }
+    }
+    accounts
+}
+
+fn publish_posted(
+    events: &events::EventBus,
+    transactions: Vec<entities::transaction::TransactionWithNames>,
+    accounts: Vec<(uuid::Uuid, entities::account::Account)>,
+) {
+    for transaction in transactions {
+        events.publish(
+            transaction.related_user,
+            events::Event::TransactionCreated(transaction),
+        );
+    }
+    for (user_id, account) in accounts {
+        events.publish(user_id, events::Event::AccountUpdated(account));
+    }
+}
+
+// New days and newly published index rates are paid within the hour.
+const EARNING_ACCRUAL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn spawn_earning_accruals(pool: database::DbPool, events: events::EventBus) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EARNING_ACCRUAL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pool = pool.clone();
+            let accrued = tokio::task::spawn_blocking(move || {
+                services::earning::accrue_all_earnings(&pool, Utc::now().naive_utc().date())
+                    .map(|posted| {
+                        let accounts = accounts_moved_by(&pool, &posted);
+                        (posted, accounts)
+                    })
+            })
+            .await;
+            match accrued {
+                Ok(Ok((posted, accounts))) => {
+                    if !posted.is_empty() {
+                        log::info!("Posted {} earnings", posted.len());
+                    }
+                    publish_posted(&events, posted, accounts);
+                }
+                Ok(Err(err)) => log::error!("Failed to accrue earnings: {}", err),
+                Err(err) => log::error!("Earning accrual task panicked: {}", err),
+            }
+        }
+    });
+}
+
+// SMTP when SMTP_URL is set, `.eml` files in MAIL_OUTBOX_DIR otherwise.
+// Outside of PROD, emails can also just be logged. Every TEST login code is
+// the same known one, so TEST must never deliver them to anyone.
+fn make_mailer(env: entities::Env) -> Arc<dyn sendemail::Mailer> {
+    let from = || env::var("MAIL_FROM").expect("MAIL_FROM must be set to send emails");
+    if let entities::Env::TEST = env {
+        if env::var("SMTP_URL").is_ok() || env::var("MAIL_OUTBOX_DIR").is_ok() {
+            panic!("SMTP_URL and MAIL_OUTBOX_DIR must not be set when ENV is TEST");
+        }
+    }
+    if let Ok(url) = env::var("SMTP_URL") {
+        Arc::new(sendemail::SmtpMailer::new(&url, &from()).expect("SMTP_URL must be valid"))
+    } else if let Ok(dir) = env::var("MAIL_OUTBOX_DIR") {
+        Arc::new(
+            sendemail::OutboxMailer::new(PathBuf::from(dir), &from())
+                .expect("MAIL_FROM must be a valid address"),
+        )
+    } else if let entities::Env::PROD = env {
+        panic!("SMTP_URL or MAIL_OUTBOX_DIR must be set");
+    } else {
+        log::warn!("No mailer configured, emails will only be logged at debug level");
+        Arc::new(sendemail::LogMailer)
+    }
+}
+
+// Users whose deletion grace period is over.
+const USER_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn spawn_user_purge(pool: database::DbPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(USER_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pool = pool.clone();
+            let purged = tokio::task::spawn_blocking(move || {
+                services::user::purge_scheduled_deletions(&pool, Utc::now().naive_utc())
+            })
+            .await;
+            match purged {
+                Ok(Ok(count)) if count > 0 => log::info!("Deleted {} users", count),
+                Ok(Ok(_)) => (),
+                Ok(Err(err)) => log::error!("Failed to delete scheduled users: {}", err),
+                Err(err) => log::error!("User purge task panicked: {}", err),
+            }
+        }
+    });
+}
+
+// Pool settings that are not set keep the defaults of `database::PoolConfig`.
+fn pool_config() -> database::PoolConfig {
+    let number = |name: &str| {
+        env::var(name).ok().map(|value| {
+            value
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("{} must be a number", name))
+        })
+    };
+    let defaults = database::PoolConfig::default();
+    database::PoolConfig {
+        max_size: number("DB_POOL_SIZE").map_or(defaults.max_size, |size| {
+            u32::try_from(size)
+                .ok()
+                .filter(|size| *size > 0)
+                .unwrap_or_else(|| panic!("DB_POOL_SIZE must be between 1 and {}", u32::MAX))
+        }),
+        connection_timeout: number("DB_CONNECTION_TIMEOUT_SECS")
+            .map_or(defaults.connection_timeout, Duration::from_secs),
+        statement_timeout: number("DB_STATEMENT_TIMEOUT_MS")
+            .map(Duration::from_millis)
+            .or(defaults.statement_timeout),
+    }
 }
 
 #[launch]
@@ -48,33 +300,95 @@ async fn rocket() -> _ {
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    let integration_encryption_key = env::var("INTEGRATION_ENCRYPTION_KEY")
+        .expect("INTEGRATION_ENCRYPTION_KEY must be set");
     let api_port = env::var("API_PORT").expect("API_PORT must be set").parse::<u16>().expect("API_PORT must be a number");
+    // Subscriptions are served on a port of their own, next to the API one
+    // unless WS_PORT says otherwise.
+    let ws_port = env::var("WS_PORT").map_or_else(
+        |_| {
+            api_port
+                .checked_add(1)
+                .expect("WS_PORT must be set when API_PORT is the last port")
+        },
+        |port| port.parse::<u16>().expect("WS_PORT must be a number"),
+    );
 
     let env = match env::var("ENV").expect("ENV must be set").as_str() {
         "DEV" => entities::Env::DEV,
+        "TEST" => entities::Env::TEST,
         "PROD" => entities::Env::PROD,
-        x => panic!("ENV must be DEV or PROD but is {}", x),
+        x => panic!("ENV must be DEV, TEST or PROD but is {}", x),
     };
 
-    let pool = database::establish_pooled_connection(database_url);
+    // The magic code logs into any account, so it only works in DEV.
+    let magic_login_code = match (env, env::var("MAGIC_LOGIN_CODE")) {
+        (entities::Env::DEV, Ok(code)) => Some(
+            code.parse::<i32>()
+                .expect("MAGIC_LOGIN_CODE must be a number"),
+        ),
+        (_, Ok(_)) => {
+            log::warn!("Ignoring MAGIC_LOGIN_CODE, it only works when ENV is DEV");
+            None
+        }
+        (_, Err(_)) => None,
+    };
+
+    let mailer = make_mailer(env);
+
+    let nubank_discovery_url = env::var("NUBANK_DISCOVERY_URL")
+        .unwrap_or_else(|_| nubank::DEFAULT_DISCOVERY_URL.to_string());
+
+    let pool = database::establish_pooled_connection(database_url, &pool_config());
+
+    if let Ok(path) = env::var("INDEX_RATES_FILE") {
+        let loaded = services::earning::load_index_rates_file(&pool, &path)
+            .expect("INDEX_RATES_FILE must contain valid index rates");
+        log::info!("Loaded {} index rates from {}", loaded, path);
+    }
+
+    let events = events::EventBus::default();
+
+    spawn_envelope_refills(pool.clone(), events.clone());
+    spawn_earning_accruals(pool.clone(), events.clone());
+    spawn_user_purge(pool.clone());
 
     let figment = rocket::Config::figment()
         .merge(("port", api_port))
         .merge(("address", "0.0.0.0"));
+    // Subscriptions listen on the same address as the API.
+    let address: IpAddr = figment
+        .extract_inner("address")
+        .expect("The Rocket address must be an IP address");
 
     let context = graphql_resolvers::Context {
         pool,
         jwt_secret,
+        integration_encryption_key,
         env,
+        magic_login_code,
+        mailer,
+        nubank: nubank::NubankTransport::new(nubank_discovery_url)
+            .expect("Failed to build the Nubank HTTP client"),
+        events,
+        auth: auth::Authentication::Anonymous,
+        account_names: None,
     };
 
-    let schema = graphql_resolvers::Schema::new(
-        graphql_resolvers::Query,
-        graphql_resolvers::Mutations,
-        EmptySubscription::<graphql_resolvers::Context>::new(),
+    subscriptions::spawn_subscription_server(
+        SocketAddr::new(address, ws_port),
+        context.clone(),
+        Arc::new(graphql_resolvers::schema()),
     );
 
-    let routes = rocket::routes![graphiql, get_graphql_handler, post_graphql_handler];
+    let schema = graphql_resolvers::schema();
+
+    let routes = rocket::routes![
+        graphiql,
+        get_graphql_handler,
+        post_graphql_handler,
+        export_handler
+    ];
 
     rocket::custom(figment)
         .manage(context)
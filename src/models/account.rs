@@ -1,14 +1,24 @@
-use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use diesel_derive_enum;
 use log;
 use uuid::Uuid;
 
-use crate::{database, entities::account, schema::accounts as account_schema};
+use crate::{
+    database,
+    entities::{account, money::Money, transaction},
+    schema::{
+        accounts as account_schema, transactions as transaction_schema, users as user_schema,
+    },
+};
 
 #[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy)]
 #[DieselTypePath = "crate::schema::sql_types::EarningIndexEnum"]
-enum EarningIndexEnum {
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) enum EarningIndexEnum {
     CDI,
     FIXED,
     IPCA,
@@ -18,19 +28,24 @@ enum EarningIndexEnum {
 #[diesel(table_name = account_schema)]
 struct Account {
     id: Uuid,
+    #[allow(dead_code)]
     related_user: Uuid,
     time: NaiveDateTime,
     name: String,
     description: Option<String>,
-    last_calculated_balance: f64,
+    last_calculated_balance: i64,
     is_pre_allocation: bool,
-    pre_allocation_amount: Option<f64>,
+    pre_allocation_amount: Option<i64>,
     pre_allocation_accumulative: Option<bool>,
     is_earning: bool,
-    earning_rate: Option<f64>,
+    earning_rate: Option<i32>,
     earning_index: Option<EarningIndexEnum>,
     is_available: bool,
     in_trash: bool,
+    initial_balance: i64,
+    last_accrual_date: Option<NaiveDate>,
+    pre_allocation_source: Option<Uuid>,
+    last_refill_date: Option<NaiveDate>,
 }
 
 #[derive(Insertable, Clone, Debug)]
@@ -40,15 +55,17 @@ struct NewAccount {
     time: NaiveDateTime,
     name: String,
     description: Option<String>,
-    last_calculated_balance: f64,
+    last_calculated_balance: i64,
     is_pre_allocation: bool,
-    pre_allocation_amount: Option<f64>,
+    pre_allocation_amount: Option<i64>,
     pre_allocation_accumulative: Option<bool>,
     is_earning: bool,
-    earning_rate: Option<f64>,
+    earning_rate: Option<i32>,
     earning_index: Option<EarningIndexEnum>,
     is_available: bool,
     in_trash: bool,
+    initial_balance: i64,
+    pre_allocation_source: Option<Uuid>,
 }
 
 #[derive(AsChangeset)]
@@ -57,17 +74,18 @@ struct UpdatedAccount {
     name: Option<String>,
     description: Option<String>,
     is_pre_allocation: Option<bool>,
-    pre_allocation_amount: Option<f64>,
+    pre_allocation_amount: Option<i64>,
     pre_allocation_accumulative: Option<bool>,
+    pre_allocation_source: Option<Uuid>,
     is_earning: Option<bool>,
-    earning_rate: Option<f64>,
+    earning_rate: Option<i32>,
     earning_index: Option<EarningIndexEnum>,
     is_available: Option<bool>,
     in_trash: Option<bool>,
 }
 
 impl account::EarningIndex {
-    fn to_model(&self) -> EarningIndexEnum {
+    pub(crate) fn to_model(self) -> EarningIndexEnum {
         match self {
             account::EarningIndex::CDI => EarningIndexEnum::CDI,
             account::EarningIndex::FIXED => EarningIndexEnum::FIXED,
@@ -77,7 +95,7 @@ impl account::EarningIndex {
 }
 
 impl EarningIndexEnum {
-    fn to_entity(&self) -> account::EarningIndex {
+    pub(crate) fn to_entity(self) -> account::EarningIndex {
         match self {
             EarningIndexEnum::CDI => account::EarningIndex::CDI,
             EarningIndexEnum::FIXED => account::EarningIndex::FIXED,
@@ -93,46 +111,53 @@ impl account::NewAccount {
             time: self.time,
             name: self.name.clone(),
             description: self.description.clone(),
-            last_calculated_balance: 0.0,
-            is_pre_allocation: match self.pre_allocation {
-                Some(_) => true,
-                None => false,
-            },
-            pre_allocation_amount: self.pre_allocation.map(|x| x.amount),
+            last_calculated_balance: self.initial_balance.cents(),
+            is_pre_allocation: self.pre_allocation.is_some(),
+            pre_allocation_amount: self.pre_allocation.map(|x| x.amount.cents()),
             pre_allocation_accumulative: self.pre_allocation.map(|x| x.accumulative),
-            is_earning: match self.earning {
-                Some(_) => true,
-                None => false,
-            },
-            earning_rate: self.earning.map(|x| x.rate),
+            is_earning: self.earning.is_some(),
+            earning_rate: self.earning.map(|x| to_basis_points(x.rate)),
             earning_index: self.earning.map(|x| x.index.to_model()),
             is_available: self.is_available,
             in_trash: false,
+            initial_balance: self.initial_balance.cents(),
+            pre_allocation_source: self.pre_allocation.and_then(|x| x.source),
         }
     }
 }
 
 fn pre_allocation_from_table_fields(
     is_pre_allocation: bool,
-    amount: Option<f64>,
+    amount: Option<i64>,
     accumulative: Option<bool>,
+    source: Option<Uuid>,
 ) -> Option<account::PreAllocation> {
     match (is_pre_allocation, amount, accumulative) {
         (true, Some(amount), Some(accumulative)) => Some(account::PreAllocation {
-            amount,
+            amount: Money::from_cents(amount),
             accumulative,
+            source,
         }),
         _ => None,
     }
 }
 
+// Rates are stored as whole basis points, so they are rounded to the
+// hundredth of a percent.
+fn to_basis_points(rate: f64) -> i32 {
+    (rate * 100.0).round() as i32
+}
+
 fn earning_from_table_fields(
     is_earning: bool,
-    rate: Option<f64>,
+    rate: Option<i32>,
     index: Option<account::EarningIndex>,
 ) -> Option<account::Earning> {
     match (is_earning, rate, index) {
-        (true, Some(rate), Some(index)) => Some(account::Earning { rate, index }),
+        (true, Some(rate), Some(index)) => Some(account::Earning {
+            rate: f64::from(rate) / 100.0,
+            index,
+        }),
         _ => None,
     }
 }
@@ -143,6 +168,7 @@ impl Account {
             self.is_pre_allocation,
             self.pre_allocation_amount,
             self.pre_allocation_accumulative,
+            self.pre_allocation_source,
         );
 
         let earning = earning_from_table_fields(
@@ -156,11 +182,13 @@ impl Account {
             time: self.time,
             name: self.name.clone(),
             description: self.description.clone(),
-            balance: self.last_calculated_balance,
+            balance: Money::from_cents(self.last_calculated_balance),
             pre_allocation,
             earning,
             is_available: self.is_available,
             in_trash: self.in_trash,
+            last_accrual_date: self.last_accrual_date,
+            last_refill_date: self.last_refill_date,
         }
     }
 }
@@ -170,17 +198,12 @@ impl account::UpdatedAccount {
         UpdatedAccount {
             name: self.name.clone(),
             description: self.description.clone(),
-            is_pre_allocation: match self.pre_allocation {
-                Some(_) => Some(true),
-                None => None,
-            },
-            pre_allocation_amount: self.pre_allocation.map(|x| x.amount),
+            is_pre_allocation: self.pre_allocation.map(|_| true),
+            pre_allocation_amount: self.pre_allocation.map(|x| x.amount.cents()),
             pre_allocation_accumulative: self.pre_allocation.map(|x| x.accumulative),
-            is_earning: match self.earning {
-                Some(_) => Some(true),
-                None => None,
-            },
-            earning_rate: self.earning.map(|x| x.rate),
+            pre_allocation_source: self.pre_allocation.and_then(|x| x.source),
+            is_earning: self.earning.map(|_| true),
+            earning_rate: self.earning.map(|x| to_basis_points(x.rate)),
             earning_index: self.earning.map(|x| x.index.to_model()),
             is_available: self.is_available,
             in_trash: self.in_trash,
@@ -188,6 +211,44 @@ impl account::UpdatedAccount {
     }
 }
 
+// Adds each delta to the balance of its account, failing with `NotFound` when
+// the account does not exist or belongs to someone else.
+pub(crate) fn apply_balance_effects(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    effects: &[(Uuid, Money)],
+) -> QueryResult<()> {
+    for (account_id, delta) in effects {
+        let updated = diesel::update(
+            account_schema::table
+                .filter(account_schema::id.eq(account_id))
+                .filter(account_schema::related_user.eq(user_id)),
+        )
+        .set(
+            account_schema::last_calculated_balance
+                .eq(account_schema::last_calculated_balance + delta.cents()),
+        )
+        .execute(conn)?;
+
+        if updated == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+    }
+    Ok(())
+}
+
+fn map_account_error(
+    error: DieselError,
+    otherwise: fn(DieselError) -> account::AccountModelError,
+) -> account::AccountModelError {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            account::AccountModelError::NameAlreadyTaken
+        }
+        error => otherwise(error),
+    }
+}
+
 impl account::AccountModel for database::DbPool {
     fn create_account(
         &self,
@@ -200,7 +261,9 @@ impl account::AccountModel for database::DbPool {
             .values(&parsed_account)
             .get_result::<Account>(&mut self.get()?)
             .map(|t| t.to_entity())
-            .map_err(account::AccountModelError::FailedToCreateAccount)
+            .map_err(|err| {
+                map_account_error(err, account::AccountModelError::FailedToCreateAccount)
+            })
     }
     fn get_account(&self, id: &Uuid, user_id: &Uuid) -> account::Result<account::Account> {
         let accounts = account_schema::table
@@ -215,6 +278,19 @@ impl account::AccountModel for database::DbPool {
             _ => Err(account::AccountModelError::MultipleAccountWithSameId),
         }
     }
+    fn get_account_names(
+        &self,
+        ids: &[Uuid],
+        user_id: &Uuid,
+    ) -> account::Result<HashMap<Uuid, String>> {
+        let names = account_schema::table
+            .filter(account_schema::related_user.eq(user_id))
+            .filter(account_schema::id.eq_any(ids))
+            .select((account_schema::id, account_schema::name))
+            .load::<(Uuid, String)>(&mut self.get()?)
+            .map_err(account::AccountModelError::FailedToGetAccount)?;
+        Ok(names.into_iter().collect())
+    }
     fn get_accounts(&self, user_id: &Uuid) -> account::Result<Vec<account::Account>> {
         let accounts = account_schema::table
             .filter(account_schema::related_user.eq(user_id))
@@ -257,7 +333,77 @@ impl account::AccountModel for database::DbPool {
         )
         .set(updated_account.to_model())
         .get_result::<Account>(&mut self.get()?)
-        .map_err(account::AccountModelError::FailedToUpdateAccount)?;
+        .map_err(|err| map_account_error(err, account::AccountModelError::FailedToUpdateAccount))?;
         Ok(account.to_entity())
     }
+
+    fn recalculate_balances(&self, user_id: &Uuid) -> account::Result<Vec<account::Account>> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, account::AccountModelError, _>(|conn| {
+            let accounts = account_schema::table
+                .filter(account_schema::related_user.eq(user_id))
+                .load::<Account>(conn)
+                .map_err(account::AccountModelError::FailedToGetAccount)?;
+
+            let legs = transaction_schema::table
+                .filter(transaction_schema::related_user.eq(user_id))
+                .filter(transaction_schema::voided.eq(false))
+                .select((
+                    transaction_schema::entry_account_code,
+                    transaction_schema::exit_account_code,
+                    transaction_schema::amount,
+                ))
+                .load::<(Option<Uuid>, Option<Uuid>, i64)>(conn)
+                .map_err(account::AccountModelError::FailedToRecalculateBalances)?;
+
+            let mut balances: HashMap<Uuid, Money> = accounts
+                .iter()
+                .map(|acc| (acc.id, Money::from_cents(acc.initial_balance)))
+                .collect();
+            for (entry, exit, amount) in legs {
+                let amount = Money::from_cents(amount);
+                for (account_id, delta) in transaction::balance_effects(entry, exit, amount) {
+                    if let Some(balance) = balances.get_mut(&account_id) {
+                        *balance += delta;
+                    }
+                }
+            }
+
+            accounts
+                .iter()
+                .map(|acc| {
+                    diesel::update(account_schema::table.filter(account_schema::id.eq(acc.id)))
+                        .set(account_schema::last_calculated_balance.eq(balances[&acc.id].cents()))
+                        .get_result::<Account>(conn)
+                        .map(|t| t.to_entity())
+                        .map_err(account::AccountModelError::FailedToRecalculateBalances)
+                })
+                .collect()
+        })
+    }
+
+    // Accounts left behind by deleted users are skipped, there is nobody to
+    // refill them for.
+    fn list_users_with_envelopes(&self) -> account::Result<Vec<Uuid>> {
+        account_schema::table
+            .filter(account_schema::related_user.eq_any(user_schema::table.select(user_schema::id)))
+            .filter(account_schema::is_pre_allocation.eq(true))
+            .filter(account_schema::in_trash.eq(false))
+            .filter(account_schema::pre_allocation_source.is_not_null())
+            .select(account_schema::related_user)
+            .distinct()
+            .load::<Uuid>(&mut self.get()?)
+            .map_err(account::AccountModelError::FailedToGetAccount)
+    }
+
+    fn list_users_with_earnings(&self) -> account::Result<Vec<Uuid>> {
+        account_schema::table
+            .filter(account_schema::related_user.eq_any(user_schema::table.select(user_schema::id)))
+            .filter(account_schema::is_earning.eq(true))
+            .filter(account_schema::in_trash.eq(false))
+            .select(account_schema::related_user)
+            .distinct()
+            .load::<Uuid>(&mut self.get()?)
+            .map_err(account::AccountModelError::FailedToGetAccount)
+    }
 }
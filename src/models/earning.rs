@@ -0,0 +1,65 @@
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+
+use crate::{
+    database,
+    entities::{account, earning},
+    models::account::EarningIndexEnum,
+    schema::index_rates as index_rate_schema,
+};
+
+#[derive(Queryable, Insertable, Clone)]
+#[diesel(table_name = index_rate_schema)]
+struct IndexRate {
+    earning_index: EarningIndexEnum,
+    date: NaiveDate,
+    rate: f64,
+}
+
+impl earning::IndexRate {
+    fn to_model(self) -> IndexRate {
+        IndexRate {
+            earning_index: self.index.to_model(),
+            date: self.date,
+            rate: self.rate,
+        }
+    }
+}
+
+impl IndexRate {
+    fn to_entity(&self) -> earning::IndexRate {
+        earning::IndexRate {
+            index: self.earning_index.to_entity(),
+            date: self.date,
+            rate: self.rate,
+        }
+    }
+}
+
+impl earning::IndexRateModel for database::DbPool {
+    fn save_index_rates(&self, rates: Vec<earning::IndexRate>) -> earning::Result<usize> {
+        let rates: Vec<IndexRate> = rates.iter().map(|r| r.to_model()).collect();
+        diesel::insert_into(index_rate_schema::table)
+            .values(&rates)
+            .on_conflict((index_rate_schema::earning_index, index_rate_schema::date))
+            .do_update()
+            .set(index_rate_schema::rate.eq(excluded(index_rate_schema::rate)))
+            .execute(&mut self.get()?)
+            .map_err(earning::IndexRateModelError::FailedToSaveIndexRates)
+    }
+
+    fn list_index_rates(
+        &self,
+        index: account::EarningIndex,
+    ) -> earning::Result<Vec<earning::IndexRate>> {
+        Ok(index_rate_schema::table
+            .filter(index_rate_schema::earning_index.eq(index.to_model()))
+            .order(index_rate_schema::date.asc())
+            .load::<IndexRate>(&mut self.get()?)
+            .map_err(earning::IndexRateModelError::FailedToListIndexRates)?
+            .iter()
+            .map(|r| r.to_entity())
+            .collect())
+    }
+}
@@ -1,11 +1,21 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel_derive_enum;
 use uuid::Uuid;
 
 use crate::{
     database, entities::integration, schema::user_integrations as user_integration_schema,
 };
 
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy)]
+#[DieselTypePath = "crate::schema::sql_types::IntegrationStatusEnum"]
+#[allow(clippy::upper_case_acronyms)]
+enum IntegrationStatusEnum {
+    CREATED,
+    PENDING,
+    CONNECTED,
+}
+
 #[derive(Queryable, Clone)]
 #[diesel(table_name = user_integration_schema)]
 struct UserIntegration {
@@ -13,6 +23,10 @@ struct UserIntegration {
     related_user: Uuid,
     name: String,
     time: NaiveDateTime,
+    status: IntegrationStatusEnum,
+    sent_to: Option<String>,
+    state: Option<Vec<u8>>,
+    account_id: Option<Uuid>,
 }
 
 #[derive(Insertable, Clone)]
@@ -21,14 +35,58 @@ struct NewUserIntegration {
     related_user: Uuid,
     name: String,
     time: NaiveDateTime,
+    status: IntegrationStatusEnum,
+    sent_to: Option<String>,
+    state: Option<Vec<u8>>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = user_integration_schema, treat_none_as_null = true)]
+struct UpdatedIntegrationState {
+    status: IntegrationStatusEnum,
+    sent_to: Option<String>,
+    state: Option<Vec<u8>>,
+}
+
+impl integration::IntegrationStatus {
+    fn to_model(self) -> IntegrationStatusEnum {
+        match self {
+            integration::IntegrationStatus::CREATED => IntegrationStatusEnum::CREATED,
+            integration::IntegrationStatus::PENDING => IntegrationStatusEnum::PENDING,
+            integration::IntegrationStatus::CONNECTED => IntegrationStatusEnum::CONNECTED,
+        }
+    }
+}
+
+impl IntegrationStatusEnum {
+    fn to_entity(self) -> integration::IntegrationStatus {
+        match self {
+            IntegrationStatusEnum::CREATED => integration::IntegrationStatus::CREATED,
+            IntegrationStatusEnum::PENDING => integration::IntegrationStatus::PENDING,
+            IntegrationStatusEnum::CONNECTED => integration::IntegrationStatus::CONNECTED,
+        }
+    }
 }
 
 impl integration::NewUserIntegration {
     fn to_model(&self) -> NewUserIntegration {
         NewUserIntegration {
-            related_user: self.related_user.clone(),
+            related_user: self.related_user,
             name: self.name.clone(),
             time: self.time,
+            status: self.status.to_model(),
+            sent_to: self.sent_to.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl integration::UpdatedIntegrationState {
+    fn to_model(&self) -> UpdatedIntegrationState {
+        UpdatedIntegrationState {
+            status: self.status.to_model(),
+            sent_to: self.sent_to.clone(),
+            state: self.state.clone(),
         }
     }
 }
@@ -36,10 +94,14 @@ impl integration::NewUserIntegration {
 impl UserIntegration {
     fn to_entity(&self) -> integration::UserIntegration {
         integration::UserIntegration {
-            id: self.id.clone(),
-            related_user: self.related_user.clone(),
+            id: self.id,
+            related_user: self.related_user,
             name: self.name.clone(),
             time: self.time,
+            status: self.status.to_entity(),
+            sent_to: self.sent_to.clone(),
+            state: self.state.clone(),
+            account_id: self.account_id,
         }
     }
 }
@@ -56,32 +118,78 @@ impl integration::IntegrationModel for database::DbPool {
             .map_err(integration::IntegrationModelError::FailedToCreateIntegration)
     }
 
-    fn list_user_integrations(
+    fn get_integration(
         &self,
+        id: &Uuid,
         user_id: &Uuid,
-    ) -> integration::Result<Vec<integration::UserIntegration>> {
-        Ok(user_integration_schema::table
+    ) -> integration::Result<integration::UserIntegration> {
+        let integrations = user_integration_schema::table
+            .filter(user_integration_schema::id.eq(id))
             .filter(user_integration_schema::related_user.eq(user_id))
             .load::<UserIntegration>(&mut self.get()?)
-            .map_err(integration::IntegrationModelError::FailedToListIntegrations)?
-            .iter()
-            .map(|t| t.to_entity())
-            .collect())
+            .map_err(integration::IntegrationModelError::FailedToGetIntegration)?;
+
+        match integrations.as_slice() {
+            [i] => Ok(i.to_entity()),
+            _ => Err(integration::IntegrationModelError::IntegrationNotFound),
+        }
     }
 
-    fn delete_integration_by_user_id(
+    fn update_integration_state(
         &self,
+        id: &Uuid,
         user_id: &Uuid,
-    ) -> integration::Result<Vec<integration::UserIntegration>> {
-        Ok(diesel::delete(
+        updated_state: integration::UpdatedIntegrationState,
+    ) -> integration::Result<integration::UserIntegration> {
+        diesel::update(
             user_integration_schema::table
+                .filter(user_integration_schema::id.eq(id))
                 .filter(user_integration_schema::related_user.eq(user_id)),
         )
-        .get_results::<UserIntegration>(&mut self.get()?)
-        .map_err(integration::IntegrationModelError::FailedToDeleteIntegration)?
-        .iter()
-        .map(|t| t.to_entity())
-        .collect())
+        .set(updated_state.to_model())
+        .get_result::<UserIntegration>(&mut self.get()?)
+        .map(|i| i.to_entity())
+        .map_err(|err| match err {
+            diesel::result::Error::NotFound => {
+                integration::IntegrationModelError::IntegrationNotFound
+            }
+            err => integration::IntegrationModelError::FailedToUpdateIntegration(err),
+        })
+    }
+
+    fn link_integration_account(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        account_id: Option<Uuid>,
+    ) -> integration::Result<integration::UserIntegration> {
+        diesel::update(
+            user_integration_schema::table
+                .filter(user_integration_schema::id.eq(id))
+                .filter(user_integration_schema::related_user.eq(user_id)),
+        )
+        .set(user_integration_schema::account_id.eq(account_id))
+        .get_result::<UserIntegration>(&mut self.get()?)
+        .map(|i| i.to_entity())
+        .map_err(|err| match err {
+            diesel::result::Error::NotFound => {
+                integration::IntegrationModelError::IntegrationNotFound
+            }
+            err => integration::IntegrationModelError::FailedToUpdateIntegration(err),
+        })
+    }
+
+    fn list_user_integrations(
+        &self,
+        user_id: &Uuid,
+    ) -> integration::Result<Vec<integration::UserIntegration>> {
+        Ok(user_integration_schema::table
+            .filter(user_integration_schema::related_user.eq(user_id))
+            .load::<UserIntegration>(&mut self.get()?)
+            .map_err(integration::IntegrationModelError::FailedToListIntegrations)?
+            .iter()
+            .map(|t| t.to_entity())
+            .collect())
     }
 
     fn delete_integration(&self, id: &Uuid) -> integration::Result<integration::UserIntegration> {
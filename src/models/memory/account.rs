@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::{account, money::Money};
+
+pub(super) struct AccountRow {
+    pub(super) related_user: Uuid,
+    pub(super) initial_balance: Money,
+    pub(super) account: account::Account,
+}
+
+impl Tables {
+    pub(super) fn account_mut(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> Option<&mut account::Account> {
+        self.accounts
+            .iter_mut()
+            .find(|row| row.account.id == *id && row.related_user == *user_id)
+            .map(|row| &mut row.account)
+    }
+
+    fn user_accounts<'a>(&'a self, user_id: &'a Uuid) -> impl Iterator<Item = &'a AccountRow> {
+        self.accounts
+            .iter()
+            .filter(move |row| row.related_user == *user_id)
+    }
+
+    // Accounts of deleted users are skipped, just as the join on users does.
+    fn users_with_accounts<F: Fn(&account::Account) -> bool>(&self, pick: F) -> Vec<Uuid> {
+        let mut users: Vec<Uuid> = Vec::new();
+        for row in &self.accounts {
+            if pick(&row.account)
+                && !row.account.in_trash
+                && self.user_exists(&row.related_user)
+                && !users.contains(&row.related_user)
+            {
+                users.push(row.related_user);
+            }
+        }
+        users
+    }
+
+    // The partial unique index on (related_user, name) of accounts not in
+    // the trash.
+    fn name_taken(&self, user_id: &Uuid, name: &str, except: Option<Uuid>) -> bool {
+        self.user_accounts(user_id).any(|row| {
+            !row.account.in_trash && row.account.name == name && Some(row.account.id) != except
+        })
+    }
+}
+
+impl account::NewAccount {
+    fn to_row(&self, related_user: Uuid) -> AccountRow {
+        AccountRow {
+            related_user,
+            initial_balance: self.initial_balance,
+            account: account::Account {
+                id: Uuid::new_v4(),
+                time: self.time,
+                name: self.name.clone(),
+                description: self.description.clone(),
+                balance: self.initial_balance,
+                pre_allocation: self.pre_allocation,
+                earning: self.earning,
+                is_available: self.is_available,
+                in_trash: false,
+                last_accrual_date: None,
+                last_refill_date: None,
+            },
+        }
+    }
+}
+
+impl account::UpdatedAccount {
+    // Like the changeset, fields left as None keep their value, and so does
+    // the source of a pre-allocation given without one.
+    fn apply_to(&self, account: &account::Account) -> account::Account {
+        account::Account {
+            name: self.name.clone().unwrap_or_else(|| account.name.clone()),
+            description: self
+                .description
+                .clone()
+                .or_else(|| account.description.clone()),
+            pre_allocation: match self.pre_allocation {
+                Some(pre_allocation) => Some(account::PreAllocation {
+                    source: pre_allocation
+                        .source
+                        .or_else(|| account.pre_allocation.and_then(|old| old.source)),
+                    ..pre_allocation
+                }),
+                None => account.pre_allocation,
+            },
+            earning: self.earning.or(account.earning),
+            is_available: self.is_available.unwrap_or(account.is_available),
+            in_trash: self.in_trash.unwrap_or(account.in_trash),
+            ..account.clone()
+        }
+    }
+}
+
+impl account::AccountModel for InMemoryStore {
+    fn create_account(
+        &self,
+        user_id: Uuid,
+        new_account: account::NewAccount,
+    ) -> account::Result<account::Account> {
+        let mut tables = self.tables();
+        if tables.name_taken(&user_id, &new_account.name, None) {
+            return Err(account::AccountModelError::NameAlreadyTaken);
+        }
+        let row = new_account.to_row(user_id);
+        let created = row.account.clone();
+        tables.accounts.push(row);
+        Ok(created)
+    }
+
+    fn get_account(&self, id: &Uuid, user_id: &Uuid) -> account::Result<account::Account> {
+        self.tables()
+            .account_mut(id, user_id)
+            .cloned()
+            .ok_or(account::AccountModelError::AccountNotFound)
+    }
+
+    fn get_accounts(&self, user_id: &Uuid) -> account::Result<Vec<account::Account>> {
+        Ok(self
+            .tables()
+            .user_accounts(user_id)
+            .map(|row| row.account.clone())
+            .collect())
+    }
+
+    fn get_account_names(
+        &self,
+        ids: &[Uuid],
+        user_id: &Uuid,
+    ) -> account::Result<HashMap<Uuid, String>> {
+        Ok(self
+            .tables()
+            .user_accounts(user_id)
+            .filter(|row| ids.contains(&row.account.id))
+            .map(|row| (row.account.id, row.account.name.clone()))
+            .collect())
+    }
+
+    fn delete_account(&self, id: &Uuid, user_id: &Uuid) -> account::Result<()> {
+        match self.edit_account(
+            id,
+            user_id,
+            account::UpdatedAccount {
+                name: None,
+                description: None,
+                pre_allocation: None,
+                earning: None,
+                is_available: None,
+                in_trash: Some(true),
+            },
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(account::AccountModelError::FailedToDeleteAccount(Box::new(
+                err,
+            ))),
+        }
+    }
+
+    fn edit_account(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_account: account::UpdatedAccount,
+    ) -> account::Result<account::Account> {
+        let mut tables = self.tables();
+        let account = tables.account_mut(id, user_id).ok_or(
+            account::AccountModelError::FailedToUpdateAccount(DieselError::NotFound),
+        )?;
+        let edited = updated_account.apply_to(account);
+        if !edited.in_trash && tables.name_taken(user_id, &edited.name, Some(*id)) {
+            return Err(account::AccountModelError::NameAlreadyTaken);
+        }
+        if let Some(account) = tables.account_mut(id, user_id) {
+            *account = edited.clone();
+        }
+        Ok(edited)
+    }
+
+    fn recalculate_balances(&self, user_id: &Uuid) -> account::Result<Vec<account::Account>> {
+        let mut tables = self.tables();
+        let mut balances: HashMap<Uuid, Money> = tables
+            .user_accounts(user_id)
+            .map(|row| (row.account.id, row.initial_balance))
+            .collect();
+        let effects: Vec<(Uuid, Money)> = tables
+            .user_transactions(user_id)
+            .flat_map(|t| t.balance_effects())
+            .collect();
+        for (account_id, delta) in effects {
+            if let Some(balance) = balances.get_mut(&account_id) {
+                *balance += delta;
+            }
+        }
+
+        Ok(tables
+            .accounts
+            .iter_mut()
+            .filter(|row| row.related_user == *user_id)
+            .map(|row| {
+                row.account.balance = balances[&row.account.id];
+                row.account.clone()
+            })
+            .collect())
+    }
+
+    fn list_users_with_envelopes(&self) -> account::Result<Vec<Uuid>> {
+        Ok(self.tables().users_with_accounts(|account| {
+            account
+                .pre_allocation
+                .map_or(false, |pre_allocation| pre_allocation.source.is_some())
+        }))
+    }
+
+    fn list_users_with_earnings(&self) -> account::Result<Vec<Uuid>> {
+        Ok(self
+            .tables()
+            .users_with_accounts(|account| account.earning.is_some()))
+    }
+}
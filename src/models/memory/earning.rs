@@ -0,0 +1,33 @@
+use super::InMemoryStore;
+use crate::entities::{account, earning};
+
+impl earning::IndexRateModel for InMemoryStore {
+    // Like the upsert on (earning_index, date), a rate replaces the one
+    // stored for its day.
+    fn save_index_rates(&self, rates: Vec<earning::IndexRate>) -> earning::Result<usize> {
+        let mut tables = self.tables();
+        let saved = rates.len();
+        for rate in rates {
+            tables
+                .index_rates
+                .retain(|stored| !(stored.index == rate.index && stored.date == rate.date));
+            tables.index_rates.push(rate);
+        }
+        Ok(saved)
+    }
+
+    fn list_index_rates(
+        &self,
+        index: account::EarningIndex,
+    ) -> earning::Result<Vec<earning::IndexRate>> {
+        let mut rates: Vec<earning::IndexRate> = self
+            .tables()
+            .index_rates
+            .iter()
+            .filter(|rate| rate.index == index)
+            .copied()
+            .collect();
+        rates.sort_by_key(|rate| rate.date);
+        Ok(rates)
+    }
+}
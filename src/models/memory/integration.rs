@@ -0,0 +1,99 @@
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::integration;
+
+impl Tables {
+    fn integration_mut(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> integration::Result<&mut integration::UserIntegration> {
+        self.integrations
+            .iter_mut()
+            .find(|i| i.id == *id && i.related_user == *user_id)
+            .ok_or(integration::IntegrationModelError::IntegrationNotFound)
+    }
+}
+
+impl integration::NewUserIntegration {
+    fn to_entity(&self) -> integration::UserIntegration {
+        integration::UserIntegration {
+            id: Uuid::new_v4(),
+            related_user: self.related_user,
+            name: self.name.clone(),
+            time: self.time,
+            status: self.status,
+            sent_to: self.sent_to.clone(),
+            state: self.state.clone(),
+            account_id: None,
+        }
+    }
+}
+
+impl integration::IntegrationModel for InMemoryStore {
+    fn create_integration(
+        &self,
+        t: integration::NewUserIntegration,
+    ) -> integration::Result<integration::UserIntegration> {
+        let created = t.to_entity();
+        self.tables().integrations.push(created.clone());
+        Ok(created)
+    }
+
+    fn get_integration(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> integration::Result<integration::UserIntegration> {
+        Ok(self.tables().integration_mut(id, user_id)?.clone())
+    }
+
+    fn list_user_integrations(
+        &self,
+        user_id: &Uuid,
+    ) -> integration::Result<Vec<integration::UserIntegration>> {
+        Ok(self
+            .tables()
+            .integrations
+            .iter()
+            .filter(|i| i.related_user == *user_id)
+            .cloned()
+            .collect())
+    }
+
+    fn update_integration_state(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_state: integration::UpdatedIntegrationState,
+    ) -> integration::Result<integration::UserIntegration> {
+        let mut tables = self.tables();
+        let integration = tables.integration_mut(id, user_id)?;
+        integration.status = updated_state.status;
+        integration.sent_to = updated_state.sent_to;
+        integration.state = updated_state.state;
+        Ok(integration.clone())
+    }
+
+    fn link_integration_account(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        account_id: Option<Uuid>,
+    ) -> integration::Result<integration::UserIntegration> {
+        let mut tables = self.tables();
+        let integration = tables.integration_mut(id, user_id)?;
+        integration.account_id = account_id;
+        Ok(integration.clone())
+    }
+
+    fn delete_integration(&self, id: &Uuid) -> integration::Result<integration::UserIntegration> {
+        let mut tables = self.tables();
+        let index = tables.integrations.iter().position(|i| i.id == *id).ok_or(
+            integration::IntegrationModelError::FailedToDeleteIntegration(DieselError::NotFound),
+        )?;
+        Ok(tables.integrations.remove(index))
+    }
+}
@@ -0,0 +1,62 @@
+mod account;
+mod earning;
+mod integration;
+mod session;
+mod tag;
+mod transaction;
+mod user;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use uuid::Uuid;
+
+use crate::entities;
+
+// The rows of the tables the store stands in for. What the entities leave out,
+// like the initial balance of an account, is kept next to them.
+#[derive(Default)]
+struct Tables {
+    users: Vec<user::UserRow>,
+    accounts: Vec<account::AccountRow>,
+    transactions: Vec<transaction::TransactionRow>,
+    integrations: Vec<entities::integration::UserIntegration>,
+    tags: Vec<entities::tag::Tag>,
+    account_tags: Vec<entities::tag::AccountTag>,
+    sessions: Vec<session::SessionRow>,
+    // (token hash, session) of the refresh tokens already replaced.
+    rotated_refresh_tokens: Vec<(String, Uuid)>,
+    index_rates: Vec<entities::earning::IndexRate>,
+}
+
+// Implements every model in memory, following the same rules as Postgres:
+// rows are scoped to their user, names, usernames and emails are unique, and
+// trashed accounts give up their names.
+// Each call holds the lock for its whole length, so it is all or nothing like
+// the database transactions it replaces. Clones share the tables, like the
+// handles of a connection pool. Lets services be tested without a database.
+#[derive(Clone, Default)]
+pub struct InMemoryStore {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl InMemoryStore {
+    fn tables(&self) -> MutexGuard<Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+// A plain account for tests, adjusted with struct update syntax when they
+// need more. The integration tests get it through `test-store`, and the
+// binary builds it without using it.
+#[allow(dead_code)]
+pub fn new_account(name: &str, balance: entities::money::Money) -> entities::account::NewAccount {
+    entities::account::NewAccount {
+        time: chrono::NaiveDateTime::from_timestamp(1665366563, 0),
+        initial_balance: balance,
+        name: name.to_string(),
+        description: None,
+        pre_allocation: None,
+        earning: None,
+        is_available: true,
+    }
+}
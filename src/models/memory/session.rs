@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::session;
+
+pub(super) struct SessionRow {
+    refresh_token_hash: String,
+    pub(super) session: session::Session,
+}
+
+impl Tables {
+    fn session_mut(&mut self, id: &Uuid) -> session::Result<&mut session::Session> {
+        self.sessions
+            .iter_mut()
+            .map(|row| &mut row.session)
+            .find(|s| s.id == *id)
+            .ok_or(session::SessionModelError::SessionNotFound)
+    }
+
+    fn user_sessions<'a>(
+        &'a self,
+        user_id: &'a Uuid,
+    ) -> impl Iterator<Item = &'a session::Session> {
+        self.sessions
+            .iter()
+            .map(|row| &row.session)
+            .filter(move |s| s.related_user == *user_id)
+    }
+}
+
+impl session::NewSession {
+    fn to_row(&self) -> SessionRow {
+        SessionRow {
+            refresh_token_hash: self.refresh_token_hash.clone(),
+            session: session::Session {
+                id: Uuid::new_v4(),
+                related_user: self.related_user,
+                device_name: self.device_name.clone(),
+                created_at: self.time,
+                last_used_at: self.time,
+                revoked_at: None,
+            },
+        }
+    }
+}
+
+impl session::SessionModel for InMemoryStore {
+    fn create_session(
+        &self,
+        new_session: session::NewSession,
+    ) -> session::Result<session::Session> {
+        let row = new_session.to_row();
+        let created = row.session.clone();
+        self.tables().sessions.push(row);
+        Ok(created)
+    }
+
+    fn get_session(&self, id: &Uuid) -> session::Result<session::Session> {
+        Ok(self.tables().session_mut(id)?.clone())
+    }
+
+    fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        used_since: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> session::Result<session::Session> {
+        let mut tables = self.tables();
+        let current = tables.sessions.iter_mut().find(|row| {
+            row.refresh_token_hash == old_hash
+                && row.session.is_active()
+                && row.session.last_used_at >= used_since
+        });
+        if let Some(row) = current {
+            row.refresh_token_hash = new_hash.to_string();
+            row.session.last_used_at = now;
+            let rotated = row.session.clone();
+            tables
+                .rotated_refresh_tokens
+                .push((old_hash.to_string(), rotated.id));
+            return Ok(rotated);
+        }
+
+        let reused = tables
+            .rotated_refresh_tokens
+            .iter()
+            .find(|(hash, _)| hash == old_hash)
+            .map(|(_, session_id)| *session_id);
+        match reused {
+            Some(session_id) => {
+                let session = tables.session_mut(&session_id)?;
+                session.revoked_at = session.revoked_at.or(Some(now));
+                Err(session::SessionModelError::RefreshTokenReused(session_id))
+            }
+            None => Err(session::SessionModelError::SessionNotFound),
+        }
+    }
+
+    fn revoke_session(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> session::Result<session::Session> {
+        let mut tables = self.tables();
+        let session = tables.session_mut(id)?;
+        if session.related_user != *user_id || !session.is_active() {
+            return Err(session::SessionModelError::SessionNotFound);
+        }
+        session.revoked_at = Some(now);
+        Ok(session.clone())
+    }
+
+    fn revoke_user_sessions(
+        &self,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> session::Result<Vec<session::Session>> {
+        let mut tables = self.tables();
+        let mut revoked = Vec::new();
+        for row in tables.sessions.iter_mut() {
+            if row.session.related_user == *user_id && row.session.is_active() {
+                row.session.revoked_at = Some(now);
+                revoked.push(row.session.clone());
+            }
+        }
+        Ok(revoked)
+    }
+
+    fn list_active_sessions(&self, user_id: &Uuid) -> session::Result<Vec<session::Session>> {
+        let mut sessions: Vec<session::Session> = self
+            .tables()
+            .user_sessions(user_id)
+            .filter(|s| s.is_active())
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| b.last_used_at.cmp(&a.last_used_at));
+        Ok(sessions)
+    }
+
+    fn list_user_sessions(&self, user_id: &Uuid) -> session::Result<Vec<session::Session>> {
+        let mut sessions: Vec<session::Session> =
+            self.tables().user_sessions(user_id).cloned().collect();
+        sessions.sort_by_key(|s| s.created_at);
+        Ok(sessions)
+    }
+}
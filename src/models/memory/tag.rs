@@ -0,0 +1,133 @@
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::tag;
+
+impl Tables {
+    fn tag_mut(&mut self, id: &Uuid, user_id: &Uuid) -> tag::Result<&mut tag::Tag> {
+        self.tags
+            .iter_mut()
+            .find(|t| t.id == *id && t.related_user == *user_id)
+            .ok_or(tag::TagModelError::TagNotFound)
+    }
+
+    // The unique index on (related_user, name).
+    fn tag_name_taken(&self, user_id: &Uuid, name: &str, except: Option<Uuid>) -> bool {
+        self.tags
+            .iter()
+            .any(|t| t.related_user == *user_id && t.name == name && Some(t.id) != except)
+    }
+
+    fn check_ownership(
+        &mut self,
+        account_id: &Uuid,
+        tag_id: &Uuid,
+        user_id: &Uuid,
+    ) -> tag::Result<()> {
+        self.tag_mut(tag_id, user_id)?;
+        self.account_mut(account_id, user_id)
+            .map(|_| ())
+            .ok_or(tag::TagModelError::AccountNotFound)
+    }
+}
+
+impl tag::TagModel for InMemoryStore {
+    fn create_tag(&self, user_id: &Uuid, new_tag: tag::NewTag) -> tag::Result<tag::Tag> {
+        let mut tables = self.tables();
+        if tables.tag_name_taken(user_id, &new_tag.name, None) {
+            return Err(tag::TagModelError::TagNameAlreadyTaken);
+        }
+        let created = tag::Tag {
+            id: Uuid::new_v4(),
+            related_user: *user_id,
+            name: new_tag.name,
+            color: new_tag.color,
+            time: new_tag.time,
+        };
+        tables.tags.push(created.clone());
+        Ok(created)
+    }
+
+    fn list_user_tags(&self, user_id: &Uuid) -> tag::Result<Vec<tag::Tag>> {
+        let mut tags: Vec<tag::Tag> = self
+            .tables()
+            .tags
+            .iter()
+            .filter(|t| t.related_user == *user_id)
+            .cloned()
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    fn edit_tag(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_tag: tag::UpdatedTag,
+    ) -> tag::Result<tag::Tag> {
+        let mut tables = self.tables();
+        tables.tag_mut(id, user_id)?;
+        if let Some(name) = &updated_tag.name {
+            if tables.tag_name_taken(user_id, name, Some(*id)) {
+                return Err(tag::TagModelError::TagNameAlreadyTaken);
+            }
+        }
+        let tag = tables.tag_mut(id, user_id)?;
+        if let Some(name) = updated_tag.name {
+            tag.name = name;
+        }
+        if let Some(color) = updated_tag.color {
+            tag.color = Some(color);
+        }
+        Ok(tag.clone())
+    }
+
+    fn delete_tag(&self, id: &Uuid, user_id: &Uuid) -> tag::Result<tag::Tag> {
+        let mut tables = self.tables();
+        let deleted = tables.tag_mut(id, user_id)?.clone();
+        tables.tags.retain(|t| t.id != *id);
+        tables.account_tags.retain(|t| t.tag_id != *id);
+        Ok(deleted)
+    }
+
+    fn tag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> tag::Result<()> {
+        let mut tables = self.tables();
+        tables.check_ownership(account_id, tag_id, user_id)?;
+        let tagged = tables
+            .account_tags
+            .iter()
+            .any(|t| t.account_id == *account_id && t.tag_id == *tag_id);
+        if !tagged {
+            tables.account_tags.push(tag::AccountTag {
+                account_id: *account_id,
+                tag_id: *tag_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn untag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> tag::Result<()> {
+        let mut tables = self.tables();
+        tables.check_ownership(account_id, tag_id, user_id)?;
+        tables
+            .account_tags
+            .retain(|t| !(t.account_id == *account_id && t.tag_id == *tag_id));
+        Ok(())
+    }
+
+    fn list_account_tags(&self, user_id: &Uuid) -> tag::Result<Vec<tag::AccountTag>> {
+        let tables = self.tables();
+        Ok(tables
+            .account_tags
+            .iter()
+            .filter(|account_tag| {
+                tables
+                    .tags
+                    .iter()
+                    .any(|t| t.id == account_tag.tag_id && t.related_user == *user_id)
+            })
+            .copied()
+            .collect())
+    }
+}
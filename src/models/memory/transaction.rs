@@ -0,0 +1,317 @@
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::{money::Money, transaction, Order};
+
+pub(super) struct TransactionRow {
+    pub(super) external_id: Option<String>,
+    pub(super) transaction: transaction::Transaction,
+}
+
+impl Tables {
+    pub(super) fn user_transactions<'a>(
+        &'a self,
+        user_id: &'a Uuid,
+    ) -> impl Iterator<Item = &'a transaction::Transaction> {
+        self.transactions
+            .iter()
+            .map(|row| &row.transaction)
+            .filter(move |t| t.related_user == *user_id)
+    }
+
+    fn transaction_mut(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> transaction::Result<&mut transaction::Transaction> {
+        self.transactions
+            .iter_mut()
+            .map(|row| &mut row.transaction)
+            .find(|t| t.id == *id && t.related_user == *user_id)
+            .ok_or(transaction::TransactionModelError::TransactionNotFound)
+    }
+
+    // Every account is checked before any balance moves, so a missing one
+    // leaves them all as they were.
+    fn apply_transaction_effects(
+        &mut self,
+        user_id: &Uuid,
+        effects: &[(Uuid, Money)],
+    ) -> transaction::Result<()> {
+        if effects
+            .iter()
+            .any(|(account_id, _)| self.account_mut(account_id, user_id).is_none())
+        {
+            return Err(transaction::TransactionModelError::AccountNotFound);
+        }
+        for (account_id, delta) in effects {
+            if let Some(account) = self.account_mut(account_id, user_id) {
+                account.balance += *delta;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_transactions(
+        &mut self,
+        user_id: &Uuid,
+        new_transactions: Vec<(transaction::NewTransaction, Option<String>)>,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        let rows: Vec<TransactionRow> = new_transactions
+            .into_iter()
+            .map(|(new_transaction, external_id)| TransactionRow {
+                external_id,
+                transaction: new_transaction.to_entity(user_id),
+            })
+            .collect();
+        let effects: Vec<(Uuid, Money)> = rows
+            .iter()
+            .flat_map(|row| row.transaction.balance_effects())
+            .collect();
+        self.apply_transaction_effects(user_id, &effects)?;
+
+        let created = rows.iter().map(|row| row.transaction.clone()).collect();
+        self.transactions.extend(rows);
+        Ok(created)
+    }
+
+    fn insert_transaction(
+        &mut self,
+        user_id: &Uuid,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut created = self.insert_transactions(user_id, vec![(new_transaction, None)])?;
+        Ok(created.remove(0))
+    }
+}
+
+impl transaction::NewTransaction {
+    fn to_entity(&self, related_user: &Uuid) -> transaction::Transaction {
+        transaction::Transaction {
+            id: Uuid::new_v4(),
+            related_user: *related_user,
+            entry_date: self.entry_date,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            amount: self.amount,
+            description: self.description.clone(),
+            voided: false,
+            kind: self.kind,
+        }
+    }
+}
+
+impl transaction::UpdatedTransaction {
+    fn apply_to(&self, t: &transaction::Transaction) -> transaction::Transaction {
+        transaction::Transaction {
+            entry_date: self.entry_date.unwrap_or(t.entry_date),
+            entry_account_code: self.entry_account_code.unwrap_or(t.entry_account_code),
+            exit_account_code: self.exit_account_code.unwrap_or(t.exit_account_code),
+            amount: self.amount.unwrap_or(t.amount),
+            description: self
+                .description
+                .clone()
+                .unwrap_or_else(|| t.description.clone()),
+            ..t.clone()
+        }
+    }
+}
+
+impl transaction::TransactionFilter {
+    // The same conditions as the query, the description being matched like
+    // ILIKE does.
+    fn matches(&self, t: &transaction::Transaction) -> bool {
+        let in_description = |substring: &String| {
+            t.description.as_ref().map_or(false, |description| {
+                description
+                    .to_lowercase()
+                    .contains(&substring.to_lowercase())
+            })
+        };
+        self.from_date.map_or(true, |from| t.entry_date >= from)
+            && self.to_date.map_or(true, |to| t.entry_date <= to)
+            && self.account.map_or(true, |account| {
+                t.entry_account_code == Some(account) || t.exit_account_code == Some(account)
+            })
+            && self.min_amount.map_or(true, |min| t.amount >= min)
+            && self.max_amount.map_or(true, |max| t.amount <= max)
+            && self.description.as_ref().map_or(true, in_description)
+    }
+}
+
+impl transaction::TransactionModel for InMemoryStore {
+    fn create_transaction(
+        &self,
+        user_id: &Uuid,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        self.tables().insert_transaction(user_id, new_transaction)
+    }
+
+    fn list_user_transactions(
+        &self,
+        user_id: &Uuid,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        Ok(self.tables().user_transactions(user_id).cloned().collect())
+    }
+
+    fn list_user_transactions_between(
+        &self,
+        user_id: &Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        Ok(self
+            .tables()
+            .user_transactions(user_id)
+            .filter(|t| t.entry_date >= from && t.entry_date <= to)
+            .cloned()
+            .collect())
+    }
+
+    fn filter_user_transactions(
+        &self,
+        user_id: &Uuid,
+        filter: &transaction::TransactionFilter,
+        page: &transaction::TransactionPageRequest,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        let after = |t: &transaction::Transaction| match (page.order, page.after) {
+            (Order::ASC, Some(after)) => (t.entry_date, t.id) > (after.entry_date, after.id),
+            (Order::DESC, Some(after)) => (t.entry_date, t.id) < (after.entry_date, after.id),
+            (_, None) => true,
+        };
+        let mut transactions: Vec<transaction::Transaction> = self
+            .tables()
+            .user_transactions(user_id)
+            .filter(|t| filter.matches(t) && after(t))
+            .cloned()
+            .collect();
+
+        transactions.sort_by_key(|t| (t.entry_date, t.id));
+        if let Order::DESC = page.order {
+            transactions.reverse();
+        }
+        transactions.truncate((page.first + 1).max(0) as usize);
+        Ok(transactions)
+    }
+
+    fn edit_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_transaction: transaction::UpdatedTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut tables = self.tables();
+        let old = tables.transaction_mut(id, user_id)?.clone();
+        if old.voided {
+            return Err(transaction::TransactionModelError::TransactionIsVoided);
+        }
+        if updated_transaction.is_empty() {
+            return Ok(old);
+        }
+        let edited = updated_transaction.apply_to(&old);
+        let effects: Vec<(Uuid, Money)> = old
+            .reversed_balance_effects()
+            .into_iter()
+            .chain(edited.balance_effects())
+            .collect();
+        tables.apply_transaction_effects(user_id, &effects)?;
+
+        *tables.transaction_mut(id, user_id)? = edited.clone();
+        Ok(edited)
+    }
+
+    fn delete_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut tables = self.tables();
+        let old = tables.transaction_mut(id, user_id)?.clone();
+        tables.apply_transaction_effects(user_id, &old.reversed_balance_effects())?;
+
+        tables.transactions.retain(|row| row.transaction.id != *id);
+        Ok(old)
+    }
+
+    fn void_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut tables = self.tables();
+        let old = tables.transaction_mut(id, user_id)?.clone();
+        if old.voided {
+            return Err(transaction::TransactionModelError::TransactionIsVoided);
+        }
+        tables.apply_transaction_effects(user_id, &old.reversed_balance_effects())?;
+
+        let voided = tables.transaction_mut(id, user_id)?;
+        voided.voided = true;
+        Ok(voided.clone())
+    }
+
+    fn create_accrual_transaction(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        accrued_until: NaiveDate,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut tables = self.tables();
+        let created = tables.insert_transaction(user_id, new_transaction)?;
+        if let Some(account) = tables.account_mut(account_id, user_id) {
+            account.last_accrual_date = Some(accrued_until);
+        }
+        Ok(created)
+    }
+
+    fn create_refill_transactions(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        cycle_start: NaiveDate,
+        refilled_on: NaiveDate,
+        new_transactions: Vec<transaction::NewTransaction>,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        let mut tables = self.tables();
+        let last_refill_date = tables
+            .account_mut(account_id, user_id)
+            .ok_or(transaction::TransactionModelError::AccountNotFound)?
+            .last_refill_date;
+        if last_refill_date.map_or(false, |date| date >= cycle_start) {
+            return Ok(vec![]);
+        }
+
+        let created = tables.insert_transactions(
+            user_id,
+            new_transactions.into_iter().map(|t| (t, None)).collect(),
+        )?;
+        if let Some(account) = tables.account_mut(account_id, user_id) {
+            account.last_refill_date = Some(refilled_on);
+        }
+        Ok(created)
+    }
+
+    fn import_transaction(
+        &self,
+        user_id: &Uuid,
+        external_id: &str,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<Option<transaction::Transaction>> {
+        let mut tables = self.tables();
+        let already_imported = tables.transactions.iter().any(|row| {
+            row.transaction.related_user == *user_id
+                && row.external_id.as_deref() == Some(external_id)
+        });
+        if already_imported {
+            return Ok(None);
+        }
+        let mut created = tables.insert_transactions(
+            user_id,
+            vec![(new_transaction, Some(external_id.to_string()))],
+        )?;
+        Ok(Some(created.remove(0)))
+    }
+}
@@ -0,0 +1,315 @@
+use chrono::NaiveDateTime;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use super::{InMemoryStore, Tables};
+use crate::entities::user;
+
+pub(super) struct UserRow {
+    user: user::User,
+    login_code_attempts: i32,
+    login_locked_until: Option<NaiveDateTime>,
+    email_change_code: Option<i32>,
+    email_change_requested_at: Option<NaiveDateTime>,
+    email_change_attempts: i32,
+}
+
+impl user::NewUser {
+    fn to_row(&self) -> UserRow {
+        UserRow {
+            user: user::User {
+                id: Uuid::new_v4(),
+                name: self.name.clone(),
+                username: self.username.clone(),
+                register_date: None,
+                email: self.email.clone(),
+                last_code_gen_request: None,
+                login_code: None,
+                is_registered: false,
+                payday: None,
+                deletion_scheduled_for: None,
+                pending_email: None,
+            },
+            login_code_attempts: 0,
+            login_locked_until: None,
+            email_change_code: None,
+            email_change_requested_at: None,
+            email_change_attempts: 0,
+        }
+    }
+}
+
+impl UserRow {
+    fn clear_email_change(&mut self) {
+        self.user.pending_email = None;
+        self.email_change_code = None;
+        self.email_change_requested_at = None;
+        self.email_change_attempts = 0;
+    }
+}
+
+impl Tables {
+    pub(super) fn user_exists(&self, id: &Uuid) -> bool {
+        self.users.iter().any(|row| row.user.id == *id)
+    }
+
+    fn user_mut(&mut self, id: &Uuid) -> user::Result<&mut UserRow> {
+        self.users
+            .iter_mut()
+            .find(|row| row.user.id == *id)
+            .ok_or(user::UserModelError::UserDoesNotExists)
+    }
+
+    // Updates by email fail like the queries do when no row matches.
+    fn user_by_email_mut(&mut self, email: &str) -> user::Result<&mut UserRow> {
+        self.users
+            .iter_mut()
+            .find(|row| row.user.email == email)
+            .ok_or(user::UserModelError::FailedToUpdateLoginCode(
+                DieselError::NotFound,
+            ))
+    }
+
+    // Whether another user than `except` has the username or email.
+    fn taken_by_other(&self, except: &Uuid, taken: impl Fn(&user::User) -> bool) -> bool {
+        self.users
+            .iter()
+            .any(|row| row.user.id != *except && taken(&row.user))
+    }
+}
+
+impl user::UserModel for InMemoryStore {
+    fn create_user(&self, user: user::NewUser) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        let taken = tables
+            .users
+            .iter()
+            .any(|row| row.user.username == user.username || row.user.email == user.email);
+        if taken {
+            return Err(user::UserModelError::UserAlreadyExists);
+        }
+        let row = user.to_row();
+        let created = row.user.clone();
+        tables.users.push(row);
+        Ok(created)
+    }
+
+    // What the foreign keys cascade to in the database.
+    fn delete_user(&self, id: &Uuid) -> user::Result<Option<user::User>> {
+        let mut tables = self.tables();
+        let tags: Vec<Uuid> = tables
+            .tags
+            .iter()
+            .filter(|t| t.related_user == *id)
+            .map(|t| t.id)
+            .collect();
+        tables.account_tags.retain(|t| !tags.contains(&t.tag_id));
+        tables.tags.retain(|t| t.related_user != *id);
+        tables
+            .sessions
+            .retain(|row| row.session.related_user != *id);
+        let sessions: Vec<Uuid> = tables.sessions.iter().map(|row| row.session.id).collect();
+        tables
+            .rotated_refresh_tokens
+            .retain(|(_, session_id)| sessions.contains(session_id));
+        tables.accounts.retain(|row| row.related_user != *id);
+        tables
+            .transactions
+            .retain(|row| row.transaction.related_user != *id);
+        tables
+            .integrations
+            .retain(|integration| integration.related_user != *id);
+
+        let deleted = tables.users.iter().position(|row| row.user.id == *id);
+        Ok(deleted.map(|index| tables.users.remove(index).user))
+    }
+
+    fn schedule_user_deletion(&self, id: &Uuid, at: NaiveDateTime) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        let row = tables.user_mut(id)?;
+        row.user.deletion_scheduled_for = Some(at);
+        Ok(row.user.clone())
+    }
+
+    fn cancel_user_deletion(&self, id: &Uuid) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        let row = tables.user_mut(id)?;
+        row.user.deletion_scheduled_for = None;
+        Ok(row.user.clone())
+    }
+
+    fn list_users_due_for_deletion(&self, now: NaiveDateTime) -> user::Result<Vec<Uuid>> {
+        Ok(self
+            .tables()
+            .users
+            .iter()
+            .filter(|row| {
+                row.user
+                    .deletion_scheduled_for
+                    .map_or(false, |at| at <= now)
+            })
+            .map(|row| row.user.id)
+            .collect())
+    }
+
+    fn get_user(&self, id: Uuid) -> user::Result<user::User> {
+        Ok(self.tables().user_mut(&id)?.user.clone())
+    }
+
+    fn check_if_username_available(&self, username: &str) -> user::Result<bool> {
+        Ok(!self
+            .tables()
+            .users
+            .iter()
+            .any(|row| row.user.username == username))
+    }
+
+    fn check_if_email_available(&self, email: &str) -> user::Result<bool> {
+        Ok(!self
+            .tables()
+            .users
+            .iter()
+            .any(|row| row.user.email == email))
+    }
+
+    fn refresh_login_code(
+        &self,
+        email: &str,
+        login_code: i32,
+        time: NaiveDateTime,
+    ) -> user::Result<()> {
+        let mut tables = self.tables();
+        let row = tables.user_by_email_mut(email)?;
+        row.user.login_code = Some(login_code);
+        row.user.last_code_gen_request = Some(time);
+        Ok(())
+    }
+
+    fn get_login_code_state(&self, email: &str) -> user::Result<user::LoginCodeState> {
+        let mut tables = self.tables();
+        let row = tables
+            .user_by_email_mut(email)
+            .map_err(|_| user::UserModelError::UserDoesNotExists)?;
+        Ok(user::LoginCodeState {
+            login_code: row.user.login_code,
+            generated_at: row.user.last_code_gen_request,
+            locked_until: row.login_locked_until,
+            attempts: row.login_code_attempts,
+        })
+    }
+
+    fn attempt_login_code<R, F>(&self, email: &str, check: F) -> user::Result<R>
+    where
+        F: FnOnce(&user::LoginCodeState) -> (user::LoginCodeAttempt, R),
+    {
+        let mut tables = self.tables();
+        let row = tables
+            .user_by_email_mut(email)
+            .map_err(|_| user::UserModelError::UserDoesNotExists)?;
+        let (attempt, value) = check(&user::LoginCodeState {
+            login_code: row.user.login_code,
+            generated_at: row.user.last_code_gen_request,
+            locked_until: row.login_locked_until,
+            attempts: row.login_code_attempts,
+        });
+        match attempt {
+            user::LoginCodeAttempt::Accept => {
+                row.user.login_code = None;
+                row.login_code_attempts = 0;
+                row.login_locked_until = None;
+            }
+            user::LoginCodeAttempt::Fail => row.login_code_attempts += 1,
+            user::LoginCodeAttempt::Lock(until) => {
+                row.user.login_code = None;
+                row.login_code_attempts = 0;
+                row.login_locked_until = Some(until);
+            }
+            user::LoginCodeAttempt::Keep => {}
+        }
+        Ok(value)
+    }
+
+    fn get_id_by_email(&self, email: &str) -> user::Result<Uuid> {
+        let mut tables = self.tables();
+        let row = tables
+            .user_by_email_mut(email)
+            .map_err(|_| user::UserModelError::UserDoesNotExists)?;
+        Ok(row.user.id)
+    }
+
+    fn set_payday(&self, id: &Uuid, payday: Option<i32>) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        let row = tables.user_mut(id)?;
+        row.user.payday = payday;
+        Ok(row.user.clone())
+    }
+
+    fn update_profile(
+        &self,
+        id: &Uuid,
+        profile: user::UpdatedProfile,
+        email_change: Option<user::NewEmailChange>,
+    ) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        let _ = tables.user_mut(id)?;
+        if let Some(username) = &profile.username {
+            if tables.taken_by_other(id, |user| user.username == *username) {
+                return Err(user::UserModelError::UserAlreadyExists);
+            }
+        }
+        let row = tables.user_mut(id)?;
+        if let Some(name) = profile.name {
+            row.user.name = name;
+        }
+        if let Some(username) = profile.username {
+            row.user.username = username;
+        }
+        if let Some(payday) = profile.payday {
+            row.user.payday = payday;
+        }
+        if let Some(change) = email_change {
+            row.user.pending_email = Some(change.email);
+            row.email_change_code = Some(change.code);
+            row.email_change_requested_at = Some(change.time);
+            row.email_change_attempts = 0;
+        }
+        Ok(row.user.clone())
+    }
+
+    fn get_email_change(&self, id: &Uuid) -> user::Result<user::EmailChange> {
+        let mut tables = self.tables();
+        let row = tables.user_mut(id)?;
+        Ok(user::EmailChange {
+            email: row.user.pending_email.clone(),
+            code: row.email_change_code,
+            requested_at: row.email_change_requested_at,
+            attempts: row.email_change_attempts,
+        })
+    }
+
+    fn register_failed_email_change_attempt(&self, id: &Uuid) -> user::Result<i32> {
+        let mut tables = self.tables();
+        let row = tables.user_mut(id)?;
+        row.email_change_attempts += 1;
+        Ok(row.email_change_attempts)
+    }
+
+    fn confirm_email_change(&self, id: &Uuid, email: &str) -> user::Result<user::User> {
+        let mut tables = self.tables();
+        if tables.user_mut(id)?.user.pending_email.as_deref() != Some(email) {
+            return Err(user::UserModelError::UserDoesNotExists);
+        }
+        if tables.taken_by_other(id, |user| user.email == email) {
+            return Err(user::UserModelError::UserAlreadyExists);
+        }
+        let row = tables.user_mut(id)?;
+        row.user.email = email.to_string();
+        row.clear_email_change();
+        Ok(row.user.clone())
+    }
+
+    fn cancel_email_change(&self, id: &Uuid) -> user::Result<()> {
+        self.tables().user_mut(id)?.clear_email_change();
+        Ok(())
+    }
+}
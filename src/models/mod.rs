@@ -1,4 +1,9 @@
 pub mod account;
+pub mod earning;
 pub mod integration;
+#[cfg(any(test, feature = "test-store"))]
+pub mod memory;
+pub mod session;
+pub mod tag;
 pub mod transaction;
-pub mod user;
\ No newline at end of file
+pub mod user;
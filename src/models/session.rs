@@ -0,0 +1,209 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+use crate::{
+    database,
+    entities::session,
+    schema::{rotated_refresh_tokens as rotated_schema, sessions as session_schema},
+};
+
+#[derive(Queryable, Clone)]
+#[diesel(table_name = session_schema)]
+struct Session {
+    id: Uuid,
+    related_user: Uuid,
+    #[allow(dead_code)]
+    refresh_token_hash: String,
+    device_name: Option<String>,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = session_schema)]
+struct NewSession {
+    related_user: Uuid,
+    refresh_token_hash: String,
+    device_name: Option<String>,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+}
+
+impl session::NewSession {
+    fn to_model(&self) -> NewSession {
+        NewSession {
+            related_user: self.related_user,
+            refresh_token_hash: self.refresh_token_hash.clone(),
+            device_name: self.device_name.clone(),
+            created_at: self.time,
+            last_used_at: self.time,
+        }
+    }
+}
+
+impl Session {
+    fn to_entity(&self) -> session::Session {
+        session::Session {
+            id: self.id,
+            related_user: self.related_user,
+            device_name: self.device_name.clone(),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            revoked_at: self.revoked_at,
+        }
+    }
+}
+
+fn map_session_error(
+    error: DieselError,
+    otherwise: fn(DieselError) -> session::SessionModelError,
+) -> session::SessionModelError {
+    match error {
+        DieselError::NotFound => session::SessionModelError::SessionNotFound,
+        error => otherwise(error),
+    }
+}
+
+impl session::SessionModel for database::DbPool {
+    fn create_session(
+        &self,
+        new_session: session::NewSession,
+    ) -> session::Result<session::Session> {
+        diesel::insert_into(session_schema::table)
+            .values(&new_session.to_model())
+            .get_result::<Session>(&mut self.get()?)
+            .map(|s| s.to_entity())
+            .map_err(session::SessionModelError::FailedToCreateSession)
+    }
+
+    fn get_session(&self, id: &Uuid) -> session::Result<session::Session> {
+        session_schema::table
+            .filter(session_schema::id.eq(id))
+            .get_result::<Session>(&mut self.get()?)
+            .map(|s| s.to_entity())
+            .map_err(|err| map_session_error(err, session::SessionModelError::FailedToGetSession))
+    }
+
+    fn rotate_refresh_token(
+        &self,
+        old_hash: &str,
+        new_hash: &str,
+        used_since: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> session::Result<session::Session> {
+        let mut conn = self.get()?;
+        let rotated = conn.transaction::<_, session::SessionModelError, _>(|conn| {
+            let rotated = diesel::update(
+                session_schema::table
+                    .filter(session_schema::refresh_token_hash.eq(old_hash))
+                    .filter(session_schema::revoked_at.is_null())
+                    .filter(session_schema::last_used_at.ge(used_since)),
+            )
+            .set((
+                session_schema::refresh_token_hash.eq(new_hash),
+                session_schema::last_used_at.eq(now),
+            ))
+            .get_result::<Session>(conn)
+            .optional()
+            .map_err(session::SessionModelError::FailedToUpdateSession)?;
+            if let Some(rotated) = &rotated {
+                diesel::insert_into(rotated_schema::table)
+                    .values((
+                        rotated_schema::token_hash.eq(old_hash),
+                        rotated_schema::session_id.eq(rotated.id),
+                        rotated_schema::rotated_at.eq(now),
+                    ))
+                    .execute(conn)
+                    .map_err(session::SessionModelError::FailedToUpdateSession)?;
+            }
+            Ok(rotated)
+        })?;
+        if let Some(rotated) = rotated {
+            return Ok(rotated.to_entity());
+        }
+
+        // Done outside of the transaction, which the error would roll back.
+        let reused = rotated_schema::table
+            .filter(rotated_schema::token_hash.eq(old_hash))
+            .select(rotated_schema::session_id)
+            .get_result::<Uuid>(&mut conn)
+            .optional()
+            .map_err(session::SessionModelError::FailedToGetSession)?;
+        match reused {
+            Some(session_id) => {
+                diesel::update(
+                    session_schema::table
+                        .filter(session_schema::id.eq(session_id))
+                        .filter(session_schema::revoked_at.is_null()),
+                )
+                .set(session_schema::revoked_at.eq(now))
+                .execute(&mut conn)
+                .map_err(session::SessionModelError::FailedToUpdateSession)?;
+                Err(session::SessionModelError::RefreshTokenReused(session_id))
+            }
+            None => Err(session::SessionModelError::SessionNotFound),
+        }
+    }
+
+    fn revoke_session(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> session::Result<session::Session> {
+        diesel::update(
+            session_schema::table
+                .filter(session_schema::id.eq(id))
+                .filter(session_schema::related_user.eq(user_id))
+                .filter(session_schema::revoked_at.is_null()),
+        )
+        .set(session_schema::revoked_at.eq(now))
+        .get_result::<Session>(&mut self.get()?)
+        .map(|s| s.to_entity())
+        .map_err(|err| map_session_error(err, session::SessionModelError::FailedToUpdateSession))
+    }
+
+    fn revoke_user_sessions(
+        &self,
+        user_id: &Uuid,
+        now: NaiveDateTime,
+    ) -> session::Result<Vec<session::Session>> {
+        Ok(diesel::update(
+            session_schema::table
+                .filter(session_schema::related_user.eq(user_id))
+                .filter(session_schema::revoked_at.is_null()),
+        )
+        .set(session_schema::revoked_at.eq(now))
+        .get_results::<Session>(&mut self.get()?)
+        .map_err(session::SessionModelError::FailedToUpdateSession)?
+        .iter()
+        .map(|s| s.to_entity())
+        .collect())
+    }
+
+    fn list_active_sessions(&self, user_id: &Uuid) -> session::Result<Vec<session::Session>> {
+        Ok(session_schema::table
+            .filter(session_schema::related_user.eq(user_id))
+            .filter(session_schema::revoked_at.is_null())
+            .order(session_schema::last_used_at.desc())
+            .load::<Session>(&mut self.get()?)
+            .map_err(session::SessionModelError::FailedToListSessions)?
+            .iter()
+            .map(|s| s.to_entity())
+            .collect())
+    }
+
+    fn list_user_sessions(&self, user_id: &Uuid) -> session::Result<Vec<session::Session>> {
+        Ok(session_schema::table
+            .filter(session_schema::related_user.eq(user_id))
+            .order(session_schema::created_at.asc())
+            .load::<Session>(&mut self.get()?)
+            .map_err(session::SessionModelError::FailedToListSessions)?
+            .iter()
+            .map(|s| s.to_entity())
+            .collect())
+    }
+}
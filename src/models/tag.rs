@@ -0,0 +1,217 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use uuid::Uuid;
+
+use crate::{
+    database,
+    entities::tag,
+    schema::{account_tags as account_tag_schema, accounts as account_schema, tags as tag_schema},
+};
+
+#[derive(Queryable, Clone)]
+#[diesel(table_name = tag_schema)]
+struct Tag {
+    id: Uuid,
+    related_user: Uuid,
+    name: String,
+    color: Option<String>,
+    time: NaiveDateTime,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = tag_schema)]
+struct NewTag {
+    related_user: Uuid,
+    name: String,
+    color: Option<String>,
+    time: NaiveDateTime,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = tag_schema)]
+struct UpdatedTag {
+    name: Option<String>,
+    color: Option<String>,
+}
+
+#[derive(Insertable, Queryable, Clone)]
+#[diesel(table_name = account_tag_schema)]
+struct AccountTag {
+    account_id: Uuid,
+    tag_id: Uuid,
+}
+
+impl tag::NewTag {
+    fn to_model(&self, related_user: &Uuid) -> NewTag {
+        NewTag {
+            related_user: *related_user,
+            name: self.name.clone(),
+            color: self.color.clone(),
+            time: self.time,
+        }
+    }
+}
+
+impl tag::UpdatedTag {
+    fn to_model(&self) -> UpdatedTag {
+        UpdatedTag {
+            name: self.name.clone(),
+            color: self.color.clone(),
+        }
+    }
+}
+
+impl Tag {
+    fn to_entity(&self) -> tag::Tag {
+        tag::Tag {
+            id: self.id,
+            related_user: self.related_user,
+            name: self.name.clone(),
+            color: self.color.clone(),
+            time: self.time,
+        }
+    }
+}
+
+impl AccountTag {
+    fn to_entity(&self) -> tag::AccountTag {
+        tag::AccountTag {
+            account_id: self.account_id,
+            tag_id: self.tag_id,
+        }
+    }
+}
+
+fn map_tag_error(
+    error: DieselError,
+    otherwise: fn(DieselError) -> tag::TagModelError,
+) -> tag::TagModelError {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            tag::TagModelError::TagNameAlreadyTaken
+        }
+        DieselError::NotFound => tag::TagModelError::TagNotFound,
+        error => otherwise(error),
+    }
+}
+
+fn check_ownership(
+    conn: &mut PgConnection,
+    account_id: &Uuid,
+    tag_id: &Uuid,
+    user_id: &Uuid,
+) -> tag::Result<()> {
+    let tags = tag_schema::table
+        .filter(tag_schema::id.eq(tag_id))
+        .filter(tag_schema::related_user.eq(user_id))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(tag::TagModelError::FailedToListTags)?;
+    if tags == 0 {
+        return Err(tag::TagModelError::TagNotFound);
+    }
+
+    let accounts = account_schema::table
+        .filter(account_schema::id.eq(account_id))
+        .filter(account_schema::related_user.eq(user_id))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(tag::TagModelError::FailedToTagAccount)?;
+    if accounts == 0 {
+        return Err(tag::TagModelError::AccountNotFound);
+    }
+    Ok(())
+}
+
+impl tag::TagModel for database::DbPool {
+    fn create_tag(&self, user_id: &Uuid, new_tag: tag::NewTag) -> tag::Result<tag::Tag> {
+        diesel::insert_into(tag_schema::table)
+            .values(&new_tag.to_model(user_id))
+            .get_result::<Tag>(&mut self.get()?)
+            .map(|t| t.to_entity())
+            .map_err(|err| map_tag_error(err, tag::TagModelError::FailedToCreateTag))
+    }
+
+    fn list_user_tags(&self, user_id: &Uuid) -> tag::Result<Vec<tag::Tag>> {
+        Ok(tag_schema::table
+            .filter(tag_schema::related_user.eq(user_id))
+            .order(tag_schema::name.asc())
+            .load::<Tag>(&mut self.get()?)
+            .map_err(tag::TagModelError::FailedToListTags)?
+            .iter()
+            .map(|t| t.to_entity())
+            .collect())
+    }
+
+    fn edit_tag(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_tag: tag::UpdatedTag,
+    ) -> tag::Result<tag::Tag> {
+        diesel::update(
+            tag_schema::table
+                .filter(tag_schema::id.eq(id))
+                .filter(tag_schema::related_user.eq(user_id)),
+        )
+        .set(updated_tag.to_model())
+        .get_result::<Tag>(&mut self.get()?)
+        .map(|t| t.to_entity())
+        .map_err(|err| map_tag_error(err, tag::TagModelError::FailedToUpdateTag))
+    }
+
+    fn delete_tag(&self, id: &Uuid, user_id: &Uuid) -> tag::Result<tag::Tag> {
+        diesel::delete(
+            tag_schema::table
+                .filter(tag_schema::id.eq(id))
+                .filter(tag_schema::related_user.eq(user_id)),
+        )
+        .get_result::<Tag>(&mut self.get()?)
+        .map(|t| t.to_entity())
+        .map_err(|err| map_tag_error(err, tag::TagModelError::FailedToDeleteTag))
+    }
+
+    fn tag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> tag::Result<()> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, tag::TagModelError, _>(|conn| {
+            check_ownership(conn, account_id, tag_id, user_id)?;
+            diesel::insert_into(account_tag_schema::table)
+                .values(&AccountTag {
+                    account_id: *account_id,
+                    tag_id: *tag_id,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .map_err(tag::TagModelError::FailedToTagAccount)?;
+            Ok(())
+        })
+    }
+
+    fn untag_account(&self, account_id: &Uuid, tag_id: &Uuid, user_id: &Uuid) -> tag::Result<()> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, tag::TagModelError, _>(|conn| {
+            check_ownership(conn, account_id, tag_id, user_id)?;
+            diesel::delete(
+                account_tag_schema::table
+                    .filter(account_tag_schema::account_id.eq(account_id))
+                    .filter(account_tag_schema::tag_id.eq(tag_id)),
+            )
+            .execute(conn)
+            .map_err(tag::TagModelError::FailedToUntagAccount)?;
+            Ok(())
+        })
+    }
+
+    fn list_account_tags(&self, user_id: &Uuid) -> tag::Result<Vec<tag::AccountTag>> {
+        Ok(account_tag_schema::table
+            .inner_join(tag_schema::table)
+            .filter(tag_schema::related_user.eq(user_id))
+            .select((account_tag_schema::account_id, account_tag_schema::tag_id))
+            .load::<AccountTag>(&mut self.get()?)
+            .map_err(tag::TagModelError::FailedToListTags)?
+            .iter()
+            .map(|t| t.to_entity())
+            .collect())
+    }
+}
@@ -1,8 +1,25 @@
 use chrono::NaiveDate;
 use diesel::prelude::*;
+use diesel_derive_enum;
 use uuid::Uuid;
 
-use crate::{database, entities::transaction, schema::transactions as transaction_schema};
+use crate::{
+    database,
+    entities::{money::Money, transaction, Order},
+    models::account::apply_balance_effects,
+    schema::{accounts as account_schema, transactions as transaction_schema},
+};
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Clone, Copy)]
+#[DieselTypePath = "crate::schema::sql_types::TransactionKindEnum"]
+#[allow(clippy::upper_case_acronyms)]
+enum TransactionKindEnum {
+    MANUAL,
+    EARNING,
+    REFILL,
+    SWEEP,
+    IMPORT,
+}
 
 #[derive(Queryable, Clone)]
 #[diesel(table_name = transaction_schema)]
@@ -12,8 +29,12 @@ struct Transaction {
     entry_date: NaiveDate,
     entry_account_code: Option<Uuid>,
     exit_account_code: Option<Uuid>,
-    amount: f64,
+    amount: i64,
     description: Option<String>,
+    voided: bool,
+    kind: TransactionKindEnum,
+    #[allow(dead_code)]
+    external_id: Option<String>,
 }
 
 #[derive(Insertable, Clone)]
@@ -23,19 +44,57 @@ struct NewTransaction {
     entry_date: NaiveDate,
     entry_account_code: Option<Uuid>,
     exit_account_code: Option<Uuid>,
-    amount: f64,
+    amount: i64,
     description: Option<String>,
+    kind: TransactionKindEnum,
+    external_id: Option<String>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = transaction_schema)]
+struct UpdatedTransaction {
+    entry_date: Option<NaiveDate>,
+    entry_account_code: Option<Option<Uuid>>,
+    exit_account_code: Option<Option<Uuid>>,
+    amount: Option<i64>,
+    description: Option<Option<String>>,
+}
+
+impl transaction::TransactionKind {
+    fn to_model(self) -> TransactionKindEnum {
+        match self {
+            transaction::TransactionKind::MANUAL => TransactionKindEnum::MANUAL,
+            transaction::TransactionKind::EARNING => TransactionKindEnum::EARNING,
+            transaction::TransactionKind::REFILL => TransactionKindEnum::REFILL,
+            transaction::TransactionKind::SWEEP => TransactionKindEnum::SWEEP,
+            transaction::TransactionKind::IMPORT => TransactionKindEnum::IMPORT,
+        }
+    }
+}
+
+impl TransactionKindEnum {
+    fn to_entity(self) -> transaction::TransactionKind {
+        match self {
+            TransactionKindEnum::MANUAL => transaction::TransactionKind::MANUAL,
+            TransactionKindEnum::EARNING => transaction::TransactionKind::EARNING,
+            TransactionKindEnum::REFILL => transaction::TransactionKind::REFILL,
+            TransactionKindEnum::SWEEP => transaction::TransactionKind::SWEEP,
+            TransactionKindEnum::IMPORT => transaction::TransactionKind::IMPORT,
+        }
+    }
 }
 
 impl transaction::NewTransaction {
     fn to_model(&self, related_user: &Uuid) -> NewTransaction {
         NewTransaction {
-            related_user: related_user.clone(),
+            related_user: *related_user,
             entry_date: self.entry_date,
-            entry_account_code: self.entry_account_code.clone(),
-            exit_account_code: self.exit_account_code.clone(),
-            amount: self.amount,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            amount: self.amount.cents(),
             description: self.description.clone(),
+            kind: self.kind.to_model(),
+            external_id: None,
         }
     }
 }
@@ -46,25 +105,83 @@ impl Transaction {
             id: self.id,
             related_user: self.related_user,
             entry_date: self.entry_date,
-            entry_account_code: self.entry_account_code.clone(),
-            exit_account_code: self.exit_account_code.clone(),
-            amount: self.amount,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            amount: Money::from_cents(self.amount),
+            description: self.description.clone(),
+            voided: self.voided,
+            kind: self.kind.to_entity(),
+        }
+    }
+}
+
+impl transaction::UpdatedTransaction {
+    fn to_model(&self) -> UpdatedTransaction {
+        UpdatedTransaction {
+            entry_date: self.entry_date,
+            entry_account_code: self.entry_account_code,
+            exit_account_code: self.exit_account_code,
+            amount: self.amount.map(|x| x.cents()),
             description: self.description.clone(),
         }
     }
 }
 
+// Locks the transaction row until the end of the current database transaction.
+fn get_transaction_for_update(
+    conn: &mut PgConnection,
+    id: &Uuid,
+    user_id: &Uuid,
+) -> transaction::Result<transaction::Transaction> {
+    let transactions = transaction_schema::table
+        .filter(transaction_schema::id.eq(id))
+        .filter(transaction_schema::related_user.eq(user_id))
+        .for_update()
+        .load::<Transaction>(conn)
+        .map_err(transaction::TransactionModelError::FailedToGetTransaction)?;
+
+    match transactions.as_slice() {
+        [t] => Ok(t.to_entity()),
+        _ => Err(transaction::TransactionModelError::TransactionNotFound),
+    }
+}
+
+// Escapes LIKE wildcards so the description filter is a plain substring match.
+fn like_pattern(substring: &str) -> String {
+    let escaped = substring
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn apply_transaction_effects(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    effects: &[(Uuid, Money)],
+) -> transaction::Result<()> {
+    apply_balance_effects(conn, user_id, effects).map_err(|err| match err {
+        diesel::result::Error::NotFound => transaction::TransactionModelError::AccountNotFound,
+        err => transaction::TransactionModelError::FailedToUpdateBalances(err),
+    })
+}
+
 impl transaction::TransactionModel for database::DbPool {
     fn create_transaction(
         &self,
         user_id: &Uuid,
         new_transaction: transaction::NewTransaction,
     ) -> transaction::Result<transaction::Transaction> {
-        diesel::insert_into(transaction_schema::table)
-            .values(&new_transaction.to_model(user_id))
-            .get_result::<Transaction>(&mut self.get()?)
-            .map(|t| t.to_entity())
-            .map_err(transaction::TransactionModelError::FailedToCreateTransaction)
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let created = diesel::insert_into(transaction_schema::table)
+                .values(&new_transaction.to_model(user_id))
+                .get_result::<Transaction>(conn)
+                .map(|t| t.to_entity())
+                .map_err(transaction::TransactionModelError::FailedToCreateTransaction)?;
+            apply_transaction_effects(conn, user_id, &created.balance_effects())?;
+            Ok(created)
+        })
     }
 
     fn list_user_transactions(
@@ -80,12 +197,263 @@ impl transaction::TransactionModel for database::DbPool {
             .collect())
     }
 
-    fn delete_transaction_by_user_id(&self, user_id: &Uuid) -> transaction::Result<()> {
-        diesel::delete(
-            transaction_schema::table.filter(transaction_schema::related_user.eq(user_id)),
-        )
-        .execute(&mut self.get()?)
-        .map_err(transaction::TransactionModelError::FailedToDeleteTransaction)?;
-        Ok(())
+    fn list_user_transactions_between(
+        &self,
+        user_id: &Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        Ok(transaction_schema::table
+            .filter(transaction_schema::related_user.eq(user_id))
+            .filter(transaction_schema::entry_date.ge(from))
+            .filter(transaction_schema::entry_date.le(to))
+            .load::<Transaction>(&mut self.get()?)
+            .map_err(transaction::TransactionModelError::FailedToListTransactions)?
+            .iter()
+            .map(|t| t.to_entity())
+            .collect())
+    }
+
+    fn filter_user_transactions(
+        &self,
+        user_id: &Uuid,
+        filter: &transaction::TransactionFilter,
+        page: &transaction::TransactionPageRequest,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        let mut query = transaction_schema::table
+            .filter(transaction_schema::related_user.eq(user_id))
+            .into_boxed();
+
+        if let Some(from_date) = filter.from_date {
+            query = query.filter(transaction_schema::entry_date.ge(from_date));
+        }
+        if let Some(to_date) = filter.to_date {
+            query = query.filter(transaction_schema::entry_date.le(to_date));
+        }
+        if let Some(account) = filter.account {
+            query = query.filter(
+                transaction_schema::entry_account_code
+                    .eq(account)
+                    .or(transaction_schema::exit_account_code.eq(account)),
+            );
+        }
+        if let Some(min_amount) = filter.min_amount {
+            query = query.filter(transaction_schema::amount.ge(min_amount.cents()));
+        }
+        if let Some(max_amount) = filter.max_amount {
+            query = query.filter(transaction_schema::amount.le(max_amount.cents()));
+        }
+        if let Some(description) = &filter.description {
+            query = query.filter(transaction_schema::description.ilike(like_pattern(description)));
+        }
+
+        query = match (page.order, page.after) {
+            (Order::ASC, Some(after)) => query.filter(
+                transaction_schema::entry_date.gt(after.entry_date).or(
+                    transaction_schema::entry_date
+                        .eq(after.entry_date)
+                        .and(transaction_schema::id.gt(after.id)),
+                ),
+            ),
+            (Order::DESC, Some(after)) => query.filter(
+                transaction_schema::entry_date.lt(after.entry_date).or(
+                    transaction_schema::entry_date
+                        .eq(after.entry_date)
+                        .and(transaction_schema::id.lt(after.id)),
+                ),
+            ),
+            (_, None) => query,
+        };
+
+        query = match page.order {
+            Order::ASC => query.order((
+                transaction_schema::entry_date.asc(),
+                transaction_schema::id.asc(),
+            )),
+            Order::DESC => query.order((
+                transaction_schema::entry_date.desc(),
+                transaction_schema::id.desc(),
+            )),
+        };
+
+        Ok(query
+            .limit(page.first + 1)
+            .load::<Transaction>(&mut self.get()?)
+            .map_err(transaction::TransactionModelError::FailedToListTransactions)?
+            .iter()
+            .map(|t| t.to_entity())
+            .collect())
+    }
+
+    fn edit_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        updated_transaction: transaction::UpdatedTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let old = get_transaction_for_update(conn, id, user_id)?;
+            if old.voided {
+                return Err(transaction::TransactionModelError::TransactionIsVoided);
+            }
+            // An empty changeset is not valid SQL, and there is nothing to do.
+            if updated_transaction.is_empty() {
+                return Ok(old);
+            }
+            apply_transaction_effects(conn, user_id, &old.reversed_balance_effects())?;
+
+            let edited =
+                diesel::update(transaction_schema::table.filter(transaction_schema::id.eq(id)))
+                    .set(updated_transaction.to_model())
+                    .get_result::<Transaction>(conn)
+                    .map(|t| t.to_entity())
+                    .map_err(transaction::TransactionModelError::FailedToUpdateTransaction)?;
+            apply_transaction_effects(conn, user_id, &edited.balance_effects())?;
+            Ok(edited)
+        })
+    }
+
+    fn delete_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let old = get_transaction_for_update(conn, id, user_id)?;
+            apply_transaction_effects(conn, user_id, &old.reversed_balance_effects())?;
+
+            diesel::delete(transaction_schema::table.filter(transaction_schema::id.eq(id)))
+                .execute(conn)
+                .map_err(transaction::TransactionModelError::FailedToDeleteTransaction)?;
+            Ok(old)
+        })
+    }
+
+    fn void_transaction(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let old = get_transaction_for_update(conn, id, user_id)?;
+            if old.voided {
+                return Err(transaction::TransactionModelError::TransactionIsVoided);
+            }
+            apply_transaction_effects(conn, user_id, &old.reversed_balance_effects())?;
+
+            diesel::update(transaction_schema::table.filter(transaction_schema::id.eq(id)))
+                .set(transaction_schema::voided.eq(true))
+                .get_result::<Transaction>(conn)
+                .map(|t| t.to_entity())
+                .map_err(transaction::TransactionModelError::FailedToUpdateTransaction)
+        })
+    }
+
+    fn create_accrual_transaction(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        accrued_until: NaiveDate,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<transaction::Transaction> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let created = diesel::insert_into(transaction_schema::table)
+                .values(&new_transaction.to_model(user_id))
+                .get_result::<Transaction>(conn)
+                .map(|t| t.to_entity())
+                .map_err(transaction::TransactionModelError::FailedToCreateTransaction)?;
+            apply_transaction_effects(conn, user_id, &created.balance_effects())?;
+
+            diesel::update(
+                account_schema::table
+                    .filter(account_schema::id.eq(account_id))
+                    .filter(account_schema::related_user.eq(user_id)),
+            )
+            .set(account_schema::last_accrual_date.eq(accrued_until))
+            .execute(conn)
+            .map_err(transaction::TransactionModelError::FailedToUpdateBalances)?;
+            Ok(created)
+        })
+    }
+
+    fn create_refill_transactions(
+        &self,
+        user_id: &Uuid,
+        account_id: &Uuid,
+        cycle_start: NaiveDate,
+        refilled_on: NaiveDate,
+        new_transactions: Vec<transaction::NewTransaction>,
+    ) -> transaction::Result<Vec<transaction::Transaction>> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            // Locking the envelope keeps two concurrent refills from both
+            // seeing it as pending.
+            let last_refill_dates = account_schema::table
+                .filter(account_schema::id.eq(account_id))
+                .filter(account_schema::related_user.eq(user_id))
+                .select(account_schema::last_refill_date)
+                .for_update()
+                .load::<Option<NaiveDate>>(conn)
+                .map_err(transaction::TransactionModelError::FailedToUpdateBalances)?;
+            match last_refill_dates.as_slice() {
+                [] => return Err(transaction::TransactionModelError::AccountNotFound),
+                [Some(last_refill_date)] if *last_refill_date >= cycle_start => return Ok(vec![]),
+                _ => (),
+            }
+
+            let mut created = Vec::with_capacity(new_transactions.len());
+            for new_transaction in new_transactions {
+                let transaction = diesel::insert_into(transaction_schema::table)
+                    .values(&new_transaction.to_model(user_id))
+                    .get_result::<Transaction>(conn)
+                    .map(|t| t.to_entity())
+                    .map_err(transaction::TransactionModelError::FailedToCreateTransaction)?;
+                apply_transaction_effects(conn, user_id, &transaction.balance_effects())?;
+                created.push(transaction);
+            }
+
+            diesel::update(
+                account_schema::table
+                    .filter(account_schema::id.eq(account_id))
+                    .filter(account_schema::related_user.eq(user_id)),
+            )
+            .set(account_schema::last_refill_date.eq(refilled_on))
+            .execute(conn)
+            .map_err(transaction::TransactionModelError::FailedToUpdateBalances)?;
+            Ok(created)
+        })
+    }
+
+    fn import_transaction(
+        &self,
+        user_id: &Uuid,
+        external_id: &str,
+        new_transaction: transaction::NewTransaction,
+    ) -> transaction::Result<Option<transaction::Transaction>> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, transaction::TransactionModelError, _>(|conn| {
+            let imported = diesel::insert_into(transaction_schema::table)
+                .values(&NewTransaction {
+                    external_id: Some(external_id.to_string()),
+                    ..new_transaction.to_model(user_id)
+                })
+                .on_conflict((
+                    transaction_schema::related_user,
+                    transaction_schema::external_id,
+                ))
+                .do_nothing()
+                .get_result::<Transaction>(conn)
+                .optional()
+                .map_err(transaction::TransactionModelError::FailedToCreateTransaction)?
+                .map(|t| t.to_entity());
+            if let Some(created) = &imported {
+                apply_transaction_effects(conn, user_id, &created.balance_effects())?;
+            }
+            Ok(imported)
+        })
     }
 }
@@ -1,5 +1,6 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use uuid::Uuid;
 
 use crate::{database, entities::user, schema::users as user_schema};
@@ -16,6 +17,13 @@ struct User {
     is_registered: bool,
     name: String,
     payday: Option<i32>,
+    login_code_attempts: i32,
+    login_locked_until: Option<NaiveDateTime>,
+    deletion_scheduled_for: Option<NaiveDateTime>,
+    pending_email: Option<String>,
+    email_change_code: Option<i32>,
+    email_change_requested_at: Option<NaiveDateTime>,
+    email_change_attempts: i32,
 }
 
 #[derive(Insertable, Clone)]
@@ -26,6 +34,24 @@ struct NewUser {
     email: String,
 }
 
+#[derive(AsChangeset)]
+#[diesel(table_name = user_schema)]
+struct UpdatedProfile {
+    name: Option<String>,
+    username: Option<String>,
+    payday: Option<Option<i32>>,
+}
+
+impl user::UpdatedProfile {
+    fn to_model(&self) -> UpdatedProfile {
+        UpdatedProfile {
+            name: self.name.clone(),
+            username: self.username.clone(),
+            payday: self.payday,
+        }
+    }
+}
+
 impl user::NewUser {
     fn to_model(&self) -> NewUser {
         NewUser {
@@ -48,7 +74,40 @@ impl User {
             login_code: self.login_code,
             is_registered: self.is_registered,
             payday: self.payday,
+            deletion_scheduled_for: self.deletion_scheduled_for,
+            pending_email: self.pending_email.clone(),
+        }
+    }
+
+    fn to_login_code_state(&self) -> user::LoginCodeState {
+        user::LoginCodeState {
+            login_code: self.login_code,
+            generated_at: self.last_code_gen_request,
+            locked_until: self.login_locked_until,
+            attempts: self.login_code_attempts,
+        }
+    }
+
+    fn to_email_change(&self) -> user::EmailChange {
+        user::EmailChange {
+            email: self.pending_email.clone(),
+            code: self.email_change_code,
+            requested_at: self.email_change_requested_at,
+            attempts: self.email_change_attempts,
+        }
+    }
+}
+
+fn map_user_error(
+    error: DieselError,
+    otherwise: fn(DieselError) -> user::UserModelError,
+) -> user::UserModelError {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            user::UserModelError::UserAlreadyExists
         }
+        DieselError::NotFound => user::UserModelError::UserDoesNotExists,
+        error => otherwise(error),
     }
 }
 
@@ -67,11 +126,45 @@ impl user::UserModel for database::DbPool {
         }
     }
 
-    fn delete_user(&self, id: &Uuid) -> user::Result<user::User> {
-        diesel::delete(user_schema::table.filter(user_schema::id.eq(id)))
+    // Everything the user owns goes with them, through the foreign keys.
+    fn delete_user(&self, id: &Uuid) -> user::Result<Option<user::User>> {
+        Ok(
+            diesel::delete(user_schema::table.filter(user_schema::id.eq(id)))
+                .get_result::<User>(&mut self.get()?)
+                .optional()
+                .map_err(user::UserModelError::FailedToDeleteUser)?
+                .map(|u| u.to_entity()),
+        )
+    }
+
+    fn schedule_user_deletion(&self, id: &Uuid, at: NaiveDateTime) -> user::Result<user::User> {
+        diesel::update(user_schema::table.filter(user_schema::id.eq(id)))
+            .set(user_schema::deletion_scheduled_for.eq(at))
             .get_result::<User>(&mut self.get()?)
             .map(|u| u.to_entity())
-            .map_err(user::UserModelError::FailedToDeleteUser)
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => user::UserModelError::UserDoesNotExists,
+                err => user::UserModelError::FailedToScheduleDeletion(err),
+            })
+    }
+
+    fn cancel_user_deletion(&self, id: &Uuid) -> user::Result<user::User> {
+        diesel::update(user_schema::table.filter(user_schema::id.eq(id)))
+            .set(user_schema::deletion_scheduled_for.eq(None::<NaiveDateTime>))
+            .get_result::<User>(&mut self.get()?)
+            .map(|u| u.to_entity())
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => user::UserModelError::UserDoesNotExists,
+                err => user::UserModelError::FailedToScheduleDeletion(err),
+            })
+    }
+
+    fn list_users_due_for_deletion(&self, now: NaiveDateTime) -> user::Result<Vec<Uuid>> {
+        user_schema::table
+            .filter(user_schema::deletion_scheduled_for.le(now))
+            .select(user_schema::id)
+            .load::<Uuid>(&mut self.get()?)
+            .map_err(user::UserModelError::FailedToGetUserById)
     }
 
     fn get_user(&self, id: Uuid) -> user::Result<user::User> {
@@ -119,7 +212,7 @@ impl user::UserModel for database::DbPool {
         Ok(())
     }
 
-    fn get_login_code(&self, email: &str) -> user::Result<i32> {
+    fn get_login_code_state(&self, email: &str) -> user::Result<user::LoginCodeState> {
         let result = user_schema::table
             .filter(user_schema::email.eq(email))
             .load::<User>(&mut self.get()?)
@@ -127,15 +220,55 @@ impl user::UserModel for database::DbPool {
 
         match result.as_slice() {
             [] => Err(user::UserModelError::UserDoesNotExists),
-            [User {
-                login_code: Some(l),
-                ..
-            }] => Ok(l.clone()),
-            [_u] => Err(user::UserModelError::UserWithoutLoginCode),
+            [u] => Ok(u.to_login_code_state()),
             _ => Err(user::UserModelError::MoreThanOneEmailError),
         }
     }
 
+    fn attempt_login_code<R, F>(&self, email: &str, check: F) -> user::Result<R>
+    where
+        F: FnOnce(&user::LoginCodeState) -> (user::LoginCodeAttempt, R),
+    {
+        let mut conn = self.get()?;
+        conn.transaction::<_, user::UserModelError, _>(|conn| {
+            let result = user_schema::table
+                .filter(user_schema::email.eq(email))
+                .for_update()
+                .load::<User>(conn)
+                .map_err(user::UserModelError::FailedToGetLoginCode)?;
+            let state = match result.as_slice() {
+                [] => return Err(user::UserModelError::UserDoesNotExists),
+                [u] => u.to_login_code_state(),
+                _ => return Err(user::UserModelError::MoreThanOneEmailError),
+            };
+
+            let (attempt, value) = check(&state);
+            let target = user_schema::table.filter(user_schema::email.eq(email));
+            match attempt {
+                user::LoginCodeAttempt::Accept => diesel::update(target)
+                    .set((
+                        user_schema::login_code.eq(None::<i32>),
+                        user_schema::login_code_attempts.eq(0),
+                        user_schema::login_locked_until.eq(None::<NaiveDateTime>),
+                    ))
+                    .execute(conn),
+                user::LoginCodeAttempt::Fail => diesel::update(target)
+                    .set(user_schema::login_code_attempts.eq(user_schema::login_code_attempts + 1))
+                    .execute(conn),
+                user::LoginCodeAttempt::Lock(until) => diesel::update(target)
+                    .set((
+                        user_schema::login_code.eq(None::<i32>),
+                        user_schema::login_code_attempts.eq(0),
+                        user_schema::login_locked_until.eq(until),
+                    ))
+                    .execute(conn),
+                user::LoginCodeAttempt::Keep => Ok(0),
+            }
+            .map_err(user::UserModelError::FailedToUpdateLoginCode)?;
+            Ok(value)
+        })
+    }
+
     fn get_id_by_email(&self, email: &str) -> user::Result<Uuid> {
         let result = user_schema::table
             .filter(user_schema::email.eq(email))
@@ -148,4 +281,99 @@ impl user::UserModel for database::DbPool {
             _ => Err(user::UserModelError::MoreThanOneEmailError),
         }
     }
+
+    fn set_payday(&self, id: &Uuid, payday: Option<i32>) -> user::Result<user::User> {
+        diesel::update(user_schema::table.filter(user_schema::id.eq(id)))
+            .set(user_schema::payday.eq(payday))
+            .get_result::<User>(&mut self.get()?)
+            .map(|u| u.to_entity())
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => user::UserModelError::UserDoesNotExists,
+                err => user::UserModelError::FailedToUpdatePayday(err),
+            })
+    }
+
+    fn update_profile(
+        &self,
+        id: &Uuid,
+        profile: user::UpdatedProfile,
+        email_change: Option<user::NewEmailChange>,
+    ) -> user::Result<user::User> {
+        let mut conn = self.get()?;
+        conn.transaction::<_, user::UserModelError, _>(|conn| {
+            let target = user_schema::table.filter(user_schema::id.eq(id));
+            if !profile.is_empty() {
+                diesel::update(target)
+                    .set(profile.to_model())
+                    .execute(conn)
+                    .map_err(|err| {
+                        map_user_error(err, user::UserModelError::FailedToUpdateProfile)
+                    })?;
+            }
+            if let Some(change) = email_change {
+                diesel::update(target)
+                    .set((
+                        user_schema::pending_email.eq(change.email),
+                        user_schema::email_change_code.eq(change.code),
+                        user_schema::email_change_requested_at.eq(change.time),
+                        user_schema::email_change_attempts.eq(0),
+                    ))
+                    .execute(conn)
+                    .map_err(|err| {
+                        map_user_error(err, user::UserModelError::FailedToUpdateEmailChange)
+                    })?;
+            }
+            target
+                .get_result::<User>(conn)
+                .map(|u| u.to_entity())
+                .map_err(|err| map_user_error(err, user::UserModelError::FailedToGetUserById))
+        })
+    }
+
+    fn get_email_change(&self, id: &Uuid) -> user::Result<user::EmailChange> {
+        user_schema::table
+            .filter(user_schema::id.eq(id))
+            .get_result::<User>(&mut self.get()?)
+            .map(|u| u.to_email_change())
+            .map_err(|err| map_user_error(err, user::UserModelError::FailedToGetUserById))
+    }
+
+    fn register_failed_email_change_attempt(&self, id: &Uuid) -> user::Result<i32> {
+        diesel::update(user_schema::table.filter(user_schema::id.eq(id)))
+            .set(user_schema::email_change_attempts.eq(user_schema::email_change_attempts + 1))
+            .get_result::<User>(&mut self.get()?)
+            .map(|u| u.email_change_attempts)
+            .map_err(|err| map_user_error(err, user::UserModelError::FailedToUpdateEmailChange))
+    }
+
+    fn confirm_email_change(&self, id: &Uuid, email: &str) -> user::Result<user::User> {
+        diesel::update(
+            user_schema::table
+                .filter(user_schema::id.eq(id))
+                .filter(user_schema::pending_email.eq(email)),
+        )
+        .set((
+            user_schema::email.eq(email),
+            user_schema::pending_email.eq(None::<String>),
+            user_schema::email_change_code.eq(None::<i32>),
+            user_schema::email_change_requested_at.eq(None::<NaiveDateTime>),
+            user_schema::email_change_attempts.eq(0),
+        ))
+        .get_result::<User>(&mut self.get()?)
+        .map(|u| u.to_entity())
+        .map_err(|err| map_user_error(err, user::UserModelError::FailedToUpdateEmailChange))
+    }
+
+    fn cancel_email_change(&self, id: &Uuid) -> user::Result<()> {
+        let _ = diesel::update(user_schema::table.filter(user_schema::id.eq(id)))
+            .set((
+                user_schema::pending_email.eq(None::<String>),
+                user_schema::email_change_code.eq(None::<i32>),
+                user_schema::email_change_requested_at.eq(None::<NaiveDateTime>),
+                user_schema::email_change_attempts.eq(0),
+            ))
+            .get_result::<User>(&mut self.get()?)
+            .map_err(|err| map_user_error(err, user::UserModelError::FailedToUpdateEmailChange))?;
+        Ok(())
+    }
 }
@@ -1,29 +1,18 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read};
 
-use reqwest;
 use serde::Deserialize;
 use serde_json::{self, json};
 
-#[path = "./custom_request_builder.rs"]
-mod custom_request_builder;
-use custom_request_builder::*;
-
-#[path = "../utils/mod.rs"]
-mod utils;
-
-#[path = "./discovery.rs"]
-mod discovery;
+use super::custom_request_builder::*;
+use super::discovery;
+use super::http::{HttpError, HttpRequest, HttpResponse};
+use super::NubankTransport;
+use crate::utils;
 
 #[derive(Debug)]
 pub enum AuthError {
     DiscoveryError(discovery::DiscoveryError),
-    CannotReadCertificate(io::Error),
-    CannotInterpretCertificate(reqwest::Error),
-    CannotBuildClient(reqwest::Error),
-    AuthRequestFailed(reqwest::Error),
-    AuthRequestDecodingFailed(reqwest::Error),
+    AuthRequestFailed(HttpError),
     AuthJsonConversionFailed(serde_json::Error),
     RequiredFieldsNotFoundInAuthJson(AuthDataDTO),
 }
@@ -36,51 +25,70 @@ struct Href {
 #[derive(Deserialize, Debug)]
 pub struct AuthDataDTO {
     access_token: String,
+    refresh_token: String,
     #[serde(rename = "_links")]
     links: HashMap<String, Href>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AuthData {
-    access_token: String,
-    feed_url: String,
+    pub(super) access_token: String,
+    pub(super) refresh_token: String,
+    pub(super) feed_url: String,
+    #[allow(dead_code)]
     bills_url: String,
+    #[allow(dead_code)]
     customer_url: String,
-    query_url: String,
+    pub(super) query_url: String,
+    #[allow(dead_code)]
     revoke_token_url: String,
 }
 
-pub async fn authenticate(path: &str, cpf: &str, password: &str) -> Result<AuthData, AuthError> {
-    let url = discovery::get_url("token".to_string())
+pub async fn authenticate(
+    transport: &NubankTransport,
+    certificate: &[u8],
+    cpf: &str,
+    password: &str,
+) -> Result<AuthData, AuthError> {
+    let url = discovery::get_url(transport, "token".to_string())
         .await
         .map_err(AuthError::DiscoveryError)?;
 
-    let id = get_identity(path)?;
-    let client = build_client(id)?;
-
     let payload = build_payload(cpf, password);
 
-    let response = make_auth_request(client, url, payload).await?;
+    let response = make_auth_request(transport, certificate, url, payload).await?;
     let auth_data_dto = read_request_output(response)?;
-    let auth_data = build_auth_data_obj(auth_data_dto);
 
-    auth_data
+    build_auth_data_obj(auth_data_dto)
 }
 
-fn get_identity(path: &str) -> Result<reqwest::Identity, AuthError> {
-    let mut buf = Vec::new();
-    let _ = File::open(path)
-        .map_err(AuthError::CannotReadCertificate)?
-        .read_to_end(&mut buf);
+// Authenticates again without the credentials. Each refresh token is
+// replaced by the one in the new auth data.
+pub async fn refresh(
+    transport: &NubankTransport,
+    certificate: &[u8],
+    refresh_token: &str,
+) -> Result<AuthData, AuthError> {
+    let url = discovery::get_url(transport, "token".to_string())
+        .await
+        .map_err(AuthError::DiscoveryError)?;
 
-    reqwest::Identity::from_pkcs12_der(&buf, "").map_err(AuthError::CannotInterpretCertificate)
+    let payload = build_refresh_payload(refresh_token);
+
+    let response = make_auth_request(transport, certificate, url, payload).await?;
+    build_auth_data_obj(read_request_output(response)?)
 }
 
-fn build_client(id: reqwest::Identity) -> Result<reqwest::Client, AuthError> {
-    reqwest::Client::builder()
-        .identity(id)
-        .build()
-        .map_err(AuthError::CannotBuildClient)
+fn build_refresh_payload(refresh_token: &str) -> String {
+    json!(
+        {
+            "grant_type": "refresh_token",
+            "client_id": "legacy_client_id",
+            "client_secret": "legacy_client_secret",
+            "refresh_token": refresh_token
+        }
+    )
+    .to_string()
 }
 
 fn build_payload(cpf: &str, password: &str) -> String {
@@ -96,24 +104,28 @@ fn build_payload(cpf: &str, password: &str) -> String {
     .to_string()
 }
 
+// The token endpoint authenticates the device by its client certificate.
 async fn make_auth_request(
-    client: reqwest::Client,
+    transport: &NubankTransport,
+    certificate: &[u8],
     url: String,
     payload: String,
 ) -> Result<String, AuthError> {
-    client
-        .post(url)
+    let request = HttpRequest::post(&url)
         .apply_default_header()
-        .body(payload)
-        .send()
+        .certificate(certificate)
+        .body(payload);
+    Ok(transport
+        .http
+        .send(request)
         .await
+        .and_then(HttpResponse::error_for_status)
         .map_err(AuthError::AuthRequestFailed)?
-        .text()
-        .await
-        .map_err(AuthError::AuthRequestDecodingFailed)
+        .body)
 }
+
 fn read_request_output(result: String) -> Result<AuthDataDTO, AuthError> {
-    serde_json::from_str::<AuthDataDTO>(&*result).map_err(AuthError::AuthJsonConversionFailed)
+    serde_json::from_str::<AuthDataDTO>(&result).map_err(AuthError::AuthJsonConversionFailed)
 }
 
 fn build_auth_data_obj(auth_data_dto: AuthDataDTO) -> Result<AuthData, AuthError> {
@@ -135,6 +147,7 @@ fn build_auth_data_obj(auth_data_dto: AuthDataDTO) -> Result<AuthData, AuthError
             Some(revoke_token_url),
         ) => Ok(AuthData {
             access_token: auth_data_dto.access_token,
+            refresh_token: auth_data_dto.refresh_token,
             feed_url: feed_url.clone().href,
             bills_url: bills_url.clone().href,
             customer_url: customer_url.clone().href,
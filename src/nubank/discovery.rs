@@ -1,29 +1,29 @@
-use reqwest;
 use serde_json::{self, Value};
 
+use super::http::{HttpError, HttpRequest, HttpResponse};
+use super::NubankTransport;
+
 #[derive(Debug)]
 pub enum DiscoveryError {
-    DiscoveryRequestFailed(reqwest::Error),
-    DiscoveryRequestDecodingFailed(reqwest::Error),
+    DiscoveryRequestFailed(HttpError),
     DiscoveryJsonConversionFailed(serde_json::Error),
     UrlNameNotFoundInDiscoveryJson(String),
 }
 
-pub async fn get_url(name: String) -> Result<String, DiscoveryError> {
-    let discovery_app_url = "https://prod-s0-webapp-proxy.nubank.com.br/api/app/discovery";
-
-    let urls_str = reqwest::get(discovery_app_url)
+pub async fn get_url(transport: &NubankTransport, name: String) -> Result<String, DiscoveryError> {
+    let urls_str = transport
+        .http
+        .send(HttpRequest::get(&transport.discovery_url))
         .await
+        .and_then(HttpResponse::error_for_status)
         .map_err(DiscoveryError::DiscoveryRequestFailed)?
-        .text()
-        .await
-        .map_err(DiscoveryError::DiscoveryRequestDecodingFailed)?;
+        .body;
 
-    let urls = serde_json::from_str::<Value>(&*urls_str)
+    let urls = serde_json::from_str::<Value>(&urls_str)
         .map_err(DiscoveryError::DiscoveryJsonConversionFailed)?;
 
     match urls.get(&name) {
-        Some(v) => Ok(v.to_string().replace("\"", "")),
+        Some(v) => Ok(v.to_string().replace('\"', "")),
         _ => Err(DiscoveryError::UrlNameNotFoundInDiscoveryJson(name)),
     }
 }
@@ -0,0 +1,292 @@
+use chrono::{DateTime, NaiveDate};
+use serde_json::{self, json, Value};
+
+use crate::entities::money::Money;
+
+use super::auth::AuthData;
+use super::custom_request_builder::*;
+use super::http::{HttpError, HttpRequest, HttpResponse};
+use super::NubankTransport;
+
+#[derive(Debug)]
+pub enum FeedError {
+    CardFeedRequestFailed(HttpError),
+    AccountFeedRequestFailed(HttpError),
+    FeedJsonConversionFailed(serde_json::Error),
+    UnexpectedFeedFormat(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum EventSource {
+    CARD,
+    ACCOUNT,
+}
+
+// A money movement read from one of the feeds. The amount is always positive
+// and in reais; `inflow` tells its direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEvent {
+    pub source: EventSource,
+    pub id: String,
+    pub date: NaiveDate,
+    pub description: String,
+    pub amount: Money,
+    pub inflow: bool,
+}
+
+// An event that looks like a money movement but is missing required fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreadableEvent {
+    pub source: EventSource,
+    pub raw: String,
+}
+
+pub type FeedItem = Result<FeedEvent, UnreadableEvent>;
+
+const ACCOUNT_FEED_QUERY: &str = "{
+    viewer {
+        savingsAccount {
+            feed {
+                id
+                __typename
+                title
+                detail
+                postDate
+                amount
+            }
+        }
+    }
+}";
+
+// Account events that put money into the account.
+const ACCOUNT_INFLOW_EVENTS: [&str; 5] = [
+    "TransferInEvent",
+    "TransferOutReversalEvent",
+    "DebitPurchaseReversalEvent",
+    "BarcodePaymentFailureEvent",
+    "LendingTransferInEvent",
+];
+
+// Account events that take money out of it. Events of any other type are
+// unreadable, since their direction is unknown.
+const ACCOUNT_OUTFLOW_EVENTS: [&str; 7] = [
+    "TransferOutEvent",
+    "BarcodePaymentEvent",
+    "DebitPurchaseEvent",
+    "DebitWithdrawalEvent",
+    "DebitWithdrawalFeeEvent",
+    "PhoneRechargeSuccessEvent",
+    "LendingTransferOutEvent",
+];
+
+// Card purchases are imported as they happen, so paying the bill from the
+// account would count them twice.
+const CARD_BILL_PAYMENT_EVENT: &str = "BillPaymentEvent";
+
+pub async fn fetch_card_feed(
+    transport: &NubankTransport,
+    auth_data: &AuthData,
+) -> Result<Vec<FeedItem>, FeedError> {
+    let request = HttpRequest::get(&auth_data.feed_url)
+        .apply_default_header()
+        .add_authorization(auth_data.access_token.clone());
+    let body = transport
+        .http
+        .send(request)
+        .await
+        .and_then(HttpResponse::error_for_status)
+        .map_err(FeedError::CardFeedRequestFailed)?
+        .body;
+    parse_card_feed(&body)
+}
+
+pub async fn fetch_account_feed(
+    transport: &NubankTransport,
+    auth_data: &AuthData,
+) -> Result<Vec<FeedItem>, FeedError> {
+    let request = HttpRequest::post(&auth_data.query_url)
+        .apply_default_header()
+        .add_authorization(auth_data.access_token.clone())
+        .body(json!({ "query": ACCOUNT_FEED_QUERY }).to_string());
+    let body = transport
+        .http
+        .send(request)
+        .await
+        .and_then(HttpResponse::error_for_status)
+        .map_err(FeedError::AccountFeedRequestFailed)?
+        .body;
+    parse_account_feed(&body)
+}
+
+fn parse_date(value: &Value) -> Option<NaiveDate> {
+    let text = value.as_str()?;
+    DateTime::parse_from_rfc3339(text)
+        .map(|time| time.naive_local().date())
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok())
+}
+
+// None when the event type is unknown.
+fn is_account_inflow(typename: &str) -> Option<bool> {
+    if ACCOUNT_INFLOW_EVENTS.contains(&typename) {
+        Some(true)
+    } else if ACCOUNT_OUTFLOW_EVENTS.contains(&typename) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn unreadable(source: EventSource, event: &Value) -> UnreadableEvent {
+    UnreadableEvent {
+        source,
+        raw: event.to_string(),
+    }
+}
+
+// Only card events of the "transaction" category are purchases; payments,
+// limit changes and the like are not money movements of the card.
+pub fn parse_card_feed(body: &str) -> Result<Vec<FeedItem>, FeedError> {
+    let feed = serde_json::from_str::<Value>(body).map_err(FeedError::FeedJsonConversionFailed)?;
+    let events = feed
+        .get("events")
+        .and_then(|events| events.as_array())
+        .ok_or_else(|| FeedError::UnexpectedFeedFormat(body.to_string()))?;
+
+    Ok(events
+        .iter()
+        .filter(|event| event.get("category").and_then(|c| c.as_str()) == Some("transaction"))
+        .map(|event| {
+            match (
+                event.get("id").and_then(|id| id.as_str()),
+                event.get("time").and_then(parse_date),
+                event.get("amount").and_then(|amount| amount.as_i64()),
+            ) {
+                (Some(id), Some(date), Some(cents)) => Ok(FeedEvent {
+                    source: EventSource::CARD,
+                    id: id.to_string(),
+                    date,
+                    description: event
+                        .get("description")
+                        .and_then(|d| d.as_str())
+                        .unwrap_or("Nubank card")
+                        .to_string(),
+                    amount: Money::from_cents(cents),
+                    inflow: false,
+                }),
+                _ => Err(unreadable(EventSource::CARD, event)),
+            }
+        })
+        .collect())
+}
+
+// Account events without an amount (welcome messages, notices...) are not
+// money movements and are left out, and so are card bill payments.
+pub fn parse_account_feed(body: &str) -> Result<Vec<FeedItem>, FeedError> {
+    let feed = serde_json::from_str::<Value>(body).map_err(FeedError::FeedJsonConversionFailed)?;
+    let events = feed
+        .pointer("/data/viewer/savingsAccount/feed")
+        .and_then(|events| events.as_array())
+        .ok_or_else(|| FeedError::UnexpectedFeedFormat(body.to_string()))?;
+
+    Ok(events
+        .iter()
+        .filter(|event| {
+            event
+                .get("amount")
+                .map_or(false, |amount| !amount.is_null())
+        })
+        .filter(|event| {
+            event.get("__typename").and_then(|t| t.as_str()) != Some(CARD_BILL_PAYMENT_EVENT)
+        })
+        .map(|event| {
+            let typename = event.get("__typename").and_then(|t| t.as_str());
+            match (
+                event.get("id").and_then(|id| id.as_str()),
+                event.get("postDate").and_then(parse_date),
+                event.get("amount").and_then(|amount| amount.as_f64()),
+                typename,
+                typename.and_then(is_account_inflow),
+            ) {
+                (Some(id), Some(date), Some(amount), Some(typename), Some(inflow)) => {
+                    Ok(FeedEvent {
+                        source: EventSource::ACCOUNT,
+                        id: id.to_string(),
+                        date,
+                        description: match event.get("detail").and_then(|d| d.as_str()) {
+                            Some(detail) => detail.to_string(),
+                            None => typename.to_string(),
+                        },
+                        amount: Money::from_f64(amount.abs()),
+                        inflow,
+                    })
+                }
+                _ => Err(unreadable(EventSource::ACCOUNT, event)),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod feed_tests {
+    use super::*;
+
+    #[test]
+    fn reads_card_purchases_only() {
+        let body = r#"{"events": [
+            {"id": "e1", "category": "transaction", "description": "Padaria", "amount": 1250, "time": "2022-10-01T12:30:00Z"},
+            {"id": "e2", "category": "payment", "description": "Pagamento", "amount": 50000, "time": "2022-10-02T12:30:00Z"},
+            {"id": "e3", "category": "transaction", "description": "Sem data", "amount": 100}
+        ]}"#;
+        let items = parse_card_feed(body).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0],
+            Ok(FeedEvent {
+                source: EventSource::CARD,
+                id: "e1".to_string(),
+                date: NaiveDate::from_ymd(2022, 10, 1),
+                description: "Padaria".to_string(),
+                amount: Money::from_cents(1250),
+                inflow: false,
+            })
+        );
+        assert!(items[1].is_err());
+    }
+
+    #[test]
+    fn reads_account_movements_with_direction() {
+        let body = r#"{"data": {"viewer": {"savingsAccount": {"feed": [
+            {"id": "a1", "__typename": "TransferInEvent", "title": "Transferência recebida", "detail": "Fulano - R$ 100,00", "postDate": "2022-10-03", "amount": 100.0},
+            {"id": "a2", "__typename": "BarcodePaymentEvent", "title": "Pagamento efetuado", "detail": "Conta de luz", "postDate": "2022-10-04", "amount": 80.5},
+            {"id": "a3", "__typename": "WelcomeEvent", "title": "Bem-vindo", "postDate": "2022-10-01", "amount": null},
+            {"id": "a4", "__typename": "BillPaymentEvent", "title": "Pagamento da fatura", "postDate": "2022-10-05", "amount": 57.5}
+        ]}}}}"#;
+        let items = parse_account_feed(body).unwrap();
+        assert_eq!(items.len(), 2);
+        let first = items[0].as_ref().unwrap();
+        assert!(first.inflow);
+        assert_eq!(first.amount, Money::from_cents(10000));
+        let second = items[1].as_ref().unwrap();
+        assert!(!second.inflow);
+        assert_eq!(second.description, "Conta de luz");
+        assert_eq!(second.date, NaiveDate::from_ymd(2022, 10, 4));
+    }
+
+    #[test]
+    fn account_events_of_unknown_type_are_unreadable() {
+        let body = r#"{"data": {"viewer": {"savingsAccount": {"feed": [
+            {"id": "a1", "__typename": "GenericFeedEvent", "title": "Pix", "postDate": "2022-10-03", "amount": 10.0}
+        ]}}}}"#;
+        let items = parse_account_feed(body).unwrap();
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_err());
+    }
+
+    #[test]
+    fn rejects_unexpected_feed_format() {
+        assert!(parse_card_feed(r#"{"error": "Unauthorized"}"#).is_err());
+        assert!(parse_account_feed("not json").is_err());
+    }
+}
@@ -6,36 +6,40 @@ use random_string::generate;
 use serde::{Deserialize, Serialize};
 use serde_json::{self};
 use std::collections::HashMap;
-use std::fs;
 use std::str::{self, Utf8Error};
-#[path = "./discovery.rs"]
-mod discovery;
+
+use super::discovery;
+use super::http::{HttpError, HttpRequest, HttpResponse};
+use super::NubankTransport;
 
 #[derive(Debug)]
 pub enum GenCertError {
     DiscoveryError(discovery::DiscoveryError),
     FailedToGeneratePrivateKey(openssl::error::ErrorStack),
     FailedToGeneratePublicKey(openssl::error::ErrorStack),
+    FailedToExportPrivateKey(openssl::error::ErrorStack),
+    FailedToReadPrivateKey(openssl::error::ErrorStack),
     FailedToConvertPublicKeyToStr(Utf8Error),
-    MainRequestFailed(reqwest::Error),
-    HeaderKeyNotFound(reqwest::header::HeaderMap),
-    FailedToConvertHeaderValueToStr(reqwest::header::ToStrError),
+    FailedToSerializePayload(serde_json::Error),
+    MainRequestFailed(HttpError),
+    HeaderKeyNotFound(HashMap<String, String>),
     FailedToReadHeaderValue(String),
-    HeaderValueKeyNotFound(reqwest::header::HeaderValue),
-    ExchangeCertRequestFailed(reqwest::Error),
-    ExchangeCertRequestDecodingFailed(reqwest::Error),
+    HeaderValueKeyNotFound(String),
+    ExchangeCertRequestFailed(HttpError),
     ExchangeCertJsonConversionFailed(serde_json::Error),
     FailedToGenerateCert(openssl::error::ErrorStack),
-    FailedToWriteCert(std::io::Error),
 }
 
-#[derive(Debug, Clone)]
+// Everything needed to finish the certificate exchange once the user gets
+// the code, except for the credentials, which are asked for again. The
+// private key is kept as PEM so the whole thing can be stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeRequestOutput {
     url: String,
-    payload: PayloadToRequestCode,
+    device: Device,
     pub sent_to: String,
     encrypted_code: String,
-    key1: PKey<Private>,
+    key1: String,
 }
 
 #[derive(Deserialize)]
@@ -43,31 +47,40 @@ pub struct ExchangeCertDTO {
     certificate: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct PayloadToRequestCode {
-    login: String,
-    password: String,
+// The device the certificate is issued to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Device {
     public_key: String,
     public_key_crypto: String,
     model: String,
     device_id: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct PayloadToRequestCode {
+    login: String,
+    password: String,
+    #[serde(flatten)]
+    device: Device,
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct PayloadToGenCert {
     login: String,
     password: String,
-    public_key: String,
-    public_key_crypto: String,
-    model: String,
-    device_id: String,
+    #[serde(flatten)]
+    device: Device,
     code: String,
     #[serde(rename = "encrypted-code")]
     encrypted_code: String,
 }
 
-pub async fn request_code(cpf: &str, password: &str) -> Result<CodeRequestOutput, GenCertError> {
-    let url = discovery::get_url("gen_certificate".to_string())
+pub async fn request_code(
+    transport: &NubankTransport,
+    cpf: &str,
+    password: &str,
+) -> Result<CodeRequestOutput, GenCertError> {
+    let url = discovery::get_url(transport, "gen_certificate".to_string())
         .await
         .map_err(GenCertError::DiscoveryError)?;
 
@@ -76,18 +89,17 @@ pub async fn request_code(cpf: &str, password: &str) -> Result<CodeRequestOutput
 
     let payload = build_payload_to_request_code(cpf, password, &key1, &key2)?;
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&payload)
-        .send()
+    let response = transport
+        .http
+        .send(json_request(&url, &payload)?)
         .await
         .map_err(GenCertError::MainRequestFailed)?;
 
-    let headers = response.headers();
-    let header_value = match headers.get("WWW-Authenticate") {
+    // The code is sent while the request itself is refused with a 401, so
+    // the status is left alone and the challenge header decides.
+    let header_value = match response.header("WWW-Authenticate") {
         Some(header_value) => header_value,
-        None => return Err(GenCertError::HeaderKeyNotFound(headers.to_owned())),
+        None => return Err(GenCertError::HeaderKeyNotFound(response.headers.clone())),
     };
     let parsed_header_value = parse_header_value(header_value)?;
     let (encrypted_code, sent_to) = match (
@@ -95,8 +107,8 @@ pub async fn request_code(cpf: &str, password: &str) -> Result<CodeRequestOutput
         parsed_header_value.get("sent-to"),
     ) {
         (Some(v1), Some(v2)) => (
-            v1.to_string().replace("\"", ""),
-            v2.to_string().replace("\"", ""),
+            v1.to_string().replace('\"', ""),
+            v2.to_string().replace('\"', ""),
         ),
         _ => {
             return Err(GenCertError::HeaderValueKeyNotFound(
@@ -109,51 +121,56 @@ pub async fn request_code(cpf: &str, password: &str) -> Result<CodeRequestOutput
         url,
         sent_to,
         encrypted_code,
-        payload,
-        key1,
+        device: payload.device,
+        key1: get_private_key_pem(&key1)?,
     })
 }
 
+// Returns the PKCS#12 certificate, DER encoded and without a password.
 pub async fn exchange_certs(
-    cert_folder: &str,
+    transport: &NubankTransport,
     code_request_output: CodeRequestOutput,
+    cpf: &str,
+    password: &str,
     code: &str,
-) -> Result<String, GenCertError> {
+) -> Result<Vec<u8>, GenCertError> {
     let payload = build_payload_to_gen_cert(
-        code_request_output.payload.clone(),
-        &code_request_output.encrypted_code.clone(),
+        cpf,
+        password,
+        code_request_output.device.clone(),
+        &code_request_output.encrypted_code,
         code,
     );
 
     let url = code_request_output.url.clone();
 
-    let client = reqwest::Client::new();
-    let response_str = client
-        .post(url)
-        .json(&payload)
-        .send()
+    let response_str = transport
+        .http
+        .send(json_request(&url, &payload)?)
         .await
+        .and_then(HttpResponse::error_for_status)
         .map_err(GenCertError::ExchangeCertRequestFailed)?
-        .text()
-        .await
-        .map_err(GenCertError::ExchangeCertRequestDecodingFailed)?;
+        .body;
 
-    let key = code_request_output.key1.clone();
-    let cert_str = serde_json::from_str::<ExchangeCertDTO>(&*response_str)
+    let key = PKey::private_key_from_pem(code_request_output.key1.as_bytes())
+        .map_err(GenCertError::FailedToReadPrivateKey)?;
+    let cert_str = serde_json::from_str::<ExchangeCertDTO>(&response_str)
         .map_err(GenCertError::ExchangeCertJsonConversionFailed)?
         .certificate;
 
-    let cert_bin = get_cert_bin(&cert_str, &key)?;
+    get_cert_bin(&cert_str, &key)
+}
 
-    let full_path = format!("{}/{}", cert_folder, "cert.p12");
-    let _ = fs::write(&full_path, cert_bin).map_err(GenCertError::FailedToWriteCert)?;
-    Ok(full_path)
+fn json_request<T: Serialize>(url: &str, payload: &T) -> Result<HttpRequest, GenCertError> {
+    let body = serde_json::to_string(payload).map_err(GenCertError::FailedToSerializePayload)?;
+    Ok(HttpRequest::post(url)
+        .header("Content-Type", "application/json")
+        .body(body))
 }
 
 fn gen_private_key() -> Result<PKey<Private>, GenCertError> {
     let rsa = Rsa::generate(2048).map_err(GenCertError::FailedToGeneratePrivateKey)?;
-    let private_key = PKey::from_rsa(rsa).map_err(GenCertError::FailedToGeneratePrivateKey);
-    private_key
+    PKey::from_rsa(rsa).map_err(GenCertError::FailedToGeneratePrivateKey)
 }
 
 fn build_payload_to_request_code(
@@ -171,25 +188,26 @@ fn build_payload_to_request_code(
     Ok(PayloadToRequestCode {
         login: cpf.to_string(),
         password: password.to_string(),
-        public_key,
-        public_key_crypto,
-        model,
-        device_id,
+        device: Device {
+            public_key,
+            public_key_crypto,
+            model,
+            device_id,
+        },
     })
 }
 
 fn build_payload_to_gen_cert(
-    old_payload: PayloadToRequestCode,
+    cpf: &str,
+    password: &str,
+    device: Device,
     encrypted_code: &str,
     code: &str,
 ) -> PayloadToGenCert {
     PayloadToGenCert {
-        login: old_payload.login,
-        password: old_payload.password,
-        public_key: old_payload.public_key,
-        public_key_crypto: old_payload.public_key_crypto,
-        model: old_payload.model,
-        device_id: old_payload.device_id,
+        login: cpf.to_string(),
+        password: password.to_string(),
+        device,
         code: code.to_string(),
         encrypted_code: encrypted_code.to_string(),
     }
@@ -205,15 +223,17 @@ fn get_public_key(key: &PKey<Private>) -> Result<String, GenCertError> {
     Ok(pub_key_str)
 }
 
-fn parse_header_value(
-    header_value: &reqwest::header::HeaderValue,
-) -> Result<HashMap<String, String>, GenCertError> {
-    let header_value_str = header_value
-        .to_str()
-        .map_err(GenCertError::FailedToConvertHeaderValueToStr)?;
+fn get_private_key_pem(key: &PKey<Private>) -> Result<String, GenCertError> {
+    let pem = key
+        .private_key_to_pem_pkcs8()
+        .map_err(GenCertError::FailedToExportPrivateKey)?;
+    Ok(String::from_utf8_lossy(&pem).into_owned())
+}
+
+fn parse_header_value(header_value_str: &str) -> Result<HashMap<String, String>, GenCertError> {
     header_value_str
-        .split(",")
-        .map(|chunk| chunk.split("="))
+        .split(',')
+        .map(|chunk| chunk.split('='))
         .fold(Ok(HashMap::new()), |acc, items| {
             combine_header_item(header_value_str, acc, items)
         })
@@ -222,24 +242,22 @@ fn parse_header_value(
 fn combine_header_item(
     header_value_str: &str,
     acc: Result<HashMap<String, String>, GenCertError>,
-    mut item: str::Split<&str>,
+    mut item: str::Split<char>,
 ) -> Result<HashMap<String, String>, GenCertError> {
     let key = item.next();
     let value = item.next();
     match (key, value) {
         (Some(k), Some(v)) => {
-            let parsed_key = k.trim().replace(" ", "_");
-            let parsed_value = v.replace(" ", "_");
+            let parsed_key = k.trim().replace(' ', "_");
+            let parsed_value = v.replace(' ', "_");
             acc.map(|mut hm| {
                 hm.insert(parsed_key, parsed_value);
                 hm
             })
         }
-        _ => {
-            return Err(GenCertError::FailedToReadHeaderValue(
-                header_value_str.to_string(),
-            ))
-        }
+        _ => Err(GenCertError::FailedToReadHeaderValue(
+            header_value_str.to_string(),
+        )),
     }
 }
 
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Method {
+    GET,
+    POST,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    // PKCS#12 client certificate, DER encoded and without a password.
+    pub certificate: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    // Header names are lowercase.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum HttpError {
+    CannotInterpretCertificate(reqwest::Error),
+    CannotBuildClient(reqwest::Error),
+    RequestFailed(reqwest::Error),
+    ResponseDecodingFailed(reqwest::Error),
+    // The status and body of a response outside of 2xx.
+    UnexpectedStatus(u16, String),
+}
+
+// Every call to Nubank goes through this trait, so the whole flow can run
+// against a fake server in tests.
+#[async_trait]
+pub trait NubankHttp: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpError>;
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> HttpRequest {
+        HttpRequest::new(Method::GET, url)
+    }
+
+    pub fn post(url: &str) -> HttpRequest {
+        HttpRequest::new(Method::POST, url)
+    }
+
+    fn new(method: Method, url: &str) -> HttpRequest {
+        HttpRequest {
+            method,
+            url: url.to_string(),
+            headers: vec![],
+            body: None,
+            certificate: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> HttpRequest {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(self, body: String) -> HttpRequest {
+        HttpRequest {
+            body: Some(body),
+            ..self
+        }
+    }
+
+    pub fn certificate(self, certificate: &[u8]) -> HttpRequest {
+        HttpRequest {
+            certificate: Some(certificate.to_vec()),
+            ..self
+        }
+    }
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers.get(&name.to_lowercase())
+    }
+
+    // Nubank answers errors with a JSON body too, so it is turned into an
+    // error before anything tries to read it as a success.
+    pub fn error_for_status(self) -> Result<HttpResponse, HttpError> {
+        match self.status {
+            200..=299 => Ok(self),
+            status => Err(HttpError::UnexpectedStatus(status, self.body)),
+        }
+    }
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+}
+
+// Requests share one client and its connections. Reqwest ties the client
+// certificate to the client, so the few requests that carry one get a client
+// of their own, with the same timeouts.
+pub struct ReqwestHttp {
+    client: reqwest::Client,
+}
+
+impl ReqwestHttp {
+    pub fn new() -> Result<ReqwestHttp, HttpError> {
+        Ok(ReqwestHttp {
+            client: client_builder()
+                .build()
+                .map_err(HttpError::CannotBuildClient)?,
+        })
+    }
+}
+
+#[async_trait]
+impl NubankHttp for ReqwestHttp {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpError> {
+        let client = match &request.certificate {
+            None => self.client.clone(),
+            Some(certificate) => {
+                let identity = reqwest::Identity::from_pkcs12_der(certificate, "")
+                    .map_err(HttpError::CannotInterpretCertificate)?;
+                client_builder()
+                    .identity(identity)
+                    .build()
+                    .map_err(HttpError::CannotBuildClient)?
+            }
+        };
+
+        let mut builder = match request.method {
+            Method::GET => client.get(&request.url),
+            Method::POST => client.post(&request.url),
+        };
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send().await.map_err(HttpError::RequestFailed)?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_lowercase(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let body = response
+            .text()
+            .await
+            .map_err(HttpError::ResponseDecodingFailed)?;
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod http_tests {
+    use super::*;
+
+    fn response(status: u16) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers: HashMap::new(),
+            body: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn only_2xx_responses_are_successes() {
+        assert!(response(200).error_for_status().is_ok());
+        assert!(response(204).error_for_status().is_ok());
+        for status in [301, 401, 404, 500] {
+            assert!(matches!(
+                response(status).error_for_status(),
+                Err(HttpError::UnexpectedStatus(s, _)) if s == status
+            ));
+        }
+    }
+}
@@ -1,19 +1,66 @@
 mod auth;
+mod custom_request_builder;
+mod discovery;
+mod feed;
 mod gen_cert;
-use std::io::{self};
+mod http;
+
+pub use feed::{EventSource, FeedEvent, FeedItem};
+pub use http::{HttpError, HttpRequest, HttpResponse, Method, NubankHttp, ReqwestHttp};
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_DISCOVERY_URL: &str =
+    "https://prod-s0-webapp-proxy.nubank.com.br/api/app/discovery";
 
 #[derive(Debug)]
 pub enum NubankError {
     AuthError(auth::AuthError),
     GenCertError(gen_cert::GenCertError),
+    FeedError(feed::FeedError),
     GenCertBeforeRequestCode(),
+    AuthenticateBeforeGenCert(),
+    RefreshBeforeAuthenticate(),
+    FetchBeforeAuthenticate(),
+}
+
+// Where and how Nubank is reached. Every other URL comes from the discovery
+// endpoint, so pointing it somewhere else redirects the whole flow.
+#[derive(Clone)]
+pub struct NubankTransport {
+    pub discovery_url: String,
+    pub http: Arc<dyn NubankHttp>,
 }
 
-#[derive(Debug, Clone)]
+impl NubankTransport {
+    pub fn new(discovery_url: String) -> Result<NubankTransport, HttpError> {
+        Ok(NubankTransport {
+            discovery_url,
+            http: Arc::new(ReqwestHttp::new()?),
+        })
+    }
+}
+
+// The client is serializable so an onboarding that waits for the user's code
+// can be stored and resumed, and so later syncs can authenticate with the
+// refresh token. Credentials and access tokens are never stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NubankClient {
+    #[serde(skip)]
     auth_data: Option<auth::AuthData>,
     code_request_output: Option<gen_cert::CodeRequestOutput>,
     pub sent_to: Option<String>,
+    certificate: Option<Vec<u8>>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+impl Default for NubankClient {
+    fn default() -> NubankClient {
+        NubankClient::new()
+    }
 }
 
 impl NubankClient {
@@ -22,20 +69,28 @@ impl NubankClient {
             auth_data: None,
             code_request_output: None,
             sent_to: None,
+            certificate: None,
+            refresh_token: None,
         }
     }
 
     pub async fn authenticate(
         &self,
-        cert_path: &str,
+        transport: &NubankTransport,
         cpf: &str,
         password: &str,
     ) -> Result<Self, NubankError> {
-        let auth_data = auth::authenticate(cert_path, cpf, password)
+        let certificate = match &self.certificate {
+            Some(certificate) => certificate,
+            None => return Err(NubankError::AuthenticateBeforeGenCert()),
+        };
+
+        let auth_data = auth::authenticate(transport, certificate, cpf, password)
             .await
             .map_err(NubankError::AuthError)?;
 
         let new_client = NubankClient {
+            refresh_token: Some(auth_data.refresh_token.clone()),
             auth_data: Some(auth_data),
             ..self.clone()
         };
@@ -43,12 +98,33 @@ impl NubankClient {
         Ok(new_client)
     }
 
+    // Authenticates with the refresh token of the last authentication, which
+    // is replaced by a new one.
+    pub async fn refresh(&self, transport: &NubankTransport) -> Result<Self, NubankError> {
+        let (certificate, refresh_token) = match (&self.certificate, &self.refresh_token) {
+            (Some(certificate), Some(refresh_token)) => (certificate, refresh_token),
+            (None, _) => return Err(NubankError::AuthenticateBeforeGenCert()),
+            (_, None) => return Err(NubankError::RefreshBeforeAuthenticate()),
+        };
+
+        let auth_data = auth::refresh(transport, certificate, refresh_token)
+            .await
+            .map_err(NubankError::AuthError)?;
+
+        Ok(NubankClient {
+            refresh_token: Some(auth_data.refresh_token.clone()),
+            auth_data: Some(auth_data),
+            ..self.clone()
+        })
+    }
+
     pub async fn request_code_to_gen_cert(
         &self,
+        transport: &NubankTransport,
         cpf: &str,
         password: &str,
     ) -> Result<Self, NubankError> {
-        let req_output = gen_cert::request_code(cpf, password)
+        let req_output = gen_cert::request_code(transport, cpf, password)
             .await
             .map_err(NubankError::GenCertError)?;
 
@@ -62,18 +138,48 @@ impl NubankClient {
 
     pub async fn gen_certificate(
         &self,
-        cert_folder: &str,
+        transport: &NubankTransport,
+        cpf: &str,
+        password: &str,
         code: &str,
-    ) -> Result<String, NubankError> {
+    ) -> Result<Self, NubankError> {
         let code_req_output = match self.code_request_output.clone() {
             Some(code_req_output) => code_req_output,
             None => return Err(NubankError::GenCertBeforeRequestCode()),
         };
 
-        let cert_path = gen_cert::exchange_certs(cert_folder, code_req_output, code)
+        let certificate = gen_cert::exchange_certs(transport, code_req_output, cpf, password, code)
             .await
             .map_err(NubankError::GenCertError)?;
 
-        Ok(cert_path)
+        // The code request output holds the private key, which is no longer
+        // needed once the certificate is issued.
+        let new_client = NubankClient {
+            code_request_output: None,
+            certificate: Some(certificate),
+            ..self.clone()
+        };
+        Ok(new_client)
+    }
+
+    // Card purchases followed by account movements.
+    pub async fn fetch_events(
+        &self,
+        transport: &NubankTransport,
+    ) -> Result<Vec<FeedItem>, NubankError> {
+        let auth_data = match &self.auth_data {
+            Some(auth_data) => auth_data,
+            None => return Err(NubankError::FetchBeforeAuthenticate()),
+        };
+
+        let mut items = feed::fetch_card_feed(transport, auth_data)
+            .await
+            .map_err(NubankError::FeedError)?;
+        items.extend(
+            feed::fetch_account_feed(transport, auth_data)
+                .await
+                .map_err(NubankError::FeedError)?,
+        );
+        Ok(items)
     }
 }
@@ -4,6 +4,21 @@ pub mod sql_types {
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "earning_index_enum"))]
     pub struct EarningIndexEnum;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "integration_status_enum"))]
+    pub struct IntegrationStatusEnum;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "transaction_kind_enum"))]
+    pub struct TransactionKindEnum;
+}
+
+diesel::table! {
+    account_tags (account_id, tag_id) {
+        account_id -> Uuid,
+        tag_id -> Uuid,
+    }
 }
 
 diesel::table! {
@@ -16,36 +31,94 @@ diesel::table! {
         time -> Timestamp,
         name -> Text,
         description -> Nullable<Text>,
-        last_calculated_balance -> Float8,
+        last_calculated_balance -> Int8,
         is_pre_allocation -> Bool,
-        pre_allocation_amount -> Nullable<Float8>,
+        pre_allocation_amount -> Nullable<Int8>,
         pre_allocation_accumulative -> Nullable<Bool>,
         is_earning -> Bool,
-        earning_rate -> Nullable<Float8>,
+        earning_rate -> Nullable<Int4>,
         earning_index -> Nullable<EarningIndexEnum>,
         is_available -> Bool,
         in_trash -> Bool,
+        initial_balance -> Int8,
+        last_accrual_date -> Nullable<Date>,
+        pre_allocation_source -> Nullable<Uuid>,
+        last_refill_date -> Nullable<Date>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::EarningIndexEnum;
+
+    index_rates (earning_index, date) {
+        earning_index -> EarningIndexEnum,
+        date -> Date,
+        rate -> Float8,
+    }
+}
+
+diesel::table! {
+    rotated_refresh_tokens (token_hash) {
+        token_hash -> Text,
+        session_id -> Uuid,
+        rotated_at -> Timestamp,
     }
 }
 
 diesel::table! {
+    sessions (id) {
+        id -> Uuid,
+        related_user -> Uuid,
+        refresh_token_hash -> Text,
+        device_name -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Uuid,
+        related_user -> Uuid,
+        name -> Text,
+        color -> Nullable<Text>,
+        time -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TransactionKindEnum;
+
     transactions (id) {
         id -> Uuid,
         related_user -> Uuid,
         entry_date -> Date,
         entry_account_code -> Nullable<Uuid>,
         exit_account_code -> Nullable<Uuid>,
-        amount -> Float8,
+        amount -> Int8,
         description -> Nullable<Text>,
+        voided -> Bool,
+        kind -> TransactionKindEnum,
+        external_id -> Nullable<Text>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::IntegrationStatusEnum;
+
     user_integrations (id) {
         id -> Uuid,
         related_user -> Uuid,
         name -> Text,
         time -> Timestamp,
+        status -> IntegrationStatusEnum,
+        sent_to -> Nullable<Text>,
+        state -> Nullable<Bytea>,
+        account_id -> Nullable<Uuid>,
     }
 }
 
@@ -60,11 +133,27 @@ diesel::table! {
         is_registered -> Bool,
         name -> Text,
         payday -> Nullable<Int4>,
+        login_code_attempts -> Int4,
+        login_locked_until -> Nullable<Timestamp>,
+        deletion_scheduled_for -> Nullable<Timestamp>,
+        pending_email -> Nullable<Text>,
+        email_change_code -> Nullable<Int4>,
+        email_change_requested_at -> Nullable<Timestamp>,
+        email_change_attempts -> Int4,
     }
 }
 
+diesel::joinable!(account_tags -> accounts (account_id));
+diesel::joinable!(account_tags -> tags (tag_id));
+diesel::joinable!(rotated_refresh_tokens -> sessions (session_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    account_tags,
     accounts,
+    index_rates,
+    rotated_refresh_tokens,
+    sessions,
+    tags,
     transactions,
     user_integrations,
     users,
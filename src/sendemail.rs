@@ -1,4 +0,0 @@
-pub fn send_code(email: &str, code: &i32) -> () {
-    println!("The code for {} is: {}", email, code);
-    ()
-}
@@ -0,0 +1,12 @@
+use super::{Email, Mailer, MailerError};
+
+// Only logs the emails, for local runs without a mail server. They carry
+// login codes, so they are never logged above debug.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, email: &Email) -> Result<(), MailerError> {
+        log::debug!("Email to {}: {}\n{}", email.to, email.subject, email.text);
+        Ok(())
+    }
+}
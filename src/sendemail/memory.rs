@@ -0,0 +1,23 @@
+use std::sync::Mutex;
+
+use super::{Email, Mailer, MailerError};
+
+// Keeps the emails instead of sending them, for the tests.
+#[derive(Default)]
+pub struct InMemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl InMemoryMailer {
+    #[allow(dead_code)]
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Mailer for InMemoryMailer {
+    fn send(&self, email: &Email) -> Result<(), MailerError> {
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
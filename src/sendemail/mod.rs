@@ -0,0 +1,99 @@
+mod log;
+mod memory;
+mod outbox;
+mod smtp;
+pub mod templates;
+
+pub use self::log::LogMailer;
+pub use memory::InMemoryMailer;
+pub use outbox::OutboxMailer;
+pub use smtp::SmtpMailer;
+
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+#[derive(Debug)]
+pub enum MailerError {
+    InvalidAddress(lettre::address::AddressError),
+    FailedToBuildEmail(lettre::error::Error),
+    InvalidSmtpUrl(String),
+    SmtpFailed(lettre::transport::smtp::Error),
+    OutboxFailed(lettre::transport::file::Error),
+}
+
+impl From<lettre::address::AddressError> for MailerError {
+    fn from(error: lettre::address::AddressError) -> Self {
+        MailerError::InvalidAddress(error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+// Everything sent to users goes through this trait, so it can be delivered
+// by SMTP, kept as files, logged or kept in memory for the tests.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailerError>;
+}
+
+fn build_message(from: &Mailbox, email: &Email) -> Result<Message, MailerError> {
+    Message::builder()
+        .from(from.clone())
+        .to(email.to.parse()?)
+        .subject(email.subject.clone())
+        .multipart(MultiPart::alternative_plain_html(
+            email.text.clone(),
+            email.html.clone(),
+        ))
+        .map_err(MailerError::FailedToBuildEmail)
+}
+
+#[cfg(test)]
+mod sendemail_tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn email() -> Email {
+        templates::login_code("user@example.com", 123456, crate::entities::Language::EN)
+    }
+
+    #[test]
+    fn outbox_writes_eml_files() {
+        let dir = std::env::temp_dir().join(format!("cashtools-outbox-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let mailer = OutboxMailer::new(dir.clone(), "Cashtools <no-reply@example.com>").unwrap();
+        mailer.send(&email()).unwrap();
+
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("To: user@example.com"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_recipient_is_an_error() {
+        let mailer = InMemoryMailer::default();
+        let outbox = OutboxMailer::new(std::env::temp_dir(), "no-reply@example.com").unwrap();
+        let email = Email {
+            to: "not an address".to_string(),
+            ..email()
+        };
+        assert!(matches!(
+            outbox.send(&email),
+            Err(MailerError::InvalidAddress(_))
+        ));
+        mailer.send(&email).unwrap();
+        assert_eq!(mailer.sent(), vec![email]);
+    }
+}
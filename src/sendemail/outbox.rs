@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use lettre::message::Mailbox;
+use lettre::{FileTransport, Transport};
+
+use super::{build_message, Email, Mailer, MailerError};
+
+// Writes every email as an `.eml` file to a directory instead of sending it.
+pub struct OutboxMailer {
+    transport: FileTransport,
+    from: Mailbox,
+}
+
+impl OutboxMailer {
+    pub fn new(dir: PathBuf, from: &str) -> Result<OutboxMailer, MailerError> {
+        Ok(OutboxMailer {
+            transport: FileTransport::new(dir),
+            from: from.parse()?,
+        })
+    }
+}
+
+impl Mailer for OutboxMailer {
+    fn send(&self, email: &Email) -> Result<(), MailerError> {
+        let message = build_message(&self.from, email)?;
+        self.transport
+            .send(&message)
+            .map_err(MailerError::OutboxFailed)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use crate::entities::Language;
+
+use super::Email;
+
+struct CodeText {
+    subject: &'static str,
+    greeting: &'static str,
+    instructions: &'static str,
+    ignore: &'static str,
+}
+
+const LOGIN_CODE_PT: CodeText = CodeText {
+    subject: "Seu código de acesso ao Cashtools",
+    greeting: "Olá!",
+    instructions: "Use este código para entrar no Cashtools. Ele vale por 10 minutos e só pode ser usado uma vez:",
+    ignore: "Se você não pediu este código, pode ignorar este email.",
+};
+
+const LOGIN_CODE_EN: CodeText = CodeText {
+    subject: "Your Cashtools login code",
+    greeting: "Hi!",
+    instructions:
+        "Use this code to log in to Cashtools. It is valid for 10 minutes and works only once:",
+    ignore: "If you did not ask for this code, you can ignore this email.",
+};
+
+const EMAIL_CHANGE_PT: CodeText = CodeText {
+    subject: "Confirme seu novo email no Cashtools",
+    greeting: "Olá!",
+    instructions: "Use este código para confirmar que este é o seu novo email no Cashtools. Ele vale por 10 minutos e só pode ser usado uma vez:",
+    ignore: "Se você não pediu esta troca, pode ignorar este email e nada vai mudar.",
+};
+
+const EMAIL_CHANGE_EN: CodeText = CodeText {
+    subject: "Confirm your new Cashtools email",
+    greeting: "Hi!",
+    instructions: "Use this code to confirm this is your new Cashtools email. It is valid for 10 minutes and works only once:",
+    ignore: "If you did not ask for this change, you can ignore this email and nothing will change.",
+};
+
+pub fn login_code(to: &str, code: i32, language: Language) -> Email {
+    let t = match language {
+        Language::PT => LOGIN_CODE_PT,
+        Language::EN => LOGIN_CODE_EN,
+    };
+    code_email(to, code, t, language)
+}
+
+pub fn email_change_code(to: &str, code: i32, language: Language) -> Email {
+    let t = match language {
+        Language::PT => EMAIL_CHANGE_PT,
+        Language::EN => EMAIL_CHANGE_EN,
+    };
+    code_email(to, code, t, language)
+}
+
+fn code_email(to: &str, code: i32, t: CodeText, language: Language) -> Email {
+    let lang = match language {
+        Language::PT => "pt-BR",
+        Language::EN => "en",
+    };
+    Email {
+        to: to.to_string(),
+        subject: t.subject.to_string(),
+        text: format!(
+            "{}\n\n{}\n\n{}\n\n{}\n",
+            t.greeting, t.instructions, code, t.ignore
+        ),
+        html: format!(
+            "<!DOCTYPE html>\n\
+             <html lang=\"{}\">\n\
+             <body style=\"font-family: sans-serif;\">\n\
+             <p>{}</p>\n\
+             <p>{}</p>\n\
+             <p style=\"font-size: 32px; font-weight: bold; letter-spacing: 4px;\">{}</p>\n\
+             <p style=\"color: #666666;\">{}</p>\n\
+             </body>\n\
+             </html>\n",
+            lang, t.greeting, t.instructions, code, t.ignore
+        ),
+    }
+}
+
+#[cfg(test)]
+mod templates_tests {
+    use super::*;
+
+    #[test]
+    fn login_code_is_in_both_bodies() {
+        for language in [Language::PT, Language::EN] {
+            let email = login_code("user@example.com", 123456, language);
+            assert_eq!(email.to, "user@example.com");
+            assert!(email.text.contains("123456"));
+            assert!(email.html.contains("123456"));
+        }
+    }
+
+    #[test]
+    fn login_code_follows_the_language() {
+        let pt = login_code("user@example.com", 123456, Language::PT);
+        let en = login_code("user@example.com", 123456, Language::EN);
+        assert!(pt.subject.contains("código"));
+        assert!(en.subject.contains("code"));
+        assert!(pt.html.contains("lang=\"pt-BR\""));
+    }
+
+    #[test]
+    fn email_change_code_is_not_a_login_code() {
+        let email = email_change_code("new@example.com", 123456, Language::EN);
+        assert_eq!(email.to, "new@example.com");
+        assert!(email.text.contains("123456"));
+        assert_ne!(
+            email.subject,
+            login_code("new@example.com", 123456, Language::EN).subject
+        );
+    }
+}
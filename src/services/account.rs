@@ -1,20 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use chrono::{NaiveDate, Utc};
+use chrono::NaiveDate;
 
 use log;
 use uuid::Uuid;
 
 use crate::{
-    entities::{account, transaction},
-    jwt,
+    entities::{account, money::Money, tag, transaction, user},
+    services::{budget, cycle::user_cycle_of},
 };
 
 #[derive(Debug)]
 pub enum AccountServiceError {
     AccountModelFailed(account::AccountModelError),
     TransactionModelFailed(transaction::TransactionModelError),
-    JwtError(jwt::JwtError),
+    TagModelFailed(tag::TagModelError),
+    UserModelFailed(user::UserModelError),
+    NameAlreadyTaken,
 }
 
 impl fmt::Display for AccountServiceError {
@@ -25,7 +28,10 @@ impl fmt::Display for AccountServiceError {
 
 impl From<account::AccountModelError> for AccountServiceError {
     fn from(error: account::AccountModelError) -> Self {
-        AccountServiceError::AccountModelFailed(error)
+        match error {
+            account::AccountModelError::NameAlreadyTaken => AccountServiceError::NameAlreadyTaken,
+            error => AccountServiceError::AccountModelFailed(error),
+        }
     }
 }
 
@@ -35,62 +41,66 @@ impl From<transaction::TransactionModelError> for AccountServiceError {
     }
 }
 
-impl From<jwt::JwtError> for AccountServiceError {
-    fn from(error: jwt::JwtError) -> Self {
-        AccountServiceError::JwtError(error)
+impl From<tag::TagModelError> for AccountServiceError {
+    fn from(error: tag::TagModelError) -> Self {
+        AccountServiceError::TagModelFailed(error)
+    }
+}
+
+impl From<user::UserModelError> for AccountServiceError {
+    fn from(error: user::UserModelError) -> Self {
+        AccountServiceError::UserModelFailed(error)
     }
 }
 
 pub type Result<T> = std::result::Result<T, AccountServiceError>;
 
-pub fn auth_and_create_account<T: account::AccountModel>(
+pub fn create_account<T: account::AccountModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     new_account: account::NewAccount,
 ) -> Result<account::Account> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    log::debug!("Related user: {:?}", id);
-    Ok(database.create_account(id, new_account)?)
+    log::debug!("Related user: {:?}", user_id);
+    Ok(database.create_account(*user_id, new_account)?)
 }
 
-pub fn auth_and_delete_account<T: account::AccountModel>(
+pub fn delete_account<T: account::AccountModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     id: &Uuid,
 ) -> Result<()> {
-    let user_id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    Ok(database.delete_account(id, &user_id)?)
+    Ok(database.delete_account(id, user_id)?)
 }
 
-pub fn auth_and_edit_account<T: account::AccountModel>(
+pub fn edit_account<T: account::AccountModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     id: &Uuid,
     updated_account: account::UpdatedAccount,
 ) -> Result<account::Account> {
-    let user_id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    Ok(database.edit_account(id, &user_id, updated_account)?)
+    Ok(database.edit_account(id, user_id, updated_account)?)
 }
 
-pub fn preallocate<T: account::AccountModel + transaction::TransactionModel>(
+// Turns `to` into an envelope funded by `from` and gives it its first refill
+// when it has not been refilled in the current cycle yet.
+pub fn preallocate<T: account::AccountModel + transaction::TransactionModel + user::UserModel>(
     database: &T,
     user_id: &Uuid,
     time: NaiveDate,
     from: &Uuid,
     to: &Uuid,
-    amount: f64,
+    amount: Money,
     accumulative: bool,
 ) -> Result<account::PreAllocation> {
     let pre_allocation_obj = account::PreAllocation {
         amount,
         accumulative,
+        source: Some(*from),
     };
-    let _ = database.edit_account(
-        user_id,
+    let _ = database.get_account(from, user_id)?;
+    let envelope = database.edit_account(
         to,
+        user_id,
         account::UpdatedAccount {
             name: None,
             description: None,
@@ -100,80 +110,205 @@ pub fn preallocate<T: account::AccountModel + transaction::TransactionModel>(
             in_trash: None,
         },
     )?;
-    let _ = database.create_transaction(
+    let cycle = user_cycle_of(database, user_id, time)?;
+    let _ = database.create_refill_transactions(
         user_id,
-        transaction::NewTransaction {
-            entry_date: time,
-            entry_account_code: Some(from.clone()),
-            exit_account_code: Some(to.clone()),
-            amount,
-            description: Some("Preallocation transaction".to_string()),
-        },
+        to,
+        cycle.start,
+        time,
+        budget::refill_transactions(&envelope, &pre_allocation_obj, from, time),
     )?;
     Ok(pre_allocation_obj)
 }
 
-pub fn auth_and_preallocate<T: account::AccountModel + transaction::TransactionModel>(
-    database: &T,
-    token: &str,
-    jwt_secret: &str,
-    time: NaiveDate,
-    from: &Uuid,
-    to: &Uuid,
-    amount: f64,
-    accumulative: bool,
-) -> Result<account::PreAllocation> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    preallocate(database, &id, time, from, to, amount, accumulative)
-}
-
-pub fn auth_and_get_account<T: account::AccountModel>(
+pub fn get_account<T: account::AccountModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     id: &Uuid,
 ) -> Result<account::Account> {
-    let user_id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    let account = database.get_account(&id, &user_id)?;
+    let account = database.get_account(id, user_id)?;
     Ok(account)
 }
 
-pub fn auth_and_get_accounts<T: account::AccountModel>(
+pub fn recalculate_balances<T: account::AccountModel>(
+    database: &T,
+    user_id: &Uuid,
+) -> Result<Vec<account::Account>> {
+    Ok(database.recalculate_balances(user_id)?)
+}
+
+pub fn get_accounts<T: account::AccountModel + tag::TagModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     is_pre_allocation: Option<bool>,
     in_trash: Option<bool>,
     tags: Option<Vec<Uuid>>,
+    tag_match: tag::TagMatch,
 ) -> Result<Vec<account::Account>> {
-    let user_id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    let accounts = database.get_accounts(&user_id)?;
+    let accounts = database.get_accounts(user_id)?;
+    let tags_by_account = match tags {
+        Some(_) => tags_by_account(database.list_account_tags(user_id)?),
+        None => HashMap::new(),
+    };
     Ok(accounts
         .iter()
-        .filter(filter_accounts(is_pre_allocation, in_trash, tags))
+        .filter(filter_accounts(
+            is_pre_allocation,
+            in_trash,
+            tags,
+            tag_match,
+            tags_by_account,
+        ))
         .cloned()
         .collect())
 }
 
+fn tags_by_account(account_tags: Vec<tag::AccountTag>) -> HashMap<Uuid, HashSet<Uuid>> {
+    let mut tags_by_account: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    for account_tag in account_tags {
+        tags_by_account
+            .entry(account_tag.account_id)
+            .or_default()
+            .insert(account_tag.tag_id);
+    }
+    tags_by_account
+}
+
 fn filter_accounts(
     is_pre_allocation: Option<bool>,
     in_trash: Option<bool>,
     tags: Option<Vec<Uuid>>,
+    tag_match: tag::TagMatch,
+    tags_by_account: HashMap<Uuid, HashSet<Uuid>>,
 ) -> impl Fn(&&account::Account) -> bool {
+    let no_tags = HashSet::new();
     move |account: &&account::Account| {
-        let pre_allocation_filter = match is_pre_allocation.clone() {
+        let pre_allocation_filter = match is_pre_allocation {
             None => true,
             Some(f) => account.pre_allocation.is_none() != f,
         };
-        let in_trash_filter = match in_trash.clone() {
+        let in_trash_filter = match in_trash {
             None => true,
             Some(f) => account.in_trash == f,
         };
-        let tags_filter = match tags.clone() {
+        let tags_filter = match &tags {
             None => true,
-            // TODO: Add tags table and implement this filter correctly
-            Some(_) => true,
+            Some(wanted) => {
+                let account_tags = tags_by_account.get(&account.id).unwrap_or(&no_tags);
+                match tag_match {
+                    tag::TagMatch::ANY => wanted.iter().any(|t| account_tags.contains(t)),
+                    tag::TagMatch::ALL => wanted.iter().all(|t| account_tags.contains(t)),
+                }
+            }
         };
         pre_allocation_filter & in_trash_filter & tags_filter
     }
 }
+
+#[cfg(test)]
+mod account_tests {
+    use super::*;
+    use crate::entities::user::UserModel;
+    use crate::models::memory::{new_account, InMemoryStore};
+
+    fn new_user(database: &InMemoryStore, username: &str) -> Uuid {
+        database
+            .create_user(user::NewUser {
+                name: "Account Test".to_string(),
+                username: username.to_string(),
+                email: format!("{}@example.com", username),
+            })
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn names_are_unique_per_user_until_trashed() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let other_user_id = Uuid::new_v4();
+
+        let wallet =
+            create_account(&database, &user_id, new_account("Wallet", Money::ZERO)).unwrap();
+        assert!(create_account(
+            &database,
+            &other_user_id,
+            new_account("Wallet", Money::ZERO)
+        )
+        .is_ok());
+        assert!(matches!(
+            create_account(&database, &user_id, new_account("Wallet", Money::ZERO)),
+            Err(AccountServiceError::NameAlreadyTaken)
+        ));
+
+        delete_account(&database, &user_id, &wallet.id).unwrap();
+        assert!(
+            get_account(&database, &user_id, &wallet.id)
+                .unwrap()
+                .in_trash
+        );
+        assert!(create_account(&database, &user_id, new_account("Wallet", Money::ZERO)).is_ok());
+    }
+
+    #[test]
+    fn accounts_of_other_users_are_not_found() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let wallet =
+            create_account(&database, &user_id, new_account("Wallet", Money::ZERO)).unwrap();
+
+        assert!(matches!(
+            get_account(&database, &Uuid::new_v4(), &wallet.id),
+            Err(AccountServiceError::AccountModelFailed(
+                account::AccountModelError::AccountNotFound
+            ))
+        ));
+        assert!(delete_account(&database, &Uuid::new_v4(), &wallet.id).is_err());
+        assert!(
+            !get_account(&database, &user_id, &wallet.id)
+                .unwrap()
+                .in_trash
+        );
+    }
+
+    #[test]
+    fn preallocation_refills_the_envelope_once_per_cycle() {
+        let database = InMemoryStore::default();
+        let user_id = new_user(&database, "preallocate");
+        let source = create_account(
+            &database,
+            &user_id,
+            new_account("Salary", Money::from_cents(100000)),
+        )
+        .unwrap();
+        let envelope =
+            create_account(&database, &user_id, new_account("Groceries", Money::ZERO)).unwrap();
+        let today = NaiveDate::from_ymd(2022, 3, 10);
+        let balance = |id: &Uuid| get_account(&database, &user_id, id).unwrap().balance;
+
+        for _ in 0..2 {
+            let pre_allocation = preallocate(
+                &database,
+                &user_id,
+                today,
+                &source.id,
+                &envelope.id,
+                Money::from_cents(30000),
+                true,
+            )
+            .unwrap();
+            assert_eq!(pre_allocation.source, Some(source.id));
+        }
+        assert_eq!(balance(&source.id), Money::from_cents(70000));
+        assert_eq!(balance(&envelope.id), Money::from_cents(30000));
+
+        // Recalculating from the transactions gives the same balances.
+        let recalculated: HashMap<Uuid, Money> = recalculate_balances(&database, &user_id)
+            .unwrap()
+            .iter()
+            .map(|account| (account.id, account.balance))
+            .collect();
+        assert_eq!(recalculated[&source.id], Money::from_cents(70000));
+        assert_eq!(recalculated[&envelope.id], Money::from_cents(30000));
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+use log;
+use uuid::Uuid;
+
+use crate::{
+    entities::{account, budget, money::Money, transaction, user},
+    services::cycle::user_cycle_of,
+};
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum BudgetServiceError {
+    AccountModelFailed(account::AccountModelError),
+    TransactionModelFailed(transaction::TransactionModelError),
+    UserModelFailed(user::UserModelError),
+}
+
+impl fmt::Display for BudgetServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<account::AccountModelError> for BudgetServiceError {
+    fn from(error: account::AccountModelError) -> Self {
+        BudgetServiceError::AccountModelFailed(error)
+    }
+}
+
+impl From<transaction::TransactionModelError> for BudgetServiceError {
+    fn from(error: transaction::TransactionModelError) -> Self {
+        BudgetServiceError::TransactionModelFailed(error)
+    }
+}
+
+impl From<user::UserModelError> for BudgetServiceError {
+    fn from(error: user::UserModelError) -> Self {
+        BudgetServiceError::UserModelFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, BudgetServiceError>;
+
+// Transactions that move an envelope into a new cycle: non-accumulative
+// envelopes first sweep what is left back to the funding account, then every
+// envelope receives its amount.
+pub fn refill_transactions(
+    envelope: &account::Account,
+    pre_allocation: &account::PreAllocation,
+    source: &Uuid,
+    today: NaiveDate,
+) -> Vec<transaction::NewTransaction> {
+    let mut transactions = Vec::new();
+    let leftover = envelope.balance;
+    if !pre_allocation.accumulative && leftover > Money::ZERO {
+        transactions.push(transaction::NewTransaction {
+            entry_date: today,
+            entry_account_code: Some(*source),
+            exit_account_code: Some(envelope.id),
+            amount: leftover,
+            description: Some(format!("Sweep of {}", envelope.name)),
+            kind: transaction::TransactionKind::SWEEP,
+        });
+    }
+    if pre_allocation.amount > Money::ZERO {
+        transactions.push(transaction::NewTransaction {
+            entry_date: today,
+            entry_account_code: Some(envelope.id),
+            exit_account_code: Some(*source),
+            amount: pre_allocation.amount,
+            description: Some(format!("Refill of {}", envelope.name)),
+            kind: transaction::TransactionKind::REFILL,
+        });
+    }
+    transactions
+}
+
+fn is_pending_refill(envelope: &account::Account, cycle_start: NaiveDate) -> bool {
+    match envelope.last_refill_date {
+        Some(last_refill_date) => last_refill_date < cycle_start,
+        None => true,
+    }
+}
+
+pub fn refill_envelopes<
+    T: account::AccountModel + transaction::TransactionModel + user::UserModel,
+>(
+    database: &T,
+    user_id: &Uuid,
+    today: NaiveDate,
+) -> Result<Vec<transaction::TransactionWithNames>> {
+    let cycle_start = user_cycle_of(database, user_id, today)?.start;
+    let accounts = database.get_accounts(user_id)?;
+    let names: HashMap<Uuid, String> = accounts
+        .iter()
+        .filter(|account| !account.in_trash)
+        .map(|account| (account.id, account.name.clone()))
+        .collect();
+
+    let mut posted = Vec::new();
+    for envelope in accounts.iter() {
+        let pre_allocation = match (envelope.pre_allocation, envelope.in_trash) {
+            (Some(pre_allocation), false) => pre_allocation,
+            _ => continue,
+        };
+        let source = match pre_allocation.source {
+            Some(source) => source,
+            None => continue,
+        };
+        // Envelopes funded by a trashed or unknown account stay as they are.
+        if !names.contains_key(&source) || !is_pending_refill(envelope, cycle_start) {
+            continue;
+        }
+
+        let created = database.create_refill_transactions(
+            user_id,
+            &envelope.id,
+            cycle_start,
+            today,
+            refill_transactions(envelope, &pre_allocation, &source, today),
+        )?;
+        posted.extend(created.iter().map(|t| {
+            t.with_names(
+                t.entry_account_code.and_then(|id| names.get(&id).cloned()),
+                t.exit_account_code.and_then(|id| names.get(&id).cloned()),
+            )
+        }));
+    }
+    Ok(posted)
+}
+
+// Refills the envelopes of every user, logging failures so one broken
+// envelope does not hold back everybody else.
+pub fn refill_all_envelopes<
+    T: account::AccountModel + transaction::TransactionModel + user::UserModel,
+>(
+    database: &T,
+    today: NaiveDate,
+) -> Result<Vec<transaction::TransactionWithNames>> {
+    let mut posted = Vec::new();
+    for user_id in database.list_users_with_envelopes()? {
+        match refill_envelopes(database, &user_id, today) {
+            Ok(transactions) => posted.extend(transactions),
+            Err(err) => log::error!("Failed to refill envelopes of {}: {}", user_id, err),
+        }
+    }
+    Ok(posted)
+}
+
+pub fn envelope_status(
+    envelope: &account::Account,
+    transactions: &[transaction::Transaction],
+) -> budget::EnvelopeStatus {
+    let mut allocated = Money::ZERO;
+    let mut spent = Money::ZERO;
+    for t in transactions.iter().filter(|t| !t.voided) {
+        match t.kind {
+            transaction::TransactionKind::REFILL if t.entry_account_code == Some(envelope.id) => {
+                allocated += t.amount
+            }
+            transaction::TransactionKind::REFILL | transaction::TransactionKind::SWEEP => (),
+            _ if t.exit_account_code == Some(envelope.id) => spent += t.amount,
+            _ => (),
+        }
+    }
+    budget::EnvelopeStatus {
+        account_id: envelope.id,
+        name: envelope.name.clone(),
+        accumulative: envelope.pre_allocation.map_or(false, |p| p.accumulative),
+        allocated,
+        spent,
+        remaining: envelope.balance,
+    }
+}
+
+pub fn budget_status<T: account::AccountModel + transaction::TransactionModel + user::UserModel>(
+    database: &T,
+    user_id: &Uuid,
+    today: NaiveDate,
+) -> Result<budget::BudgetStatus> {
+    let cycle = user_cycle_of(database, user_id, today)?;
+    let transactions = database.list_user_transactions_between(user_id, cycle.start, cycle.end)?;
+    let envelopes = database
+        .get_accounts(user_id)?
+        .iter()
+        .filter(|account| account.pre_allocation.is_some() && !account.in_trash)
+        .map(|envelope| envelope_status(envelope, &transactions))
+        .collect();
+    Ok(budget::BudgetStatus {
+        cycle_start: cycle.start,
+        cycle_end: cycle.end,
+        envelopes,
+    })
+}
+
+#[cfg(test)]
+mod budget_tests {
+    use super::*;
+    use crate::entities::{account::AccountModel, user::UserModel};
+    use crate::models::memory::{new_account, InMemoryStore};
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn envelope(balance: Money, accumulative: bool) -> account::Account {
+        account::Account {
+            id: Uuid::new_v4(),
+            time: day(2022, 1, 1).and_hms(0, 0, 0),
+            name: "Groceries".to_string(),
+            description: None,
+            balance,
+            pre_allocation: Some(account::PreAllocation {
+                amount: Money::from_cents(50000),
+                accumulative,
+                source: Some(Uuid::new_v4()),
+            }),
+            earning: None,
+            is_available: true,
+            in_trash: false,
+            last_accrual_date: None,
+            last_refill_date: None,
+        }
+    }
+
+    #[test]
+    fn accumulative_envelope_keeps_leftover() {
+        let envelope = envelope(Money::from_cents(12000), true);
+        let pre_allocation = envelope.pre_allocation.unwrap();
+        let source = pre_allocation.source.unwrap();
+        let transactions =
+            refill_transactions(&envelope, &pre_allocation, &source, day(2022, 3, 1));
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].kind, transaction::TransactionKind::REFILL);
+        assert_eq!(transactions[0].entry_account_code, Some(envelope.id));
+        assert_eq!(transactions[0].exit_account_code, Some(source));
+        assert_eq!(transactions[0].amount, Money::from_cents(50000));
+    }
+
+    #[test]
+    fn non_accumulative_envelope_sweeps_leftover_back() {
+        let envelope = envelope(Money::from_cents(12000), false);
+        let pre_allocation = envelope.pre_allocation.unwrap();
+        let source = pre_allocation.source.unwrap();
+        let transactions =
+            refill_transactions(&envelope, &pre_allocation, &source, day(2022, 3, 1));
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].kind, transaction::TransactionKind::SWEEP);
+        assert_eq!(transactions[0].entry_account_code, Some(source));
+        assert_eq!(transactions[0].exit_account_code, Some(envelope.id));
+        assert_eq!(transactions[0].amount, Money::from_cents(12000));
+        assert_eq!(transactions[1].kind, transaction::TransactionKind::REFILL);
+    }
+
+    #[test]
+    fn overspent_envelope_is_not_swept() {
+        let envelope = envelope(Money::from_cents(-3000), false);
+        let pre_allocation = envelope.pre_allocation.unwrap();
+        let source = pre_allocation.source.unwrap();
+        let transactions =
+            refill_transactions(&envelope, &pre_allocation, &source, day(2022, 3, 1));
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].kind, transaction::TransactionKind::REFILL);
+    }
+
+    #[test]
+    fn refill_is_pending_once_per_cycle() {
+        let mut envelope = envelope(Money::ZERO, true);
+        assert!(is_pending_refill(&envelope, day(2022, 3, 1)));
+        envelope.last_refill_date = Some(day(2022, 2, 1));
+        assert!(is_pending_refill(&envelope, day(2022, 3, 1)));
+        envelope.last_refill_date = Some(day(2022, 3, 1));
+        assert!(!is_pending_refill(&envelope, day(2022, 3, 1)));
+    }
+
+    #[test]
+    fn envelopes_of_every_user_are_refilled_once_per_cycle() {
+        let database = InMemoryStore::default();
+        let user_id = database
+            .create_user(user::NewUser {
+                name: "Budget Test".to_string(),
+                username: "budget".to_string(),
+                email: "budget@example.com".to_string(),
+            })
+            .unwrap()
+            .id;
+        // The March cycle of a payday on the 10th ends on 2022-04-07.
+        database.set_payday(&user_id, Some(10)).unwrap();
+        let source = database
+            .create_account(
+                user_id,
+                account::NewAccount {
+                    time: day(2022, 1, 1).and_hms(0, 0, 0),
+                    ..new_account("Salary", Money::from_cents(100000))
+                },
+            )
+            .unwrap();
+        let envelope = database
+            .create_account(
+                user_id,
+                account::NewAccount {
+                    time: day(2022, 1, 1).and_hms(0, 0, 0),
+                    pre_allocation: Some(account::PreAllocation {
+                        amount: Money::from_cents(50000),
+                        accumulative: false,
+                        source: Some(source.id),
+                    }),
+                    ..new_account("Groceries", Money::ZERO)
+                },
+            )
+            .unwrap();
+        let balance = |id: &Uuid| database.get_account(id, &user_id).unwrap().balance;
+
+        let posted = refill_all_envelopes(&database, day(2022, 3, 10)).unwrap();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0].related_user, user_id);
+        assert!(refill_all_envelopes(&database, day(2022, 4, 7))
+            .unwrap()
+            .is_empty());
+        assert_eq!(balance(&source.id), Money::from_cents(50000));
+        assert_eq!(balance(&envelope.id), Money::from_cents(50000));
+
+        let status = budget_status(&database, &user_id, day(2022, 3, 10)).unwrap();
+        assert_eq!(status.cycle_end, day(2022, 4, 7));
+        assert_eq!(status.envelopes[0].allocated, Money::from_cents(50000));
+
+        let posted = refill_envelopes(&database, &user_id, day(2022, 4, 8)).unwrap();
+        let kinds: Vec<transaction::TransactionKind> = posted.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                transaction::TransactionKind::SWEEP,
+                transaction::TransactionKind::REFILL
+            ]
+        );
+        assert_eq!(posted[1].entry_account_name, Some("Groceries".to_string()));
+        assert_eq!(balance(&source.id), Money::from_cents(50000));
+        assert_eq!(balance(&envelope.id), Money::from_cents(50000));
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt;
+
+use chrono::{Duration, NaiveDate};
+use uuid::Uuid;
+
+use crate::entities::{cycle, user};
+
+#[derive(Debug)]
+pub enum CycleServiceError {
+    UserModelFailed(user::UserModelError),
+    InvalidCycleRange(NaiveDate, NaiveDate),
+}
+
+impl fmt::Display for CycleServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<user::UserModelError> for CycleServiceError {
+    fn from(error: user::UserModelError) -> Self {
+        CycleServiceError::UserModelFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CycleServiceError>;
+
+// Keeps a single query from listing centuries of cycles.
+const MAX_CYCLE_RANGE_DAYS: i64 = 10 * 366;
+
+// The cycle of `date` according to the user's payday. Returns the model
+// error so every service can convert it into its own.
+pub fn user_cycle_of<T: user::UserModel>(
+    database: &T,
+    user_id: &Uuid,
+    date: NaiveDate,
+) -> user::Result<cycle::Cycle> {
+    let user = database.get_user(*user_id)?;
+    Ok(cycle::cycle_of(date, user.payday))
+}
+
+pub fn current_cycle<T: user::UserModel>(
+    database: &T,
+    user_id: &Uuid,
+    today: NaiveDate,
+) -> Result<cycle::Cycle> {
+    Ok(user_cycle_of(database, user_id, today)?)
+}
+
+pub fn list_cycles<T: user::UserModel>(
+    database: &T,
+    user_id: &Uuid,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<cycle::Cycle>> {
+    if to < from || to - from > Duration::days(MAX_CYCLE_RANGE_DAYS) {
+        return Err(CycleServiceError::InvalidCycleRange(from, to));
+    }
+    let user = database.get_user(*user_id)?;
+    Ok(cycle::cycles_between(from, to, user.payday))
+}
@@ -0,0 +1,520 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use uuid::Uuid;
+
+use crate::entities::{account, earning, money::Money, transaction};
+
+#[derive(Debug)]
+pub enum EarningServiceError {
+    AccountModelFailed(account::AccountModelError),
+    TransactionModelFailed(transaction::TransactionModelError),
+    IndexRateModelFailed(earning::IndexRateModelError),
+    AccountIsNotEarning,
+    CannotReadIndexRates(std::io::Error),
+    InvalidIndexRateLine(usize, String),
+}
+
+impl fmt::Display for EarningServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<account::AccountModelError> for EarningServiceError {
+    fn from(error: account::AccountModelError) -> Self {
+        EarningServiceError::AccountModelFailed(error)
+    }
+}
+
+impl From<transaction::TransactionModelError> for EarningServiceError {
+    fn from(error: transaction::TransactionModelError) -> Self {
+        EarningServiceError::TransactionModelFailed(error)
+    }
+}
+
+impl From<earning::IndexRateModelError> for EarningServiceError {
+    fn from(error: earning::IndexRateModelError) -> Self {
+        EarningServiceError::IndexRateModelFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, EarningServiceError>;
+
+const BUSINESS_DAYS_PER_YEAR: f64 = 252.0;
+
+// Whether accruals may use only published rates or extend the latest ones
+// into days that have not been published yet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RateMode {
+    Published,
+    Projected,
+}
+
+pub struct IndexRates {
+    cdi: BTreeMap<NaiveDate, f64>,
+    ipca: BTreeMap<NaiveDate, f64>,
+}
+
+impl IndexRates {
+    pub fn new(rates: Vec<earning::IndexRate>) -> IndexRates {
+        let mut cdi = BTreeMap::new();
+        let mut ipca = BTreeMap::new();
+        for rate in rates {
+            match rate.index {
+                account::EarningIndex::CDI => cdi.insert(rate.date, rate.rate),
+                account::EarningIndex::IPCA => ipca.insert(month_start(rate.date), rate.rate),
+                account::EarningIndex::FIXED => None,
+            };
+        }
+        IndexRates { cdi, ipca }
+    }
+
+    fn cdi_on(&self, date: NaiveDate, mode: RateMode) -> Option<f64> {
+        if let Some(rate) = self.cdi.get(&date) {
+            return Some(*rate);
+        }
+        let (last_date, last_rate) = self.cdi.iter().next_back()?;
+        if *last_date > date {
+            // CDI is not published on bank holidays, so nothing accrues.
+            Some(0.0)
+        } else {
+            match mode {
+                RateMode::Published => None,
+                RateMode::Projected => Some(*last_rate),
+            }
+        }
+    }
+
+    // IPCA is only published after its month ends. Projections let the
+    // latest known month stand in for the months that are still open.
+    fn ipca_on(&self, date: NaiveDate, mode: RateMode) -> Option<f64> {
+        match mode {
+            RateMode::Published => self.ipca.get(&month_start(date)).copied(),
+            RateMode::Projected => self
+                .ipca
+                .range(..=month_start(date))
+                .next_back()
+                .map(|(_, rate)| *rate),
+        }
+    }
+}
+
+pub fn is_business_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+fn month_start(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd(date.year(), date.month(), 1)
+}
+
+fn business_days_in_month(date: NaiveDate) -> u32 {
+    let start = month_start(date);
+    start
+        .iter_days()
+        .take_while(|d| d.month() == start.month())
+        .filter(|d| is_business_day(*d))
+        .count() as u32
+}
+
+fn annual_to_daily(annual_percentage: f64) -> f64 {
+    (1.0 + annual_percentage / 100.0).powf(1.0 / BUSINESS_DAYS_PER_YEAR) - 1.0
+}
+
+// Yield rate of a single day, or None if the index rate for it is unknown.
+pub fn daily_rate(
+    earning: &account::Earning,
+    date: NaiveDate,
+    rates: &IndexRates,
+    mode: RateMode,
+) -> Option<f64> {
+    if !is_business_day(date) {
+        return Some(0.0);
+    }
+    match earning.index {
+        account::EarningIndex::FIXED => Some(annual_to_daily(earning.rate)),
+        account::EarningIndex::CDI => {
+            let cdi = rates.cdi_on(date, mode)?;
+            Some(annual_to_daily(cdi) * earning.rate / 100.0)
+        }
+        account::EarningIndex::IPCA => {
+            let monthly = rates.ipca_on(date, mode)?;
+            let inflation = (1.0 + monthly / 100.0).powf(1.0 / business_days_in_month(date) as f64);
+            Some(inflation * (1.0 + annual_to_daily(earning.rate)) - 1.0)
+        }
+    }
+}
+
+// Accrues day by day from `from` to `until`, starting from the `opening`
+// balance and applying `movements` at the end of their day, so each day
+// yields on the balance the account had then. Stops at the first day without
+// a known rate and returns the last accrued day with the earnings up to it,
+// or None if not even `from` could be accrued.
+pub fn accrue(
+    opening: f64,
+    movements: &BTreeMap<NaiveDate, f64>,
+    earning: &account::Earning,
+    from: NaiveDate,
+    until: NaiveDate,
+    rates: &IndexRates,
+    mode: RateMode,
+) -> Option<(NaiveDate, f64)> {
+    let mut result = None;
+    let mut balance = opening;
+    let mut earnings = 0.0;
+    for date in from.iter_days().take_while(|d| *d <= until) {
+        let rate = match daily_rate(earning, date, rates, mode) {
+            Some(rate) => rate,
+            None => break,
+        };
+        let current = balance + earnings;
+        if current > 0.0 {
+            earnings += current * rate;
+        }
+        balance += movements.get(&date).copied().unwrap_or(0.0);
+        result = Some((date, earnings));
+    }
+    result
+}
+
+fn accrual_start(account: &account::Account) -> NaiveDate {
+    account
+        .last_accrual_date
+        .unwrap_or_else(|| account.time.date())
+        + Duration::days(1)
+}
+
+// The balance the account had before `from` and how much it moved on each
+// day since then.
+fn balance_history<T: transaction::TransactionModel>(
+    database: &T,
+    user_id: &Uuid,
+    account: &account::Account,
+    from: NaiveDate,
+) -> Result<(f64, BTreeMap<NaiveDate, f64>)> {
+    let mut movements = BTreeMap::new();
+    let mut opening = account.balance.to_f64();
+    for transaction in database.list_user_transactions_between(user_id, from, NaiveDate::MAX)? {
+        for (id, delta) in transaction.balance_effects() {
+            if id == account.id {
+                *movements.entry(transaction.entry_date).or_insert(0.0) += delta.to_f64();
+                opening -= delta.to_f64();
+            }
+        }
+    }
+    Ok((opening, movements))
+}
+
+fn load_rates<T: earning::IndexRateModel>(database: &T) -> Result<IndexRates> {
+    let mut rates = database.list_index_rates(account::EarningIndex::CDI)?;
+    rates.extend(database.list_index_rates(account::EarningIndex::IPCA)?);
+    Ok(IndexRates::new(rates))
+}
+
+pub fn accrue_earnings<
+    T: account::AccountModel + transaction::TransactionModel + earning::IndexRateModel,
+>(
+    database: &T,
+    user_id: &Uuid,
+    until: NaiveDate,
+    today: NaiveDate,
+) -> Result<Vec<transaction::TransactionWithNames>> {
+    // Days that have not ended yet cannot be paid, today included.
+    let until = until.min(today - Duration::days(1));
+    let rates = load_rates(database)?;
+    let mut posted = Vec::new();
+
+    for account in database.get_accounts(user_id)? {
+        let earning = match (account.earning, account.in_trash) {
+            (Some(earning), false) => earning,
+            _ => continue,
+        };
+        let from = accrual_start(&account);
+        if from > until {
+            continue;
+        }
+        let (opening, movements) = balance_history(database, user_id, &account, from)?;
+        let accrued = accrue(
+            opening,
+            &movements,
+            &earning,
+            from,
+            until,
+            &rates,
+            RateMode::Published,
+        );
+        let (accrued_until, earnings) = match accrued {
+            Some(accrued) => accrued,
+            None => continue,
+        };
+
+        // Sub-cent yields are left to be paid together with the next accrual.
+        let amount = Money::from_f64(earnings);
+        if amount <= Money::ZERO {
+            continue;
+        }
+        let created = database.create_accrual_transaction(
+            user_id,
+            &account.id,
+            accrued_until,
+            transaction::NewTransaction {
+                entry_date: accrued_until,
+                entry_account_code: Some(account.id),
+                exit_account_code: None,
+                amount,
+                description: Some(format!("Earnings ({:?})", earning.index)),
+                kind: transaction::TransactionKind::EARNING,
+            },
+        )?;
+        posted.push(created.with_names(Some(account.name.clone()), None));
+    }
+    Ok(posted)
+}
+
+// Accrues the earnings of every user up to `today`, logging failures so one
+// broken account does not hold back everybody else.
+pub fn accrue_all_earnings<
+    T: account::AccountModel + transaction::TransactionModel + earning::IndexRateModel,
+>(
+    database: &T,
+    today: NaiveDate,
+) -> Result<Vec<transaction::TransactionWithNames>> {
+    let mut posted = Vec::new();
+    for user_id in database.list_users_with_earnings()? {
+        match accrue_earnings(database, &user_id, today, today) {
+            Ok(transactions) => posted.extend(transactions),
+            Err(err) => log::error!("Failed to accrue earnings of {}: {}", user_id, err),
+        }
+    }
+    Ok(posted)
+}
+
+pub fn project_earnings<
+    T: account::AccountModel + transaction::TransactionModel + earning::IndexRateModel,
+>(
+    database: &T,
+    user_id: &Uuid,
+    account_id: &Uuid,
+    until: NaiveDate,
+) -> Result<earning::EarningProjection> {
+    let account = database.get_account(account_id, user_id)?;
+    let earning = account
+        .earning
+        .ok_or(EarningServiceError::AccountIsNotEarning)?;
+
+    let from = accrual_start(&account);
+    let rates = load_rates(database)?;
+    let (opening, movements) = balance_history(database, user_id, &account, from)?;
+    let projected_balance = accrue(
+        opening,
+        &movements,
+        &earning,
+        from,
+        until,
+        &rates,
+        RateMode::Projected,
+    )
+    .map_or(account.balance, |(_, earnings)| {
+        account.balance + Money::from_f64(earnings)
+    });
+
+    Ok(earning::EarningProjection {
+        account_id: account.id,
+        from,
+        until,
+        balance: account.balance,
+        projected_balance,
+        projected_earnings: projected_balance - account.balance,
+    })
+}
+
+// Reads "INDEX,YYYY-MM-DD,RATE" lines. Blank lines, lines starting with '#'
+// and an "index,date,rate" header are ignored.
+pub fn parse_index_rates(content: &str) -> Result<Vec<earning::IndexRate>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter(|(_, line)| !line.to_lowercase().starts_with("index,"))
+        .map(|(n, line)| parse_index_rate_line(n, line))
+        .collect()
+}
+
+fn parse_index_rate_line(line_number: usize, line: &str) -> Result<earning::IndexRate> {
+    let invalid = || EarningServiceError::InvalidIndexRateLine(line_number, line.to_string());
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    match fields.as_slice() {
+        [index, date, rate] => Ok(earning::IndexRate {
+            index: match index.to_uppercase().as_str() {
+                "CDI" => account::EarningIndex::CDI,
+                "IPCA" => account::EarningIndex::IPCA,
+                _ => return Err(invalid()),
+            },
+            date: date.parse().map_err(|_| invalid())?,
+            rate: rate.parse().map_err(|_| invalid())?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn load_index_rates_file<T: earning::IndexRateModel>(
+    database: &T,
+    path: &str,
+) -> Result<usize> {
+    let content = fs::read_to_string(path).map_err(EarningServiceError::CannotReadIndexRates)?;
+    Ok(database.save_index_rates(parse_index_rates(&content)?)?)
+}
+
+#[cfg(test)]
+mod earning_tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        // March 2023 starts on a Wednesday.
+        NaiveDate::from_ymd(2023, 3, d)
+    }
+
+    fn rates() -> IndexRates {
+        IndexRates::new(vec![
+            earning::IndexRate {
+                index: account::EarningIndex::CDI,
+                date: day(1),
+                rate: 13.65,
+            },
+            earning::IndexRate {
+                index: account::EarningIndex::CDI,
+                date: day(3),
+                rate: 13.65,
+            },
+            earning::IndexRate {
+                index: account::EarningIndex::IPCA,
+                date: day(1),
+                rate: 0.71,
+            },
+        ])
+    }
+
+    #[test]
+    fn nothing_accrues_on_weekends() {
+        let fixed = account::Earning {
+            rate: 10.0,
+            index: account::EarningIndex::FIXED,
+        };
+        assert_eq!(
+            daily_rate(&fixed, day(4), &rates(), RateMode::Published),
+            Some(0.0)
+        );
+        assert_eq!(
+            daily_rate(&fixed, day(5), &rates(), RateMode::Published),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn fixed_rate_compounds_to_annual_rate_over_252_business_days() {
+        let daily = annual_to_daily(10.0);
+        let annual = (1.0 + daily).powf(BUSINESS_DAYS_PER_YEAR) - 1.0;
+        assert!((annual - 0.10).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cdi_skips_holidays_and_stops_at_unpublished_days() {
+        let cdi = account::Earning {
+            rate: 100.0,
+            index: account::EarningIndex::CDI,
+        };
+        assert_eq!(
+            daily_rate(&cdi, day(2), &rates(), RateMode::Published),
+            Some(0.0)
+        );
+        assert_eq!(
+            daily_rate(&cdi, day(6), &rates(), RateMode::Published),
+            None
+        );
+        assert!(daily_rate(&cdi, day(6), &rates(), RateMode::Projected).unwrap() > 0.0);
+
+        let (until, earnings) = accrue(
+            1000.0,
+            &BTreeMap::new(),
+            &cdi,
+            day(1),
+            day(10),
+            &rates(),
+            RateMode::Published,
+        )
+        .unwrap();
+        assert_eq!(until, day(5));
+        let expected = 1000.0 * (1.0 + annual_to_daily(13.65)).powi(2) - 1000.0;
+        assert!((earnings - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ipca_spreads_monthly_inflation_over_business_days() {
+        let ipca = account::Earning {
+            rate: 0.0,
+            index: account::EarningIndex::IPCA,
+        };
+        let (_, earnings) = accrue(
+            1000.0,
+            &BTreeMap::new(),
+            &ipca,
+            day(1),
+            day(31),
+            &rates(),
+            RateMode::Published,
+        )
+        .unwrap();
+        assert!((earnings - 7.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ipca_is_only_projected_into_unpublished_months() {
+        let ipca = account::Earning {
+            rate: 0.0,
+            index: account::EarningIndex::IPCA,
+        };
+        let april = NaiveDate::from_ymd(2023, 4, 3);
+        assert_eq!(
+            daily_rate(&ipca, april, &rates(), RateMode::Published),
+            None
+        );
+        assert!(daily_rate(&ipca, april, &rates(), RateMode::Projected).unwrap() > 0.0);
+    }
+
+    #[test]
+    fn each_day_yields_on_the_balance_of_that_day() {
+        let fixed = account::Earning {
+            rate: 10.0,
+            index: account::EarningIndex::FIXED,
+        };
+        // Nothing until the deposit at the end of the 1st, then two business
+        // days on it.
+        let movements = BTreeMap::from([(day(1), 1000.0)]);
+        let (until, earnings) = accrue(
+            0.0,
+            &movements,
+            &fixed,
+            day(1),
+            day(3),
+            &rates(),
+            RateMode::Published,
+        )
+        .unwrap();
+        assert_eq!(until, day(3));
+        let expected = 1000.0 * (1.0 + annual_to_daily(10.0)).powi(2) - 1000.0;
+        assert!((earnings - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parses_index_rate_files() {
+        let content =
+            "index,date,rate\n# comment\nCDI,2023-03-01,13.65\n\nipca, 2023-02-01, 0.84\n";
+        let rates = parse_index_rates(content).unwrap();
+        assert_eq!(rates.len(), 2);
+        assert!(parse_index_rates("FIXED,2023-03-01,10").is_err());
+        assert!(parse_index_rates("CDI,yesterday,10").is_err());
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Cursor, Write};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+use zip::{result::ZipError, write::FileOptions, ZipWriter};
+
+use crate::{
+    entities::{account, export, integration, money::Money, session, tag, transaction, user},
+    utils::to_hex,
+};
+
+#[derive(Debug)]
+pub enum ExportServiceError {
+    UserModelFailed(user::UserModelError),
+    AccountModelFailed(account::AccountModelError),
+    TransactionModelFailed(transaction::TransactionModelError),
+    IntegrationModelFailed(integration::IntegrationModelError),
+    TagModelFailed(tag::TagModelError),
+    SessionModelFailed(session::SessionModelError),
+    FailedToGenerateSignature(sha2::digest::InvalidLength),
+    FailedToWriteJson(serde_json::Error),
+    FailedToWriteCsv(csv::Error),
+    FailedToWriteArchive(ZipError),
+    InvalidSignature,
+    ExpiredLink,
+}
+
+impl fmt::Display for ExportServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<user::UserModelError> for ExportServiceError {
+    fn from(error: user::UserModelError) -> Self {
+        ExportServiceError::UserModelFailed(error)
+    }
+}
+
+impl From<account::AccountModelError> for ExportServiceError {
+    fn from(error: account::AccountModelError) -> Self {
+        ExportServiceError::AccountModelFailed(error)
+    }
+}
+
+impl From<transaction::TransactionModelError> for ExportServiceError {
+    fn from(error: transaction::TransactionModelError) -> Self {
+        ExportServiceError::TransactionModelFailed(error)
+    }
+}
+
+impl From<integration::IntegrationModelError> for ExportServiceError {
+    fn from(error: integration::IntegrationModelError) -> Self {
+        ExportServiceError::IntegrationModelFailed(error)
+    }
+}
+
+impl From<tag::TagModelError> for ExportServiceError {
+    fn from(error: tag::TagModelError) -> Self {
+        ExportServiceError::TagModelFailed(error)
+    }
+}
+
+impl From<session::SessionModelError> for ExportServiceError {
+    fn from(error: session::SessionModelError) -> Self {
+        ExportServiceError::SessionModelFailed(error)
+    }
+}
+
+impl From<serde_json::Error> for ExportServiceError {
+    fn from(error: serde_json::Error) -> Self {
+        ExportServiceError::FailedToWriteJson(error)
+    }
+}
+
+impl From<csv::Error> for ExportServiceError {
+    fn from(error: csv::Error) -> Self {
+        ExportServiceError::FailedToWriteCsv(error)
+    }
+}
+
+impl From<ZipError> for ExportServiceError {
+    fn from(error: ZipError) -> Self {
+        ExportServiceError::FailedToWriteArchive(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ExportServiceError>;
+
+const EXPORT_LINK_EXPIRATION_IN_MINUTES: i64 = 15;
+
+// Each file of the archive is written both as JSON and as CSV, so rows are
+// kept flat.
+
+#[derive(Serialize)]
+struct ProfileRow {
+    id: Uuid,
+    username: String,
+    name: String,
+    email: String,
+    register_date: Option<NaiveDateTime>,
+    payday: Option<i32>,
+    deletion_scheduled_for: Option<NaiveDateTime>,
+    pending_email: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AccountRow {
+    id: Uuid,
+    time: NaiveDateTime,
+    name: String,
+    description: Option<String>,
+    balance: Money,
+    is_available: bool,
+    in_trash: bool,
+    pre_allocation_amount: Option<Money>,
+    pre_allocation_accumulative: Option<bool>,
+    pre_allocation_source: Option<Uuid>,
+    earning_rate: Option<f64>,
+    earning_index: Option<String>,
+    last_accrual_date: Option<NaiveDate>,
+    last_refill_date: Option<NaiveDate>,
+}
+
+#[derive(Serialize)]
+struct TransactionRow {
+    id: Uuid,
+    entry_date: NaiveDate,
+    entry_account_code: Option<Uuid>,
+    entry_account_name: Option<String>,
+    exit_account_code: Option<Uuid>,
+    exit_account_name: Option<String>,
+    amount: Money,
+    description: Option<String>,
+    voided: bool,
+    kind: String,
+}
+
+// The provider state is left out: it holds credentials, not user data.
+#[derive(Serialize)]
+struct IntegrationRow {
+    id: Uuid,
+    name: String,
+    time: NaiveDateTime,
+    status: String,
+    sent_to: Option<String>,
+    account_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+struct TagRow {
+    id: Uuid,
+    name: String,
+    color: Option<String>,
+    time: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct AccountTagRow {
+    account_id: Uuid,
+    tag_id: Uuid,
+}
+
+// The refresh token hash is left out for the same reason.
+#[derive(Serialize)]
+struct SessionRow {
+    id: Uuid,
+    device_name: Option<String>,
+    created_at: NaiveDateTime,
+    last_used_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>,
+}
+
+impl ProfileRow {
+    fn new(user: &user::User) -> ProfileRow {
+        ProfileRow {
+            id: user.id,
+            username: user.username.clone(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            register_date: user.register_date,
+            payday: user.payday,
+            deletion_scheduled_for: user.deletion_scheduled_for,
+            pending_email: user.pending_email.clone(),
+        }
+    }
+}
+
+impl AccountRow {
+    fn new(account: &account::Account) -> AccountRow {
+        AccountRow {
+            id: account.id,
+            time: account.time,
+            name: account.name.clone(),
+            description: account.description.clone(),
+            balance: account.balance,
+            is_available: account.is_available,
+            in_trash: account.in_trash,
+            pre_allocation_amount: account.pre_allocation.map(|p| p.amount),
+            pre_allocation_accumulative: account.pre_allocation.map(|p| p.accumulative),
+            pre_allocation_source: account.pre_allocation.and_then(|p| p.source),
+            earning_rate: account.earning.map(|e| e.rate),
+            earning_index: account.earning.map(|e| format!("{:?}", e.index)),
+            last_accrual_date: account.last_accrual_date,
+            last_refill_date: account.last_refill_date,
+        }
+    }
+}
+
+impl TransactionRow {
+    fn new(transaction: &transaction::TransactionWithNames) -> TransactionRow {
+        TransactionRow {
+            id: transaction.id,
+            entry_date: transaction.entry_date,
+            entry_account_code: transaction.entry_account_code,
+            entry_account_name: transaction.entry_account_name.clone(),
+            exit_account_code: transaction.exit_account_code,
+            exit_account_name: transaction.exit_account_name.clone(),
+            amount: transaction.amount,
+            description: transaction.description.clone(),
+            voided: transaction.voided,
+            kind: format!("{:?}", transaction.kind),
+        }
+    }
+}
+
+impl IntegrationRow {
+    fn new(integration: &integration::UserIntegration) -> IntegrationRow {
+        IntegrationRow {
+            id: integration.id,
+            name: integration.name.clone(),
+            time: integration.time,
+            status: format!("{:?}", integration.status),
+            sent_to: integration.sent_to.clone(),
+            account_id: integration.account_id,
+        }
+    }
+}
+
+impl TagRow {
+    fn new(tag: &tag::Tag) -> TagRow {
+        TagRow {
+            id: tag.id,
+            name: tag.name.clone(),
+            color: tag.color.clone(),
+            time: tag.time,
+        }
+    }
+}
+
+impl AccountTagRow {
+    fn new(account_tag: &tag::AccountTag) -> AccountTagRow {
+        AccountTagRow {
+            account_id: account_tag.account_id,
+            tag_id: account_tag.tag_id,
+        }
+    }
+}
+
+impl SessionRow {
+    fn new(session: &session::Session) -> SessionRow {
+        SessionRow {
+            id: session.id,
+            device_name: session.device_name.clone(),
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            revoked_at: session.revoked_at,
+        }
+    }
+}
+
+fn to_csv<R: Serialize>(rows: &[R]) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.into_inner().map_err(|err| {
+        csv::Error::from(std::io::Error::new(err.error().kind(), err.to_string())).into()
+    })
+}
+
+fn add_files<R: Serialize>(
+    files: &mut Vec<(String, Vec<u8>)>,
+    name: &str,
+    rows: &[R],
+) -> Result<()> {
+    files.push((format!("{}.json", name), serde_json::to_vec_pretty(rows)?));
+    files.push((format!("{}.csv", name), to_csv(rows)?));
+    Ok(())
+}
+
+fn write_archive(files: Vec<(String, Vec<u8>)>) -> Result<Vec<u8>> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        archive.start_file(name, FileOptions::default())?;
+        archive.write_all(&content).map_err(ZipError::from)?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
+
+// Everything the user has: profile, accounts, transactions, integrations,
+// tags and sessions.
+pub fn export_user_data<
+    T: user::UserModel
+        + account::AccountModel
+        + transaction::TransactionModel
+        + integration::IntegrationModel
+        + tag::TagModel
+        + session::SessionModel,
+>(
+    database: &T,
+    user_id: &Uuid,
+    now: NaiveDateTime,
+) -> Result<export::ExportArchive> {
+    let user = database.get_user(*user_id)?;
+    let accounts = database.get_accounts(user_id)?;
+    let names: HashMap<Uuid, String> = accounts
+        .iter()
+        .map(|account| (account.id, account.name.clone()))
+        .collect();
+    let name_of = |code: Option<Uuid>| code.and_then(|code| names.get(&code).cloned());
+    let transactions = database
+        .list_user_transactions(user_id)?
+        .iter()
+        .map(|t| t.with_names(name_of(t.entry_account_code), name_of(t.exit_account_code)))
+        .collect::<Vec<_>>();
+    let integrations = database.list_user_integrations(user_id)?;
+    let tags = database.list_user_tags(user_id)?;
+    let account_tags = database.list_account_tags(user_id)?;
+    let sessions = database.list_user_sessions(user_id)?;
+
+    let mut files = vec![];
+    add_files(&mut files, "profile", &[ProfileRow::new(&user)])?;
+    add_files(
+        &mut files,
+        "accounts",
+        &accounts.iter().map(AccountRow::new).collect::<Vec<_>>(),
+    )?;
+    add_files(
+        &mut files,
+        "transactions",
+        &transactions
+            .iter()
+            .map(TransactionRow::new)
+            .collect::<Vec<_>>(),
+    )?;
+    add_files(
+        &mut files,
+        "integrations",
+        &integrations
+            .iter()
+            .map(IntegrationRow::new)
+            .collect::<Vec<_>>(),
+    )?;
+    add_files(
+        &mut files,
+        "tags",
+        &tags.iter().map(TagRow::new).collect::<Vec<_>>(),
+    )?;
+    add_files(
+        &mut files,
+        "account_tags",
+        &account_tags
+            .iter()
+            .map(AccountTagRow::new)
+            .collect::<Vec<_>>(),
+    )?;
+    add_files(
+        &mut files,
+        "sessions",
+        &sessions.iter().map(SessionRow::new).collect::<Vec<_>>(),
+    )?;
+
+    Ok(export::ExportArchive {
+        file_name: format!("cashtools-export-{}.zip", now.format("%Y-%m-%d")),
+        content: write_archive(files)?,
+    })
+}
+
+fn sign(user_id: &Uuid, expires: i64, secret: &str) -> Result<String> {
+    let mut mac: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())
+        .map_err(ExportServiceError::FailedToGenerateSignature)?;
+    mac.update(format!("export:{}:{}", user_id, expires).as_bytes());
+    Ok(to_hex(&mac.finalize().into_bytes()))
+}
+
+// The link carries its own expiration and signature, so it can be opened
+// without the access token.
+pub fn create_export_link(
+    user_id: &Uuid,
+    secret: &str,
+    now: NaiveDateTime,
+) -> Result<export::ExportLink> {
+    let expires = (now + Duration::minutes(EXPORT_LINK_EXPIRATION_IN_MINUTES)).timestamp();
+    Ok(export::ExportLink {
+        url: format!(
+            "/export/{}?expires={}&signature={}",
+            user_id,
+            expires,
+            sign(user_id, expires, secret)?
+        ),
+        expires_at: NaiveDateTime::from_timestamp(expires, 0),
+    })
+}
+
+fn verify_export_link(
+    user_id: &Uuid,
+    expires: i64,
+    signature: &str,
+    secret: &str,
+    now: NaiveDateTime,
+) -> Result<()> {
+    let expected = sign(user_id, expires, secret)?;
+    if expected.len() != signature.len()
+        || !openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+    {
+        return Err(ExportServiceError::InvalidSignature);
+    }
+    if now.timestamp() >= expires {
+        return Err(ExportServiceError::ExpiredLink);
+    }
+    Ok(())
+}
+
+pub fn download_export<
+    T: user::UserModel
+        + account::AccountModel
+        + transaction::TransactionModel
+        + integration::IntegrationModel
+        + tag::TagModel
+        + session::SessionModel,
+>(
+    database: &T,
+    user_id: &Uuid,
+    expires: i64,
+    signature: &str,
+    secret: &str,
+    now: NaiveDateTime,
+) -> Result<export::ExportArchive> {
+    verify_export_link(user_id, expires, signature, secret, now)?;
+    export_user_data(database, user_id, now)
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+
+    const SECRET: &str = "1234567890987654321";
+    const USER: Uuid = Uuid::from_u128(1);
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(1665366563, 0)
+    }
+
+    fn query(url: &str) -> (i64, String) {
+        let (_, query) = url.split_once('?').unwrap();
+        let (expires, signature) = query.split_once('&').unwrap();
+        (
+            expires.trim_start_matches("expires=").parse().unwrap(),
+            signature.trim_start_matches("signature=").to_string(),
+        )
+    }
+
+    #[test]
+    fn export_link_is_valid_until_it_expires() {
+        let link = create_export_link(&USER, SECRET, now()).unwrap();
+        assert!(link.url.starts_with(&format!("/export/{}?", USER)));
+        assert_eq!(link.expires_at, now() + Duration::minutes(15));
+
+        let (expires, signature) = query(&link.url);
+        assert!(verify_export_link(&USER, expires, &signature, SECRET, now()).is_ok());
+        assert!(matches!(
+            verify_export_link(&USER, expires, &signature, SECRET, link.expires_at),
+            Err(ExportServiceError::ExpiredLink)
+        ));
+    }
+
+    #[test]
+    fn tampered_export_link_is_rejected() {
+        let link = create_export_link(&USER, SECRET, now()).unwrap();
+        let (expires, signature) = query(&link.url);
+        let other_user = Uuid::from_u128(2);
+        for (user_id, expires, signature, secret) in [
+            (&other_user, expires, signature.as_str(), SECRET),
+            (&USER, expires + 3600, signature.as_str(), SECRET),
+            (&USER, expires, &signature[1..], SECRET),
+            (&USER, expires, signature.as_str(), "another secret"),
+        ] {
+            assert!(matches!(
+                verify_export_link(user_id, expires, signature, secret, now()),
+                Err(ExportServiceError::InvalidSignature)
+            ));
+        }
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_line_per_row() {
+        let rows = [IntegrationRow {
+            id: USER,
+            name: "Nubank, main".to_string(),
+            time: now(),
+            status: "CONNECTED".to_string(),
+            sent_to: None,
+            account_id: None,
+        }];
+        let csv = String::from_utf8(to_csv(&rows).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "id,name,time,status,sent_to,account_id\n{},\"Nubank, main\",2022-10-10T01:49:23,CONNECTED,,\n",
+                USER
+            )
+        );
+    }
+}
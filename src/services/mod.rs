@@ -1,3 +1,10 @@
 pub mod account;
+pub mod budget;
+pub mod cycle;
+pub mod earning;
+pub mod export;
+pub mod nubank;
+pub mod session;
+pub mod tag;
 pub mod transaction;
 pub mod user;
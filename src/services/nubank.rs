@@ -0,0 +1,445 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use log;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    encryption,
+    entities::{account, integration, transaction},
+    events::{Event, EventBus},
+    nubank::{EventSource, FeedEvent, FeedItem, NubankClient, NubankError, NubankTransport},
+};
+
+#[derive(Debug)]
+pub enum NubankServiceError {
+    IntegrationModelFailed(integration::IntegrationModelError),
+    AccountModelFailed(account::AccountModelError),
+    NubankFailed(NubankError),
+    EncryptionFailed(encryption::EncryptionError),
+    CannotSerializeState(serde_json::Error),
+    IntegrationIsNotNubank,
+    IntegrationIsNotPending,
+    IntegrationIsNotConnected,
+    IntegrationWithoutState,
+    IntegrationWithoutAccount,
+    DatabaseTaskFailed(tokio::task::JoinError),
+}
+
+impl fmt::Display for NubankServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<integration::IntegrationModelError> for NubankServiceError {
+    fn from(error: integration::IntegrationModelError) -> Self {
+        NubankServiceError::IntegrationModelFailed(error)
+    }
+}
+
+impl From<account::AccountModelError> for NubankServiceError {
+    fn from(error: account::AccountModelError) -> Self {
+        NubankServiceError::AccountModelFailed(error)
+    }
+}
+
+impl From<NubankError> for NubankServiceError {
+    fn from(error: NubankError) -> Self {
+        NubankServiceError::NubankFailed(error)
+    }
+}
+
+impl From<encryption::EncryptionError> for NubankServiceError {
+    fn from(error: encryption::EncryptionError) -> Self {
+        NubankServiceError::EncryptionFailed(error)
+    }
+}
+
+impl From<serde_json::Error> for NubankServiceError {
+    fn from(error: serde_json::Error) -> Self {
+        NubankServiceError::CannotSerializeState(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, NubankServiceError>;
+
+pub const NUBANK_INTEGRATION_NAME: &str = "Nubank";
+const STATE_KEY_PURPOSE: &str = "cashtools-integration-state";
+
+// What is stored, encrypted, in `user_integrations.state`: the onboarding
+// while it waits for the code, then the certificate and the refresh token
+// that every sync authenticates with. The CPF and password are never stored.
+#[derive(Serialize, Deserialize)]
+pub struct NubankState {
+    pub client: NubankClient,
+}
+
+// `encryption_key` is the INTEGRATION_ENCRYPTION_KEY, only used for this.
+fn seal_state(state: &NubankState, encryption_key: &str) -> Result<Vec<u8>> {
+    let plaintext = serde_json::to_vec(state)?;
+    let key = encryption::derive_key(encryption_key, STATE_KEY_PURPOSE);
+    Ok(encryption::encrypt(&key, &plaintext)?)
+}
+
+pub fn open_state(
+    integration: &integration::UserIntegration,
+    encryption_key: &str,
+) -> Result<NubankState> {
+    if integration.name != NUBANK_INTEGRATION_NAME {
+        return Err(NubankServiceError::IntegrationIsNotNubank);
+    }
+    let sealed = integration
+        .state
+        .as_ref()
+        .ok_or(NubankServiceError::IntegrationWithoutState)?;
+    let key = encryption::derive_key(encryption_key, STATE_KEY_PURPOSE);
+    let plaintext = encryption::decrypt(&key, sealed)?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+// These flows await Nubank between database calls, so the synchronous Diesel
+// calls run on the blocking pool, each with its own handle to the database.
+async fn blocking<T, R, F>(database: &T, call: F) -> Result<R>
+where
+    T: Clone + Send + 'static,
+    R: Send + 'static,
+    F: FnOnce(&T) -> Result<R> + Send + 'static,
+{
+    let database = database.clone();
+    tokio::task::spawn_blocking(move || call(&database))
+        .await
+        .map_err(NubankServiceError::DatabaseTaskFailed)?
+}
+
+pub async fn start_nubank_integration<T: integration::IntegrationModel + Clone + Send + 'static>(
+    database: &T,
+    transport: &NubankTransport,
+    user_id: &Uuid,
+    encryption_key: &str,
+    now: NaiveDateTime,
+    cpf: String,
+    password: String,
+) -> Result<integration::UserIntegration> {
+    let client = NubankClient::new()
+        .request_code_to_gen_cert(transport, &cpf, &password)
+        .await?;
+    let sent_to = client.sent_to.clone();
+    let state = seal_state(&NubankState { client }, encryption_key)?;
+
+    let user_id = *user_id;
+    blocking(database, move |database| {
+        Ok(
+            database.create_integration(integration::NewUserIntegration {
+                related_user: user_id,
+                name: NUBANK_INTEGRATION_NAME.to_string(),
+                time: now,
+                status: integration::IntegrationStatus::PENDING,
+                sent_to,
+                state: Some(state),
+            })?,
+        )
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn confirm_nubank_integration<
+    T: integration::IntegrationModel + Clone + Send + 'static,
+>(
+    database: &T,
+    transport: &NubankTransport,
+    user_id: &Uuid,
+    encryption_key: &str,
+    id: &Uuid,
+    cpf: String,
+    password: String,
+    code: String,
+) -> Result<integration::UserIntegration> {
+    let (id, user_id) = (*id, *user_id);
+    let integration = blocking(database, move |database| {
+        Ok(database.get_integration(&id, &user_id)?)
+    })
+    .await?;
+    let state = open_state(&integration, encryption_key)?;
+    if integration.status != integration::IntegrationStatus::PENDING {
+        return Err(NubankServiceError::IntegrationIsNotPending);
+    }
+
+    // The first authentication, the last one with the credentials, gets the
+    // refresh token the syncs use.
+    let client = state
+        .client
+        .gen_certificate(transport, &cpf, &password, &code)
+        .await?
+        .authenticate(transport, &cpf, &password)
+        .await?;
+    let state = seal_state(&NubankState { client }, encryption_key)?;
+
+    blocking(database, move |database| {
+        Ok(database.update_integration_state(
+            &id,
+            &user_id,
+            integration::UpdatedIntegrationState {
+                status: integration::IntegrationStatus::CONNECTED,
+                sent_to: integration.sent_to,
+                state: Some(state),
+            },
+        )?)
+    })
+    .await
+}
+
+pub fn link_integration_account<T: integration::IntegrationModel + account::AccountModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+    account_id: Option<Uuid>,
+) -> Result<integration::UserIntegration> {
+    if let Some(account_id) = account_id {
+        let _ = database.get_account(&account_id, user_id)?;
+    }
+    Ok(database.link_integration_account(id, user_id, account_id)?)
+}
+
+// Upstream ids are only unique within their feed, so they are prefixed with
+// the feed they came from.
+pub fn external_id(event: &FeedEvent) -> String {
+    match event.source {
+        EventSource::CARD => format!("nubank:card:{}", event.id),
+        EventSource::ACCOUNT => format!("nubank:account:{}", event.id),
+    }
+}
+
+pub fn event_to_transaction(event: &FeedEvent, account_id: &Uuid) -> transaction::NewTransaction {
+    let (entry_account_code, exit_account_code) = match event.inflow {
+        true => (Some(*account_id), None),
+        false => (None, Some(*account_id)),
+    };
+    transaction::NewTransaction {
+        entry_date: event.date,
+        entry_account_code,
+        exit_account_code,
+        amount: event.amount,
+        description: Some(event.description.clone()),
+        kind: transaction::TransactionKind::IMPORT,
+    }
+}
+
+// Progress is published every this many events, and once more at the end.
+const SYNC_PROGRESS_STEP: usize = 10;
+
+// Everything the sync changes is also published to the user's subscriptions:
+// the imported transactions, its progress and the linked account.
+pub async fn sync_integration<
+    T: integration::IntegrationModel
+        + account::AccountModel
+        + transaction::TransactionModel
+        + Clone
+        + Send
+        + 'static,
+>(
+    database: &T,
+    transport: &NubankTransport,
+    events: &EventBus,
+    user_id: &Uuid,
+    encryption_key: &str,
+    id: &Uuid,
+) -> Result<integration::SyncReport> {
+    let (id, user_id) = (*id, *user_id);
+    let integration = blocking(database, move |database| {
+        Ok(database.get_integration(&id, &user_id)?)
+    })
+    .await?;
+    let state = open_state(&integration, encryption_key)?;
+    if integration.status != integration::IntegrationStatus::CONNECTED {
+        return Err(NubankServiceError::IntegrationIsNotConnected);
+    }
+    let account_id = integration
+        .account_id
+        .ok_or(NubankServiceError::IntegrationWithoutAccount)?;
+    let account = blocking(database, move |database| {
+        Ok(database.get_account(&account_id, &user_id)?)
+    })
+    .await?;
+
+    // The refresh token is used up, so the new one is stored before anything
+    // else can fail.
+    let client = state.client.refresh(transport).await?;
+    let sealed = seal_state(
+        &NubankState {
+            client: client.clone(),
+        },
+        encryption_key,
+    )?;
+    blocking(database, move |database| {
+        Ok(database.update_integration_state(
+            &id,
+            &user_id,
+            integration::UpdatedIntegrationState {
+                status: integration.status,
+                sent_to: integration.sent_to,
+                state: Some(sealed),
+            },
+        )?)
+    })
+    .await?;
+    let items = client.fetch_events(transport).await?;
+
+    let bus = events.clone();
+    let report = blocking(database, move |database| {
+        Ok(import_events(
+            database, &bus, &user_id, &id, &account, items,
+        ))
+    })
+    .await?;
+    if report.imported > 0 {
+        let account = blocking(database, move |database| {
+            Ok(database.get_account(&account_id, &user_id)?)
+        })
+        .await?;
+        events.publish(user_id, Event::AccountUpdated(account));
+    }
+    Ok(report)
+}
+
+fn import_events<T: transaction::TransactionModel>(
+    database: &T,
+    events: &EventBus,
+    user_id: &Uuid,
+    integration_id: &Uuid,
+    account: &account::Account,
+    items: Vec<FeedItem>,
+) -> integration::SyncReport {
+    let mut report = integration::SyncReport::default();
+    let total = items.len();
+    let publish_progress = |report: integration::SyncReport, done: bool| {
+        events.publish(
+            *user_id,
+            Event::IntegrationSyncProgress(integration::SyncProgress {
+                integration_id: *integration_id,
+                total: total as i32,
+                report,
+                done,
+            }),
+        )
+    };
+    let name_of = |code: Option<Uuid>| (code == Some(account.id)).then(|| account.name.clone());
+
+    publish_progress(report, false);
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 && index % SYNC_PROGRESS_STEP == 0 {
+            publish_progress(report, false);
+        }
+        let event = match item {
+            Ok(event) => event,
+            Err(unreadable) => {
+                log::warn!("Unreadable Nubank event: {:?}", unreadable);
+                report.failed += 1;
+                continue;
+            }
+        };
+        match database.import_transaction(
+            user_id,
+            &external_id(&event),
+            event_to_transaction(&event, &account.id),
+        ) {
+            Ok(Some(imported)) => {
+                report.imported += 1;
+                let imported = imported.with_names(
+                    name_of(imported.entry_account_code),
+                    name_of(imported.exit_account_code),
+                );
+                events.publish(*user_id, Event::TransactionCreated(imported));
+            }
+            Ok(None) => report.skipped += 1,
+            Err(err) => {
+                log::error!("Failed to import Nubank event {}: {:?}", event.id, err);
+                report.failed += 1;
+            }
+        }
+    }
+    publish_progress(report, true);
+    report
+}
+
+#[cfg(test)]
+mod nubank_tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::entities::{account::AccountModel, money::Money, transaction::TransactionModel};
+    use crate::models::memory::{new_account, InMemoryStore};
+
+    fn event(source: EventSource, inflow: bool) -> FeedEvent {
+        FeedEvent {
+            source,
+            id: "5f1c".to_string(),
+            date: NaiveDate::from_ymd(2022, 10, 1),
+            description: "Padaria".to_string(),
+            amount: Money::from_cents(1250),
+            inflow,
+        }
+    }
+
+    #[test]
+    fn external_ids_do_not_collide_between_feeds() {
+        assert_ne!(
+            external_id(&event(EventSource::CARD, false)),
+            external_id(&event(EventSource::ACCOUNT, false))
+        );
+    }
+
+    #[test]
+    fn outflows_debit_the_linked_account() {
+        let account_id = Uuid::new_v4();
+        let new_transaction = event_to_transaction(&event(EventSource::CARD, false), &account_id);
+        assert_eq!(new_transaction.entry_account_code, None);
+        assert_eq!(new_transaction.exit_account_code, Some(account_id));
+        assert_eq!(new_transaction.amount, Money::from_cents(1250));
+        assert_eq!(new_transaction.kind, transaction::TransactionKind::IMPORT);
+    }
+
+    #[test]
+    fn inflows_credit_the_linked_account() {
+        let account_id = Uuid::new_v4();
+        let new_transaction = event_to_transaction(&event(EventSource::ACCOUNT, true), &account_id);
+        assert_eq!(new_transaction.entry_account_code, Some(account_id));
+        assert_eq!(new_transaction.exit_account_code, None);
+    }
+
+    #[test]
+    fn events_are_imported_once_into_the_linked_account() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let account = database
+            .create_account(user_id, new_account("Nubank", Money::from_cents(10000)))
+            .unwrap();
+        let import = |items: Vec<FeedItem>| {
+            import_events(
+                &database,
+                &EventBus::default(),
+                &user_id,
+                &Uuid::new_v4(),
+                &account,
+                items,
+            )
+        };
+
+        let report = import(vec![
+            Ok(event(EventSource::CARD, false)),
+            Ok(event(EventSource::ACCOUNT, true)),
+        ]);
+        assert_eq!(report.imported, 2);
+        let report = import(vec![Ok(event(EventSource::CARD, false))]);
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.skipped, 1);
+
+        assert_eq!(database.list_user_transactions(&user_id).unwrap().len(), 2);
+        assert_eq!(
+            database.get_account(&account.id, &user_id).unwrap().balance,
+            Money::from_cents(10000)
+        );
+    }
+}
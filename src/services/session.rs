@@ -0,0 +1,174 @@
+use std::fmt;
+
+use chrono::{Duration, NaiveDateTime};
+use openssl::rand::rand_bytes;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    entities::{session, user, Env},
+    jwt,
+    services::user as user_service,
+    utils::to_hex,
+};
+
+#[derive(Debug)]
+pub enum SessionServiceError {
+    SessionModelFailed(session::SessionModelError),
+    UserServiceFailed(user_service::UserServiceError),
+    JwtError(jwt::JwtError),
+    FailedToGenerateRefreshToken(openssl::error::ErrorStack),
+    InvalidRefreshToken,
+}
+
+impl fmt::Display for SessionServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<session::SessionModelError> for SessionServiceError {
+    fn from(error: session::SessionModelError) -> Self {
+        SessionServiceError::SessionModelFailed(error)
+    }
+}
+
+impl From<user_service::UserServiceError> for SessionServiceError {
+    fn from(error: user_service::UserServiceError) -> Self {
+        SessionServiceError::UserServiceFailed(error)
+    }
+}
+
+impl From<jwt::JwtError> for SessionServiceError {
+    fn from(error: jwt::JwtError) -> Self {
+        SessionServiceError::JwtError(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SessionServiceError>;
+
+const REFRESH_TOKEN_BYTES: usize = 32;
+// A session nobody refreshed for this long has to log in again.
+const SESSION_IDLE_TIMEOUT_IN_DAYS: i64 = 30;
+
+fn generate_refresh_token() -> Result<String> {
+    let mut bytes = [0u8; REFRESH_TOKEN_BYTES];
+    rand_bytes(&mut bytes).map_err(SessionServiceError::FailedToGenerateRefreshToken)?;
+    Ok(to_hex(&bytes))
+}
+
+// Refresh tokens are random, so a plain hash is enough to keep a database
+// leak from handing them out.
+fn hash_refresh_token(refresh_token: &str) -> String {
+    to_hex(&Sha256::digest(refresh_token.as_bytes()))
+}
+
+fn issue_tokens(
+    session: session::Session,
+    refresh_token: String,
+    jwt_secret: &str,
+    now: NaiveDateTime,
+) -> Result<session::AuthTokens> {
+    let access_token =
+        jwt::generate_access_token(now, &session.related_user, &session.id, jwt_secret)?;
+    Ok(session::AuthTokens {
+        access_token,
+        refresh_token,
+        session,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn login<T: user::UserModel + session::SessionModel>(
+    database: &T,
+    email: &str,
+    login_code: i32,
+    device_name: Option<String>,
+    jwt_secret: &str,
+    env: &Env,
+    magic_login_code: Option<i32>,
+    now: NaiveDateTime,
+) -> Result<session::AuthTokens> {
+    let user_id =
+        user_service::verify_login_code(database, email, login_code, env, magic_login_code, now)?;
+    let refresh_token = generate_refresh_token()?;
+    let session = database.create_session(session::NewSession {
+        related_user: user_id,
+        refresh_token_hash: hash_refresh_token(&refresh_token),
+        device_name,
+        time: now,
+    })?;
+    issue_tokens(session, refresh_token, jwt_secret, now)
+}
+
+// Every refresh token works once: using it hands out a new one. Using one
+// again means it leaked, so the whole session is revoked.
+pub fn refresh<T: session::SessionModel>(
+    database: &T,
+    refresh_token: &str,
+    jwt_secret: &str,
+    now: NaiveDateTime,
+) -> Result<session::AuthTokens> {
+    let new_refresh_token = generate_refresh_token()?;
+    let session = database
+        .rotate_refresh_token(
+            &hash_refresh_token(refresh_token),
+            &hash_refresh_token(&new_refresh_token),
+            now - Duration::days(SESSION_IDLE_TIMEOUT_IN_DAYS),
+            now,
+        )
+        .map_err(|err| match err {
+            session::SessionModelError::SessionNotFound => SessionServiceError::InvalidRefreshToken,
+            session::SessionModelError::RefreshTokenReused(session_id) => {
+                log::warn!("Revoked session {} for reusing a refresh token", session_id);
+                SessionServiceError::InvalidRefreshToken
+            }
+            err => SessionServiceError::SessionModelFailed(err),
+        })?;
+    issue_tokens(session, new_refresh_token, jwt_secret, now)
+}
+
+pub fn logout<T: session::SessionModel>(
+    database: &T,
+    user_id: &Uuid,
+    session_id: &Uuid,
+    now: NaiveDateTime,
+) -> Result<session::Session> {
+    Ok(database.revoke_session(session_id, user_id, now)?)
+}
+
+pub fn logout_everywhere<T: session::SessionModel>(
+    database: &T,
+    user_id: &Uuid,
+    now: NaiveDateTime,
+) -> Result<Vec<session::Session>> {
+    Ok(database.revoke_user_sessions(user_id, now)?)
+}
+
+pub fn list_sessions<T: session::SessionModel>(
+    database: &T,
+    user_id: &Uuid,
+) -> Result<Vec<session::Session>> {
+    Ok(database.list_active_sessions(user_id)?)
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+
+    #[test]
+    fn refresh_tokens_are_random_hex() {
+        let first = generate_refresh_token().unwrap();
+        let second = generate_refresh_token().unwrap();
+        assert_eq!(first.len(), REFRESH_TOKEN_BYTES * 2);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn refresh_token_hash_is_stable_and_hides_the_token() {
+        let token = generate_refresh_token().unwrap();
+        assert_eq!(hash_refresh_token(&token), hash_refresh_token(&token));
+        assert_ne!(hash_refresh_token(&token), token);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::entities::tag;
+
+#[derive(Debug)]
+pub enum TagServiceError {
+    TagModelFailed(tag::TagModelError),
+    InvalidColor(String),
+}
+
+impl fmt::Display for TagServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<tag::TagModelError> for TagServiceError {
+    fn from(error: tag::TagModelError) -> Self {
+        TagServiceError::TagModelFailed(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TagServiceError>;
+
+// Colors are stored as "#RRGGBB" so every client renders them the same way.
+fn validate_color(color: Option<String>) -> Result<Option<String>> {
+    match color {
+        None => Ok(None),
+        Some(c) => {
+            let is_hex = c.len() == 7
+                && c.starts_with('#')
+                && c.chars().skip(1).all(|ch| ch.is_ascii_hexdigit());
+            if is_hex {
+                Ok(Some(c.to_uppercase()))
+            } else {
+                Err(TagServiceError::InvalidColor(c))
+            }
+        }
+    }
+}
+
+pub fn create_tag<T: tag::TagModel>(
+    database: &T,
+    user_id: &Uuid,
+    name: String,
+    color: Option<String>,
+) -> Result<tag::Tag> {
+    let new_tag = tag::NewTag {
+        name,
+        color: validate_color(color)?,
+        time: Utc::now().naive_utc(),
+    };
+    Ok(database.create_tag(user_id, new_tag)?)
+}
+
+pub fn list_tags<T: tag::TagModel>(database: &T, user_id: &Uuid) -> Result<Vec<tag::Tag>> {
+    Ok(database.list_user_tags(user_id)?)
+}
+
+pub fn rename_tag<T: tag::TagModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+    name: String,
+) -> Result<tag::Tag> {
+    let updated_tag = tag::UpdatedTag {
+        name: Some(name),
+        color: None,
+    };
+    Ok(database.edit_tag(id, user_id, updated_tag)?)
+}
+
+pub fn recolor_tag<T: tag::TagModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+    color: String,
+) -> Result<tag::Tag> {
+    let updated_tag = tag::UpdatedTag {
+        name: None,
+        color: validate_color(Some(color))?,
+    };
+    Ok(database.edit_tag(id, user_id, updated_tag)?)
+}
+
+pub fn delete_tag<T: tag::TagModel>(database: &T, user_id: &Uuid, id: &Uuid) -> Result<tag::Tag> {
+    Ok(database.delete_tag(id, user_id)?)
+}
+
+pub fn tag_account<T: tag::TagModel>(
+    database: &T,
+    user_id: &Uuid,
+    account_id: &Uuid,
+    tag_id: &Uuid,
+) -> Result<()> {
+    Ok(database.tag_account(account_id, tag_id, user_id)?)
+}
+
+pub fn untag_account<T: tag::TagModel>(
+    database: &T,
+    user_id: &Uuid,
+    account_id: &Uuid,
+    tag_id: &Uuid,
+) -> Result<()> {
+    Ok(database.untag_account(account_id, tag_id, user_id)?)
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use super::*;
+    use crate::entities::{account::AccountModel, money::Money, tag::TagModel};
+    use crate::models::memory::{new_account, InMemoryStore};
+
+    #[test]
+    fn accepts_hex_colors() {
+        let color = validate_color(Some("#a1b2c3".to_string())).unwrap();
+        assert_eq!(color, Some("#A1B2C3".to_string()));
+    }
+
+    #[test]
+    fn rejects_other_colors() {
+        assert!(validate_color(Some("red".to_string())).is_err());
+        assert!(validate_color(Some("#12345".to_string())).is_err());
+        assert!(validate_color(Some("#12345G".to_string())).is_err());
+    }
+
+    #[test]
+    fn tags_only_go_on_accounts_of_their_user() {
+        let database = InMemoryStore::default();
+        let (user_id, other_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let account = database
+            .create_account(user_id, new_account("Wallet", Money::ZERO))
+            .unwrap();
+        let tag = create_tag(&database, &user_id, "Daily".to_string(), None).unwrap();
+        let other_tag = create_tag(&database, &other_id, "Daily".to_string(), None).unwrap();
+
+        tag_account(&database, &user_id, &account.id, &tag.id).unwrap();
+        tag_account(&database, &user_id, &account.id, &tag.id).unwrap();
+        assert!(matches!(
+            tag_account(&database, &other_id, &account.id, &other_tag.id),
+            Err(TagServiceError::TagModelFailed(
+                tag::TagModelError::AccountNotFound
+            ))
+        ));
+        assert!(matches!(
+            create_tag(&database, &user_id, "Daily".to_string(), None),
+            Err(TagServiceError::TagModelFailed(
+                tag::TagModelError::TagNameAlreadyTaken
+            ))
+        ));
+        assert_eq!(database.list_account_tags(&user_id).unwrap().len(), 1);
+
+        delete_tag(&database, &user_id, &tag.id).unwrap();
+        assert!(database.list_account_tags(&user_id).unwrap().is_empty());
+    }
+}
@@ -1,12 +1,12 @@
+use std::collections::HashSet;
 use std::fmt;
 
-use chrono::Utc;
+use chrono::NaiveDate;
 use uuid::Uuid;
 
 use crate::{
     entities::{account, transaction, user},
-    jwt,
-    utils::opt_result_of_result_opt,
+    services::cycle::user_cycle_of,
 };
 
 #[derive(Debug)]
@@ -14,7 +14,7 @@ pub enum TransactionServiceError {
     TransactionModelFailed(transaction::TransactionModelError),
     UserModelFailed(user::UserModelError),
     AccountModelFailed(account::AccountModelError),
-    JwtError(jwt::JwtError),
+    InvalidCursor(String),
 }
 
 impl fmt::Display for TransactionServiceError {
@@ -41,50 +41,286 @@ impl From<account::AccountModelError> for TransactionServiceError {
     }
 }
 
-impl From<jwt::JwtError> for TransactionServiceError {
-    fn from(error: jwt::JwtError) -> Self {
-        TransactionServiceError::JwtError(error)
+pub type Result<T> = std::result::Result<T, TransactionServiceError>;
+
+// Looks up the names of every account involved at once, so listing a page
+// takes the same number of queries whatever its size.
+fn fill_names<T: account::AccountModel>(
+    database: &T,
+    user_id: &Uuid,
+    transactions: &[transaction::Transaction],
+) -> Result<Vec<transaction::TransactionWithNames>> {
+    let ids: Vec<Uuid> = transactions
+        .iter()
+        .flat_map(|t| [t.entry_account_code, t.exit_account_code])
+        .flatten()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    if ids.is_empty() {
+        return Ok(transactions
+            .iter()
+            .map(|t| t.with_names(None, None))
+            .collect());
     }
+    let names = database.get_account_names(&ids, user_id)?;
+    let name_of = |code: Option<Uuid>| code.and_then(|code| names.get(&code).cloned());
+    Ok(transactions
+        .iter()
+        .map(|t| t.with_names(name_of(t.entry_account_code), name_of(t.exit_account_code)))
+        .collect())
 }
 
-pub type Result<T> = std::result::Result<T, TransactionServiceError>;
-
 fn fill_name<T: account::AccountModel>(
     database: &T,
     user_id: &Uuid,
-    transaction: &transaction::Transaction,
+    transaction: transaction::Transaction,
 ) -> Result<transaction::TransactionWithNames> {
-    let entry_account = transaction
-        .entry_account_code
-        .map(|code| database.get_account(&code, user_id));
-    let exit_account = transaction
-        .exit_account_code
-        .map(|code| database.get_account(&code, user_id));
-    let entry_account_name = opt_result_of_result_opt(entry_account)?.map(|x| x.name);
-    let exit_account_name = opt_result_of_result_opt(exit_account)?.map(|x| x.name);
-    Ok(transaction.with_names(entry_account_name, exit_account_name))
+    let mut filled = fill_names(database, user_id, &[transaction])?;
+    Ok(filled.remove(0))
 }
 
-pub fn auth_and_create_transaction<T: transaction::TransactionModel + account::AccountModel>(
+pub fn create_transaction<T: transaction::TransactionModel + account::AccountModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: &Uuid,
     new_transaction: transaction::NewTransaction,
 ) -> Result<transaction::TransactionWithNames> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    let created_transaction = database.create_transaction(&id, new_transaction)?;
-    fill_name(database, &id, &created_transaction)
+    let created_transaction = database.create_transaction(user_id, new_transaction)?;
+    fill_name(database, user_id, created_transaction)
 }
 
-pub fn auth_and_list_user_transactions<T: transaction::TransactionModel + account::AccountModel>(
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+pub fn parse_cursor(cursor: &str) -> Result<transaction::TransactionCursor> {
+    transaction::TransactionCursor::decode(cursor)
+        .ok_or_else(|| TransactionServiceError::InvalidCursor(cursor.to_string()))
+}
+
+// Narrows the date range of the filter to the cycle containing `date`.
+fn restrict_to_cycle<T: user::UserModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
-) -> Result<Vec<transaction::TransactionWithNames>> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    let transactions = database.list_user_transactions(&id)?;
-    transactions
-        .into_iter()
-        .map(|t| fill_name(database, &id, &t))
-        .collect()
+    user_id: &Uuid,
+    filter: transaction::TransactionFilter,
+    date: NaiveDate,
+) -> Result<transaction::TransactionFilter> {
+    let cycle = user_cycle_of(database, user_id, date)?;
+    Ok(transaction::TransactionFilter {
+        from_date: Some(filter.from_date.map_or(cycle.start, |d| d.max(cycle.start))),
+        to_date: Some(filter.to_date.map_or(cycle.end, |d| d.min(cycle.end))),
+        ..filter
+    })
+}
+
+// The account names are left to the caller, see TransactionPage.
+pub fn list_user_transactions<T: transaction::TransactionModel + user::UserModel>(
+    database: &T,
+    user_id: &Uuid,
+    filter: transaction::TransactionFilter,
+    cycle_containing: Option<NaiveDate>,
+    page: transaction::TransactionPageRequest,
+) -> Result<transaction::TransactionPage> {
+    let filter = match cycle_containing {
+        Some(date) => restrict_to_cycle(database, user_id, filter, date)?,
+        None => filter,
+    };
+    let page = transaction::TransactionPageRequest {
+        first: page.first.clamp(1, MAX_PAGE_SIZE),
+        ..page
+    };
+    let mut transactions = database.filter_user_transactions(user_id, &filter, &page)?;
+    let has_next_page = transactions.len() as i64 > page.first;
+    transactions.truncate(page.first as usize);
+
+    Ok(transaction::TransactionPage {
+        transactions,
+        has_next_page,
+        has_previous_page: page.after.is_some(),
+    })
+}
+
+pub fn edit_transaction<T: transaction::TransactionModel + account::AccountModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+    updated_transaction: transaction::UpdatedTransaction,
+) -> Result<transaction::TransactionWithNames> {
+    let edited_transaction = database.edit_transaction(id, user_id, updated_transaction)?;
+    fill_name(database, user_id, edited_transaction)
+}
+
+pub fn delete_transaction<T: transaction::TransactionModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+) -> Result<()> {
+    let _ = database.delete_transaction(id, user_id)?;
+    Ok(())
+}
+
+pub fn void_transaction<T: transaction::TransactionModel + account::AccountModel>(
+    database: &T,
+    user_id: &Uuid,
+    id: &Uuid,
+) -> Result<transaction::TransactionWithNames> {
+    let voided_transaction = database.void_transaction(id, user_id)?;
+    fill_name(database, user_id, voided_transaction)
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use super::*;
+    use crate::entities::{
+        account::AccountModel, money::Money, transaction::TransactionModel, Order,
+    };
+    use crate::models::memory::{new_account, InMemoryStore};
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2022, 10, d)
+    }
+
+    fn create(database: &InMemoryStore, user_id: &Uuid, name: &str, balance: Money) -> Uuid {
+        database
+            .create_account(*user_id, new_account(name, balance))
+            .unwrap()
+            .id
+    }
+
+    fn transfer(
+        date: NaiveDate,
+        from: &Uuid,
+        to: &Uuid,
+        cents: i64,
+    ) -> transaction::NewTransaction {
+        transaction::NewTransaction {
+            entry_date: date,
+            entry_account_code: Some(*to),
+            exit_account_code: Some(*from),
+            amount: Money::from_cents(cents),
+            description: None,
+            kind: transaction::TransactionKind::MANUAL,
+        }
+    }
+
+    #[test]
+    fn transactions_move_balances_until_voided() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let wallet = create(&database, &user_id, "Wallet", Money::from_cents(10000));
+        let market = create(&database, &user_id, "Market", Money::ZERO);
+        let balance = |id: &Uuid| database.get_account(id, &user_id).unwrap().balance;
+
+        let created = create_transaction(
+            &database,
+            &user_id,
+            transfer(day(1), &wallet, &market, 2500),
+        )
+        .unwrap();
+        assert_eq!(created.exit_account_name, Some("Wallet".to_string()));
+        assert_eq!(created.entry_account_name, Some("Market".to_string()));
+        assert_eq!(balance(&wallet), Money::from_cents(7500));
+        assert_eq!(balance(&market), Money::from_cents(2500));
+
+        let edit = || transaction::UpdatedTransaction {
+            entry_date: None,
+            entry_account_code: None,
+            exit_account_code: None,
+            amount: Some(Money::from_cents(4000)),
+            description: None,
+        };
+        edit_transaction(&database, &user_id, &created.id, edit()).unwrap();
+        assert_eq!(balance(&wallet), Money::from_cents(6000));
+        assert_eq!(balance(&market), Money::from_cents(4000));
+
+        assert!(
+            void_transaction(&database, &user_id, &created.id)
+                .unwrap()
+                .voided
+        );
+        assert_eq!(balance(&wallet), Money::from_cents(10000));
+        assert_eq!(balance(&market), Money::ZERO);
+        assert!(matches!(
+            edit_transaction(&database, &user_id, &created.id, edit()),
+            Err(TransactionServiceError::TransactionModelFailed(
+                transaction::TransactionModelError::TransactionIsVoided
+            ))
+        ));
+    }
+
+    #[test]
+    fn accounts_of_other_users_cannot_be_used() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let other_user_id = Uuid::new_v4();
+        let wallet = create(&database, &user_id, "Wallet", Money::from_cents(10000));
+        let foreign = create(&database, &other_user_id, "Foreign", Money::ZERO);
+
+        assert!(matches!(
+            create_transaction(
+                &database,
+                &user_id,
+                transfer(day(1), &wallet, &foreign, 2500)
+            ),
+            Err(TransactionServiceError::TransactionModelFailed(
+                transaction::TransactionModelError::AccountNotFound
+            ))
+        ));
+        assert_eq!(
+            database.get_account(&wallet, &user_id).unwrap().balance,
+            Money::from_cents(10000)
+        );
+        assert!(database
+            .list_user_transactions(&user_id)
+            .unwrap()
+            .is_empty());
+
+        let created =
+            create_transaction(&database, &user_id, transfer(day(1), &wallet, &wallet, 100))
+                .unwrap();
+        assert!(matches!(
+            delete_transaction(&database, &other_user_id, &created.id),
+            Err(TransactionServiceError::TransactionModelFailed(
+                transaction::TransactionModelError::TransactionNotFound
+            ))
+        ));
+    }
+
+    #[test]
+    fn pages_continue_after_the_cursor() {
+        let database = InMemoryStore::default();
+        let user_id = Uuid::new_v4();
+        let wallet = create(&database, &user_id, "Wallet", Money::from_cents(10000));
+        let market = create(&database, &user_id, "Market", Money::ZERO);
+        for d in [3, 1, 2] {
+            create_transaction(&database, &user_id, transfer(day(d), &wallet, &market, 100))
+                .unwrap();
+        }
+        let page = |after: Option<transaction::TransactionCursor>| {
+            list_user_transactions(
+                &database,
+                &user_id,
+                transaction::TransactionFilter::default(),
+                None,
+                transaction::TransactionPageRequest {
+                    first: 2,
+                    after,
+                    order: Order::DESC,
+                },
+            )
+            .unwrap()
+        };
+
+        let first = page(None);
+        let dates: Vec<NaiveDate> = first.transactions.iter().map(|t| t.entry_date).collect();
+        assert_eq!(dates, vec![day(3), day(2)]);
+        assert!(first.has_next_page);
+        assert!(!first.has_previous_page);
+
+        let cursor = parse_cursor(&first.transactions[1].cursor().encode()).unwrap();
+        let second = page(Some(cursor));
+        let dates: Vec<NaiveDate> = second.transactions.iter().map(|t| t.entry_date).collect();
+        assert_eq!(dates, vec![day(1)]);
+        assert!(!second.has_next_page);
+        assert!(second.has_previous_page);
+    }
 }
@@ -1,14 +1,15 @@
 use std::fmt;
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
+use log;
 use rand::Rng;
 
 use uuid::Uuid;
 
 use crate::{
-    entities::{integration, transaction, user, Env},
+    entities::{integration, session, transaction, user, Env, Language},
     jwt,
-    sendemail::send_code,
+    sendemail::{self, templates, Mailer},
 };
 
 #[derive(Debug)]
@@ -16,8 +17,20 @@ pub enum UserServiceError {
     UserModelFailed(user::UserModelError),
     TransactionModelFailed(transaction::TransactionModelError),
     UserIntegrationModelFailed(integration::IntegrationModelError),
+    SessionModelFailed(session::SessionModelError),
     JwtError(jwt::JwtError),
+    MailerFailed(sendemail::MailerError),
     LoginCodeNotMatching,
+    LoginCodeExpired,
+    // Too many wrong codes: no code is accepted until the given time.
+    LoginLocked(NaiveDateTime),
+    // A new code can only be sent after the given time.
+    LoginCodeResendCooldown(NaiveDateTime),
+    InvalidPayday(i32),
+    InvalidGracePeriod(i32),
+    UsernameNotAvailable(String),
+    EmailNotAvailable(String),
+    NoPendingEmailChange,
 }
 
 impl fmt::Display for UserServiceError {
@@ -44,19 +57,111 @@ impl From<integration::IntegrationModelError> for UserServiceError {
     }
 }
 
+impl From<session::SessionModelError> for UserServiceError {
+    fn from(error: session::SessionModelError) -> Self {
+        UserServiceError::SessionModelFailed(error)
+    }
+}
+
 impl From<jwt::JwtError> for UserServiceError {
     fn from(error: jwt::JwtError) -> Self {
         UserServiceError::JwtError(error)
     }
 }
 
+impl From<sendemail::MailerError> for UserServiceError {
+    fn from(error: sendemail::MailerError) -> Self {
+        UserServiceError::MailerFailed(error)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, UserServiceError>;
 
+const LOGIN_CODE_TTL_IN_MINUTES: i64 = 10;
+const MAX_LOGIN_CODE_ATTEMPTS: i32 = 5;
+const LOGIN_LOCKOUT_IN_MINUTES: i64 = 15;
+const LOGIN_CODE_RESEND_COOLDOWN_IN_SECONDS: i64 = 60;
+pub const TEST_LOGIN_CODE: i32 = 123456;
+const MAX_DELETION_GRACE_PERIOD_IN_DAYS: i32 = 90;
+
+fn check_not_locked(state: &user::LoginCodeState, now: NaiveDateTime) -> Result<()> {
+    match state.locked_until {
+        Some(until) if now < until => Err(UserServiceError::LoginLocked(until)),
+        _ => Ok(()),
+    }
+}
+
+fn check_resend_cooldown(state: &user::LoginCodeState, now: NaiveDateTime) -> Result<()> {
+    check_not_locked(state, now)?;
+    match state.generated_at {
+        Some(generated_at) => {
+            let available_at =
+                generated_at + Duration::seconds(LOGIN_CODE_RESEND_COOLDOWN_IN_SECONDS);
+            if now < available_at {
+                Err(UserServiceError::LoginCodeResendCooldown(available_at))
+            } else {
+                Ok(())
+            }
+        }
+        None => Ok(()),
+    }
+}
+
+// A used code is burned, so it looks just like an expired one.
+fn check_login_code(
+    state: &user::LoginCodeState,
+    login_code: i32,
+    now: NaiveDateTime,
+) -> Result<()> {
+    check_not_locked(state, now)?;
+    match (state.login_code, state.generated_at) {
+        (Some(code), Some(generated_at))
+            if now - generated_at < Duration::minutes(LOGIN_CODE_TTL_IN_MINUTES) =>
+        {
+            if code == login_code {
+                Ok(())
+            } else {
+                Err(UserServiceError::LoginCodeNotMatching)
+            }
+        }
+        _ => Err(UserServiceError::LoginCodeExpired),
+    }
+}
+
+fn check_payday(payday: i32) -> Result<()> {
+    if (1..=31).contains(&payday) {
+        Ok(())
+    } else {
+        Err(UserServiceError::InvalidPayday(payday))
+    }
+}
+
+// The code confirming a new email is checked just like a login code.
+fn email_change_state(change: &user::EmailChange) -> user::LoginCodeState {
+    user::LoginCodeState {
+        login_code: change.code,
+        generated_at: change.requested_at,
+        locked_until: None,
+        attempts: change.attempts,
+    }
+}
+
+// TODO: Use login_code as a String to generate a code more dificult to crack
+fn generate_login_code(env: &Env) -> i32 {
+    match env {
+        Env::TEST => TEST_LOGIN_CODE,
+        Env::DEV | Env::PROD => rand::thread_rng().gen_range(100000..999999),
+    }
+}
+
 pub fn create_user<T: user::UserModel>(
     database: &T,
+    mailer: &dyn Mailer,
     username: &str,
     name: &str,
     email: &str,
+    language: Language,
+    env: &Env,
 ) -> Result<user::UserWithIntegrations> {
     let new_user = user::NewUser {
         name: name.to_string(),
@@ -66,53 +171,202 @@ pub fn create_user<T: user::UserModel>(
     let user = database
         .create_user(new_user)?
         .with_integrations(Vec::new());
-    refresh_login_code(database, email)?;
+    // Without the code the user could never log in, and the username and
+    // email would stay taken.
+    if let Err(err) = refresh_login_code(database, mailer, email, language, env) {
+        database.delete_user(&user.id)?;
+        return Err(err);
+    }
 
     Ok(user)
 }
 
-pub fn refresh_login_code<T: user::UserModel>(database: &T, email: &str) -> Result<()> {
+// The code is only stored once it was delivered, so a failed delivery can be
+// retried right away.
+pub fn refresh_login_code<T: user::UserModel>(
+    database: &T,
+    mailer: &dyn Mailer,
+    email: &str,
+    language: Language,
+    env: &Env,
+) -> Result<()> {
     let last_code_gen_request = Utc::now().naive_utc();
-    // TODO: Use login_code as a String to generate a code more dificult to crack
-    let mut rng = rand::thread_rng();
-    let login_code = rng.gen_range(100000..999999);
-    send_code(&email, &login_code);
+    check_resend_cooldown(
+        &database.get_login_code_state(email)?,
+        last_code_gen_request,
+    )?;
+    let login_code = generate_login_code(env);
+    mailer.send(&templates::login_code(email, login_code, language))?;
     database.refresh_login_code(email, login_code, last_code_gen_request)?;
     Ok(())
 }
 
-pub fn auth_and_delete_user<
-    T: user::UserModel + transaction::TransactionModel + integration::IntegrationModel,
->(
+// Without a grace period everything is deleted right away. With one, the
+// user is logged out everywhere and logging in again before it ends cancels
+// the deletion.
+pub fn delete_user<T: user::UserModel + integration::IntegrationModel + session::SessionModel>(
+    database: &T,
+    id: Uuid,
+    grace_period_days: Option<i32>,
+    now: NaiveDateTime,
+) -> Result<user::UserWithIntegrations> {
+    let integrations = database.list_user_integrations(&id)?;
+    match grace_period_days {
+        None => Ok(database
+            .delete_user(&id)?
+            .ok_or(user::UserModelError::UserDoesNotExists)?
+            .with_integrations(integrations)),
+        Some(days) => {
+            if !(1..=MAX_DELETION_GRACE_PERIOD_IN_DAYS).contains(&days) {
+                return Err(UserServiceError::InvalidGracePeriod(days));
+            }
+            database.revoke_user_sessions(&id, now)?;
+            Ok(database
+                .schedule_user_deletion(&id, now + Duration::days(days.into()))?
+                .with_integrations(integrations))
+        }
+    }
+}
+
+// Deletes the users whose grace period is over. One failure doesn't stop
+// the others; it is retried on the next run.
+pub fn purge_scheduled_deletions<T: user::UserModel>(
+    database: &T,
+    now: NaiveDateTime,
+) -> Result<usize> {
+    let mut deleted = 0;
+    for id in database.list_users_due_for_deletion(now)? {
+        match database.delete_user(&id) {
+            Ok(Some(_)) => deleted += 1,
+            Ok(None) => (),
+            Err(err) => log::error!("Failed to delete user {}: {:?}", id, err),
+        }
+    }
+    Ok(deleted)
+}
+
+pub fn set_payday<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    id: Uuid,
+    payday: Option<i32>,
 ) -> Result<user::UserWithIntegrations> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    delete_user(database, id)
+    if let Some(day) = payday {
+        check_payday(day)?;
+    }
+    let integrations = database.list_user_integrations(&id)?;
+    Ok(database
+        .set_payday(&id, payday)?
+        .with_integrations(integrations))
 }
 
-fn delete_user<
-    T: user::UserModel + transaction::TransactionModel + integration::IntegrationModel,
->(
+// A new email is only used once the code sent to it is confirmed with
+// `confirm_email_change`. Nothing is saved if any field is invalid or the
+// code could not be sent.
+#[allow(clippy::too_many_arguments)]
+pub fn update_profile<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
+    mailer: &dyn Mailer,
     id: Uuid,
+    profile: user::UpdatedProfile,
+    email: Option<String>,
+    language: Language,
+    env: &Env,
+    now: NaiveDateTime,
 ) -> Result<user::UserWithIntegrations> {
-    database.delete_transaction_by_user_id(&id)?;
-    let integrations = database.delete_integration_by_user_id(&id)?;
-    Ok(database.delete_user(&id)?.with_integrations(integrations))
+    let user = database.get_user(id)?;
+    if let Some(Some(day)) = profile.payday {
+        check_payday(day)?;
+    }
+    // Keeping the current username or email is not a change.
+    let username = profile
+        .username
+        .filter(|username| *username != user.username);
+    if let Some(username) = &username {
+        if !database.check_if_username_available(username)? {
+            return Err(UserServiceError::UsernameNotAvailable(username.clone()));
+        }
+    }
+    let email = email.filter(|email| *email != user.email);
+    if let Some(email) = &email {
+        if !database.check_if_email_available(email)? {
+            return Err(UserServiceError::EmailNotAvailable(email.clone()));
+        }
+        check_resend_cooldown(&email_change_state(&database.get_email_change(&id)?), now)?;
+    }
+
+    // The code is sent before anything is saved, so a failed delivery
+    // changes nothing.
+    let email_change = match email {
+        Some(email) => {
+            let code = generate_login_code(env);
+            mailer.send(&templates::email_change_code(&email, code, language))?;
+            Some(user::NewEmailChange {
+                email,
+                code,
+                time: now,
+            })
+        }
+        None => None,
+    };
+    let profile = user::UpdatedProfile {
+        username,
+        ..profile
+    };
+    let user = if profile.is_empty() && email_change.is_none() {
+        user
+    } else {
+        // Someone else may have taken the username since it was checked.
+        let username = profile.username.clone();
+        match (
+            database.update_profile(&id, profile, email_change),
+            username,
+        ) {
+            (Err(user::UserModelError::UserAlreadyExists), Some(username)) => {
+                return Err(UserServiceError::UsernameNotAvailable(username))
+            }
+            (result, _) => result?,
+        }
+    };
+    let integrations = database.list_user_integrations(&id)?;
+    Ok(user.with_integrations(integrations))
 }
 
-pub fn auth_and_get_user<T: user::UserModel + integration::IntegrationModel>(
+// Too many wrong codes cancel the change, which then has to be requested
+// again.
+pub fn confirm_email_change<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    id: Uuid,
+    code: i32,
+    now: NaiveDateTime,
 ) -> Result<user::UserWithIntegrations> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    get_user(database, id)
+    let change = database.get_email_change(&id)?;
+    let email = change
+        .email
+        .clone()
+        .ok_or(UserServiceError::NoPendingEmailChange)?;
+    match check_login_code(&email_change_state(&change), code, now) {
+        Ok(()) => (),
+        Err(UserServiceError::LoginCodeNotMatching) => {
+            if database.register_failed_email_change_attempt(&id)? >= MAX_LOGIN_CODE_ATTEMPTS {
+                database.cancel_email_change(&id)?;
+            }
+            return Err(UserServiceError::LoginCodeNotMatching);
+        }
+        Err(err) => return Err(err),
+    }
+
+    // Someone else may have signed up with the address in the meantime.
+    match database.confirm_email_change(&id, &email) {
+        Ok(user) => Ok(user.with_integrations(database.list_user_integrations(&id)?)),
+        Err(user::UserModelError::UserAlreadyExists) => {
+            database.cancel_email_change(&id)?;
+            Err(UserServiceError::EmailNotAvailable(email))
+        }
+        Err(err) => Err(err.into()),
+    }
 }
 
-fn get_user<T: user::UserModel + integration::IntegrationModel>(
+pub fn get_user<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
     id: Uuid,
 ) -> Result<user::UserWithIntegrations> {
@@ -120,42 +374,51 @@ fn get_user<T: user::UserModel + integration::IntegrationModel>(
     Ok(database.get_user(id)?.with_integrations(integrations))
 }
 
-pub fn validate_and_generate_token<T: user::UserModel>(
+// Returns the id of the user the code belongs to. A code works only once,
+// and too many wrong guesses burn it and lock the email for a while.
+// `magic_login_code` logs into any account, so it is ignored outside of DEV.
+pub fn verify_login_code<T: user::UserModel>(
     database: &T,
-    email: String,
+    email: &str,
     login_code: i32,
-    jwt_secret: &str,
     env: &Env,
-) -> Result<String> {
-    let real_login_code = database.get_login_code(&email)?;
-    let id = database.get_id_by_email(&email)?;
+    magic_login_code: Option<i32>,
+    now: NaiveDateTime,
+) -> Result<Uuid> {
+    let id = database.get_id_by_email(email)?;
+    let is_magic = matches!(
+        (env, magic_login_code),
+        (Env::DEV, Some(magic)) if login_code == magic
+    );
 
-    let token = jwt::generate_token(Utc::now().naive_utc(), &id, jwt_secret)?;
-
-    match env {
-        Env::DEV => Ok(token),
-        Env::PROD => {
-            if login_code == real_login_code {
-                Ok(token)
-            } else {
+    if !is_magic {
+        database.attempt_login_code(email, |state| {
+            match check_login_code(state, login_code, now) {
+                Ok(()) => (user::LoginCodeAttempt::Accept, Ok(())),
                 Err(UserServiceError::LoginCodeNotMatching)
+                    if state.attempts + 1 >= MAX_LOGIN_CODE_ATTEMPTS =>
+                {
+                    let until = now + Duration::minutes(LOGIN_LOCKOUT_IN_MINUTES);
+                    (
+                        user::LoginCodeAttempt::Lock(until),
+                        Err(UserServiceError::LoginLocked(until)),
+                    )
+                }
+                Err(UserServiceError::LoginCodeNotMatching) => (
+                    user::LoginCodeAttempt::Fail,
+                    Err(UserServiceError::LoginCodeNotMatching),
+                ),
+                Err(err) => (user::LoginCodeAttempt::Keep, Err(err)),
             }
-        }
+        })??;
     }
-}
 
-pub fn auth_and_create_integration<T: user::UserModel + integration::IntegrationModel>(
-    database: &T,
-    token: &str,
-    jwt_secret: &str,
-    name: String,
-    time: NaiveDateTime,
-) -> Result<integration::UserIntegration> {
-    let id = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
-    create_integration(database, id, name, time)
+    // Logging in is how a user takes back an account scheduled for deletion.
+    database.cancel_user_deletion(&id)?;
+    Ok(id)
 }
 
-fn create_integration<T: user::UserModel + integration::IntegrationModel>(
+pub fn create_integration<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
     id: Uuid,
     name: String,
@@ -165,16 +428,263 @@ fn create_integration<T: user::UserModel + integration::IntegrationModel>(
         related_user: id,
         name,
         time,
+        status: integration::IntegrationStatus::CREATED,
+        sent_to: None,
+        state: None,
     };
     Ok(database.create_integration(new_integration)?)
 }
 
-pub fn auth_and_delete_integration<T: user::UserModel + integration::IntegrationModel>(
+pub fn delete_integration<T: user::UserModel + integration::IntegrationModel>(
     database: &T,
-    token: &str,
-    jwt_secret: &str,
+    user_id: Uuid,
     id: Uuid,
 ) -> Result<integration::UserIntegration> {
-    let _ = jwt::verify_token(Utc::now().naive_utc(), token, jwt_secret)?;
+    let _ = database.get_integration(&id, &user_id)?;
     Ok(database.delete_integration(&id)?)
 }
+
+#[cfg(test)]
+mod user_tests {
+    use super::*;
+    use crate::entities::user::UserModel;
+    use crate::models::memory::InMemoryStore;
+    use crate::sendemail::{Email, InMemoryMailer, MailerError};
+
+    const NOW: i64 = 1665366563127;
+    const CODE: i32 = 123456;
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(NOW, 0)
+    }
+
+    fn state(generated_at: NaiveDateTime) -> user::LoginCodeState {
+        user::LoginCodeState {
+            login_code: Some(CODE),
+            generated_at: Some(generated_at),
+            locked_until: None,
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn fresh_code_is_accepted() {
+        assert!(check_login_code(&state(now()), CODE, now() + Duration::minutes(1)).is_ok());
+        assert!(matches!(
+            check_login_code(&state(now()), CODE + 1, now()),
+            Err(UserServiceError::LoginCodeNotMatching)
+        ));
+    }
+
+    #[test]
+    fn old_or_burned_code_is_expired() {
+        let later = now() + Duration::minutes(LOGIN_CODE_TTL_IN_MINUTES);
+        assert!(matches!(
+            check_login_code(&state(now()), CODE, later),
+            Err(UserServiceError::LoginCodeExpired)
+        ));
+        let burned = user::LoginCodeState {
+            login_code: None,
+            ..state(now())
+        };
+        assert!(matches!(
+            check_login_code(&burned, CODE, now()),
+            Err(UserServiceError::LoginCodeExpired)
+        ));
+    }
+
+    #[test]
+    fn locked_email_refuses_codes_until_lockout_ends() {
+        let until = now() + Duration::minutes(LOGIN_LOCKOUT_IN_MINUTES);
+        let locked = user::LoginCodeState {
+            locked_until: Some(until),
+            ..state(now())
+        };
+        assert!(matches!(
+            check_login_code(&locked, CODE, now()),
+            Err(UserServiceError::LoginLocked(_))
+        ));
+        assert!(matches!(
+            check_resend_cooldown(&locked, now()),
+            Err(UserServiceError::LoginLocked(_))
+        ));
+        assert!(check_resend_cooldown(&locked, until).is_ok());
+    }
+
+    #[test]
+    fn codes_cannot_be_resent_right_away() {
+        assert!(matches!(
+            check_resend_cooldown(&state(now()), now() + Duration::seconds(1)),
+            Err(UserServiceError::LoginCodeResendCooldown(_))
+        ));
+        let later = now() + Duration::seconds(LOGIN_CODE_RESEND_COOLDOWN_IN_SECONDS);
+        assert!(check_resend_cooldown(&state(now()), later).is_ok());
+    }
+
+    #[test]
+    fn payday_is_a_day_of_the_month() {
+        assert!(check_payday(1).is_ok());
+        assert!(check_payday(31).is_ok());
+        for day in [-1, 0, 32] {
+            assert!(matches!(
+                check_payday(day),
+                Err(UserServiceError::InvalidPayday(d)) if d == day
+            ));
+        }
+    }
+
+    fn new_user(database: &InMemoryStore, username: &str) -> user::User {
+        database
+            .create_user(user::NewUser {
+                name: "User Test".to_string(),
+                username: username.to_string(),
+                email: format!("{}@example.com", username),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn wrong_codes_lock_the_login() {
+        let database = InMemoryStore::default();
+        let user = new_user(&database, "lockout");
+        let verify = |code: i32, at: NaiveDateTime| {
+            verify_login_code(&database, &user.email, code, &Env::PROD, None, at)
+        };
+
+        database
+            .refresh_login_code(&user.email, CODE, now())
+            .unwrap();
+        for _ in 1..MAX_LOGIN_CODE_ATTEMPTS {
+            assert!(matches!(
+                verify(CODE + 1, now()),
+                Err(UserServiceError::LoginCodeNotMatching)
+            ));
+        }
+        assert!(matches!(
+            verify(CODE + 1, now()),
+            Err(UserServiceError::LoginLocked(_))
+        ));
+        assert!(matches!(
+            verify(CODE, now()),
+            Err(UserServiceError::LoginLocked(_))
+        ));
+
+        let later = now() + Duration::minutes(LOGIN_LOCKOUT_IN_MINUTES);
+        assert!(matches!(
+            verify(CODE, later),
+            Err(UserServiceError::LoginCodeExpired)
+        ));
+        database
+            .refresh_login_code(&user.email, CODE, later)
+            .unwrap();
+        assert_eq!(verify(CODE, later).unwrap(), user.id);
+    }
+
+    #[test]
+    fn email_change_is_dropped_when_it_cannot_be_confirmed() {
+        let database = InMemoryStore::default();
+        let mailer = InMemoryMailer::default();
+        let user = new_user(&database, "email-change");
+        let change_email = |email: &str| {
+            update_profile(
+                &database,
+                &mailer,
+                user.id,
+                user::UpdatedProfile {
+                    name: None,
+                    username: None,
+                    payday: None,
+                },
+                Some(email.to_string()),
+                Language::EN,
+                &Env::TEST,
+                now(),
+            )
+        };
+
+        change_email("first@example.com").unwrap();
+        for _ in 0..MAX_LOGIN_CODE_ATTEMPTS {
+            assert!(matches!(
+                confirm_email_change(&database, user.id, TEST_LOGIN_CODE + 1, now()),
+                Err(UserServiceError::LoginCodeNotMatching)
+            ));
+        }
+        assert!(matches!(
+            confirm_email_change(&database, user.id, TEST_LOGIN_CODE, now()),
+            Err(UserServiceError::NoPendingEmailChange)
+        ));
+
+        // Someone else signs up with the address before it is confirmed.
+        let pending = change_email("second@example.com").unwrap();
+        assert_eq!(
+            pending.pending_email,
+            Some("second@example.com".to_string())
+        );
+        new_user(&database, "second");
+        assert!(matches!(
+            confirm_email_change(&database, user.id, TEST_LOGIN_CODE, now()),
+            Err(UserServiceError::EmailNotAvailable(_))
+        ));
+        let user = database.get_user(user.id).unwrap();
+        assert_eq!(user.email, "email-change@example.com");
+        assert_eq!(user.pending_email, None);
+        assert_eq!(mailer.sent().len(), 2);
+    }
+
+    struct BrokenMailer;
+
+    impl Mailer for BrokenMailer {
+        fn send(&self, _: &Email) -> std::result::Result<(), MailerError> {
+            Err(MailerError::InvalidSmtpUrl("broken".to_string()))
+        }
+    }
+
+    #[test]
+    fn user_is_not_created_when_the_code_cannot_be_sent() {
+        let database = InMemoryStore::default();
+        let create = |mailer: &dyn Mailer| {
+            create_user(
+                &database,
+                mailer,
+                "undelivered",
+                "User Test",
+                "undelivered@example.com",
+                Language::EN,
+                &Env::TEST,
+            )
+        };
+
+        assert!(matches!(
+            create(&BrokenMailer),
+            Err(UserServiceError::MailerFailed(_))
+        ));
+        assert!(database.check_if_username_available("undelivered").unwrap());
+        assert!(create(&InMemoryMailer::default()).is_ok());
+    }
+
+    #[test]
+    fn profile_is_kept_when_the_email_change_code_cannot_be_sent() {
+        let database = InMemoryStore::default();
+        let user = new_user(&database, "unsent-change");
+        let result = update_profile(
+            &database,
+            &BrokenMailer,
+            user.id,
+            user::UpdatedProfile {
+                name: Some("New Name".to_string()),
+                username: None,
+                payday: Some(Some(5)),
+            },
+            Some("unsent@example.com".to_string()),
+            Language::EN,
+            &Env::TEST,
+            now(),
+        );
+
+        assert!(matches!(result, Err(UserServiceError::MailerFailed(_))));
+        let kept = database.get_user(user.id).unwrap();
+        assert_eq!(kept.name, user.name);
+        assert_eq!(kept.payday, None);
+        assert_eq!(kept.pending_email, None);
+    }
+}
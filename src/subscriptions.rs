@@ -0,0 +1,142 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+
+use futures::{future, SinkExt, StreamExt};
+use juniper::{DefaultScalarValue, Variables};
+use juniper_graphql_ws::{ClientMessage, Connection, ConnectionConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime;
+use tokio::task::{self, LocalSet};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::HeaderValue,
+    Message,
+};
+
+use crate::graphql_resolvers::{Context, Schema};
+
+// The subprotocol of subscriptions-transport-ws, spoken by Apollo and most
+// mobile GraphQL clients.
+const PROTOCOL: &str = "graphql-ws";
+
+// Serves the subscriptions of `schema` on a port of their own, as Rocket does
+// not upgrade connections to WebSocket. The futures juniper builds for
+// subscriptions are not `Send`, so the connections are served on a thread
+// of their own instead of the async workers.
+pub fn spawn_subscription_server(address: SocketAddr, context: Context, schema: Arc<Schema>) {
+    let spawned = thread::Builder::new()
+        .name("subscriptions".to_string())
+        .spawn(move || {
+            let runtime = match runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    log::error!("Failed to start the subscriptions runtime: {}", err);
+                    return;
+                }
+            };
+            LocalSet::new().block_on(&runtime, serve(address, context, schema));
+        });
+    if let Err(err) = spawned {
+        log::error!("Failed to start the subscriptions thread: {}", err);
+    }
+}
+
+async fn serve(address: SocketAddr, context: Context, schema: Arc<Schema>) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Failed to serve subscriptions on {}: {}", address, err);
+            return;
+        }
+    };
+    log::info!("Serving GraphQL subscriptions on ws://{}", address);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                task::spawn_local(serve_connection(stream, context.clone(), schema.clone()));
+            }
+            Err(err) => log::warn!("Failed to accept a subscription connection: {}", err),
+        }
+    }
+}
+
+fn accept_protocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    let offered = request
+        .headers()
+        .get_all("Sec-WebSocket-Protocol")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == PROTOCOL);
+    if offered {
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(PROTOCOL));
+    }
+    Ok(response)
+}
+
+// Clients authenticate in the payload of `connection_init`, with the same
+// token they send in the Authorization header of queries.
+fn token_of(params: &Variables) -> Option<String> {
+    ["Authorization", "authorization", "token"]
+        .iter()
+        .find_map(|key| params.get(*key)?.as_string_value())
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value).to_string())
+}
+
+async fn serve_connection(stream: TcpStream, context: Context, schema: Arc<Schema>) {
+    let socket = match tokio_tungstenite::accept_hdr_async(stream, accept_protocol).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            log::debug!("Subscription handshake failed: {}", err);
+            return;
+        }
+    };
+    let (mut socket_tx, mut socket_rx) = socket.split();
+
+    let init = move |params: Variables| async move {
+        let token = token_of(&params);
+        let context = context.for_connection(token).await;
+        Ok::<_, Infallible>(ConnectionConfig::new(context))
+    };
+    let (mut connection_tx, mut connection_rx) = Connection::new(schema, init).split();
+
+    let incoming = async move {
+        while let Some(Ok(message)) = socket_rx.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let message = match serde_json::from_str::<ClientMessage<DefaultScalarValue>>(&text) {
+                Ok(message) => message,
+                Err(err) => {
+                    log::debug!("Unreadable subscription message: {}", err);
+                    break;
+                }
+            };
+            if connection_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+    };
+    let outgoing = async move {
+        while let Some(message) = connection_rx.next().await {
+            let text = match serde_json::to_string(&message) {
+                Ok(text) => text,
+                Err(err) => {
+                    log::error!("Failed to write a subscription message: {}", err);
+                    break;
+                }
+            };
+            if socket_tx.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    };
+    // Either side closing ends the connection and all its subscriptions.
+    future::select(Box::pin(incoming), Box::pin(outgoing)).await;
+}
@@ -5,6 +5,6 @@ pub fn first_or<T>(a: Option<T>, b: Option<T>) -> Option<T> {
     }
 }
 
-pub fn opt_result_of_result_opt<T, E>(x: Option<Result<T, E>>) -> Result<Option<T>, E> {
-    x.map_or(Ok(None), |v| v.map(Some))
-}
\ No newline at end of file
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
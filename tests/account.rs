@@ -1,48 +1,141 @@
-use cashtools::entities::account::{AccountModel, Earning, EarningIndex, NewAccount};
+use cashtools::entities::{
+    account::{AccountModel, AccountModelError, Earning, EarningIndex, NewAccount, UpdatedAccount},
+    money::Money,
+    user::UserModel,
+};
+use cashtools::graphql_loaders::AccountNames;
 mod common;
-use env_logger;
 use uuid::Uuid;
 
 #[test]
 fn create_and_delete_account() {
     let conn = common::make_conn();
-    let user_id: Uuid = Uuid::from_u128(160141200314647599499076565412518613020);
+    let user_id = common::new_user(&conn);
     let new_account = NewAccount {
-        time: common::now(),
-        initial_balance: 15.0,
-        name: format!("test account - {}", Uuid::new_v4()),
         description: Some("test account description".to_string()),
-        pre_allocation: None,
-        earning: None,
         is_available: false,
+        ..common::new_account("Wallet", Money::from_cents(1500))
     };
     let account = conn
         .create_account(user_id, new_account)
         .expect(common::DEFAULT_MESSAGE);
     conn.delete_account(&account.id, &user_id)
         .expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
 }
 
 #[test]
 fn create_and_delete_account_with_earning() {
     env_logger::init();
     let conn = common::make_conn();
-    let user_id: Uuid = Uuid::from_u128(160141200314647599499076565412518613020);
+    let user_id = common::new_user(&conn);
     let new_account = NewAccount {
-        time: common::now(),
-        initial_balance: 15.0,
-        name: format!("test account - {}", Uuid::new_v4()),
         description: Some("test account description".to_string()),
-        pre_allocation: None,
         earning: Some(Earning {
             rate: 15.0,
             index: EarningIndex::CDI,
         }),
         is_available: false,
+        ..common::new_account("Savings", Money::from_cents(1500))
     };
     let account = conn
         .create_account(user_id, new_account)
         .expect(common::DEFAULT_MESSAGE);
     conn.delete_account(&account.id, &user_id)
         .expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn account_names_are_unique_per_user() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let other_user_id = common::new_user(&conn);
+    let name = format!("Nubank - {}", Uuid::new_v4());
+    let trash = |in_trash: bool| UpdatedAccount {
+        name: None,
+        description: None,
+        pre_allocation: None,
+        earning: None,
+        is_available: None,
+        in_trash: Some(in_trash),
+    };
+
+    let account = conn
+        .create_account(user_id, common::new_account(&name, Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_account(other_user_id, common::new_account(&name, Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        conn.create_account(user_id, common::new_account(&name, Money::ZERO)),
+        Err(AccountModelError::NameAlreadyTaken)
+    ));
+
+    // A trashed account frees its name, and can't come back while it's taken.
+    conn.edit_account(&account.id, &user_id, trash(true))
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_account(user_id, common::new_account(&name, Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        conn.edit_account(&account.id, &user_id, trash(false)),
+        Err(AccountModelError::NameAlreadyTaken)
+    ));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&other_user_id)
+        .expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn account_names_are_looked_up_in_bulk() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let other_user_id = common::new_user(&conn);
+
+    let wallet = conn
+        .create_account(user_id, common::new_account("Wallet", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let savings = conn
+        .create_account(user_id, common::new_account("Savings", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let foreign = conn
+        .create_account(other_user_id, common::new_account("Foreign", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+
+    let names = conn
+        .get_account_names(&[wallet.id, savings.id, foreign.id], &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[&wallet.id], "Wallet");
+    assert_eq!(names[&savings.id], "Savings");
+
+    // The GraphQL loader answers every field with the names of one batch.
+    let loader = AccountNames::default();
+    let loaded = tokio::runtime::Runtime::new()
+        .expect(common::DEFAULT_MESSAGE)
+        .block_on(async {
+            tokio::join!(
+                loader.load(&conn, user_id, wallet.id),
+                loader.load(&conn, user_id, savings.id),
+                loader.load(&conn, user_id, foreign.id),
+                loader.load(&conn, user_id, wallet.id),
+            )
+        });
+    let loaded = [loaded.0, loaded.1, loaded.2, loaded.3]
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(
+        loaded,
+        vec![
+            Some("Wallet".to_string()),
+            Some("Savings".to_string()),
+            None,
+            Some("Wallet".to_string()),
+        ]
+    );
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&other_user_id)
+        .expect(common::DEFAULT_MESSAGE);
 }
@@ -0,0 +1,88 @@
+use cashtools::{
+    entities::{
+        account::AccountModel,
+        money::Money,
+        transaction::{NewTransaction, TransactionKind, TransactionModel},
+        user::UserModel,
+    },
+    services::{account::preallocate, budget},
+};
+use chrono::{Duration, NaiveDate};
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn envelopes_are_refilled_once_per_payday_cycle() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    // Payday on the 10th: 2022-03-10 is a Thursday and 2022-04-10 a Sunday,
+    // so the March cycle ends on 2022-04-07.
+    conn.set_payday(&user_id, Some(10))
+        .expect(common::DEFAULT_MESSAGE);
+    let source = conn
+        .create_account(
+            user_id,
+            common::new_account("Salary", Money::from_cents(100000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let envelope = conn
+        .create_account(user_id, common::new_account("Groceries", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let today = NaiveDate::from_ymd(2022, 3, 10);
+    let balance = |id: &Uuid| {
+        conn.get_account(id, &user_id)
+            .expect(common::DEFAULT_MESSAGE)
+            .balance
+    };
+
+    preallocate(
+        &conn,
+        &user_id,
+        today,
+        &source.id,
+        &envelope.id,
+        Money::from_cents(30000),
+        false,
+    )
+    .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(balance(&source.id), Money::from_cents(70000));
+    assert_eq!(balance(&envelope.id), Money::from_cents(30000));
+
+    let posted = budget::refill_envelopes(&conn, &user_id, today + Duration::days(5))
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(posted.is_empty());
+
+    conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: today,
+            entry_account_code: None,
+            exit_account_code: Some(envelope.id),
+            amount: Money::from_cents(12000),
+            description: None,
+            kind: TransactionKind::MANUAL,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+
+    let status = budget::budget_status(&conn, &user_id, today).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(status.cycle_start, today);
+    assert_eq!(status.cycle_end, NaiveDate::from_ymd(2022, 4, 7));
+    let envelope_status = status
+        .envelopes
+        .iter()
+        .find(|e| e.account_id == envelope.id)
+        .unwrap();
+    assert_eq!(envelope_status.allocated, Money::from_cents(30000));
+    assert_eq!(envelope_status.spent, Money::from_cents(12000));
+    assert_eq!(envelope_status.remaining, Money::from_cents(18000));
+
+    let posted = budget::refill_envelopes(&conn, &user_id, NaiveDate::from_ymd(2022, 4, 8))
+        .expect(common::DEFAULT_MESSAGE);
+    let kinds: Vec<TransactionKind> = posted.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![TransactionKind::SWEEP, TransactionKind::REFILL]);
+    assert_eq!(balance(&source.id), Money::from_cents(58000));
+    assert_eq!(balance(&envelope.id), Money::from_cents(30000));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
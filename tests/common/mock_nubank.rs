@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cashtools::nubank::{
+    HttpError, HttpRequest, HttpResponse, Method, NubankHttp, NubankTransport,
+};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::{X509NameBuilder, X509};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const BASE_URL: &str = "http://nubank.mock";
+const ENCRYPTED_CODE: &str = "mock-encrypted-code";
+const ACCESS_TOKEN: &str = "mock-access-token";
+
+pub const CPF: &str = "12345678900";
+pub const PASSWORD: &str = "mock-password";
+pub const CODE: &str = "A1B2C3";
+pub const SENT_TO: &str = "t***@example.com";
+
+// Card purchases are always outflows: 12.50 + 45.00.
+const CARD_FEED: &str = r#"{"events": [
+    {"id": "card-1", "category": "transaction", "description": "Padaria", "amount": 1250, "time": "2022-10-01T12:30:00Z"},
+    {"id": "card-2", "category": "transaction", "description": "Mercado", "amount": 4500, "time": "2022-10-02T18:00:00Z"},
+    {"id": "card-3", "category": "payment", "description": "Pagamento recebido", "amount": 5750, "time": "2022-10-05T09:00:00Z"}
+]}"#;
+
+// One inflow of 100.00 and one outflow of 80.50. The card bill payment is
+// left out, the purchases were already imported from the card feed.
+const ACCOUNT_FEED: &str = r#"{"data": {"viewer": {"savingsAccount": {"feed": [
+    {"id": "account-1", "__typename": "TransferInEvent", "title": "Transferência recebida", "detail": "Fulano - R$ 100,00", "postDate": "2022-10-03", "amount": 100.0},
+    {"id": "account-2", "__typename": "BarcodePaymentEvent", "title": "Pagamento efetuado", "detail": "Conta de luz", "postDate": "2022-10-04", "amount": 80.5},
+    {"id": "account-3", "__typename": "WelcomeEvent", "title": "Bem-vindo", "postDate": "2022-10-01", "amount": null},
+    {"id": "account-4", "__typename": "BillPaymentEvent", "title": "Pagamento da fatura", "postDate": "2022-10-05", "amount": 57.5}
+]}}}}"#;
+
+// An in-process stand-in for Nubank: discovery, certificate generation with
+// the code sent by email, the certificate-authenticated token endpoint and
+// both feeds. It only knows one customer, CPF/PASSWORD, whose code is CODE.
+// Refresh tokens can be used once.
+struct MockNubank {
+    ca_key: PKey<Private>,
+    refresh_tokens: Mutex<HashSet<String>>,
+}
+
+pub fn transport() -> NubankTransport {
+    NubankTransport {
+        discovery_url: format!("{}/api/discovery", BASE_URL),
+        http: Arc::new(MockNubank::new()),
+    }
+}
+
+fn response(body: Value) -> HttpResponse {
+    HttpResponse {
+        status: 200,
+        headers: HashMap::new(),
+        body: body.to_string(),
+    }
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    HttpResponse {
+        status,
+        ..response(json!({ "error": message }))
+    }
+}
+
+fn unauthorized() -> HttpResponse {
+    error(401, "Unauthorized")
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn json_body(request: &HttpRequest) -> Value {
+    request
+        .body
+        .as_ref()
+        .and_then(|body| serde_json::from_str(body).ok())
+        .unwrap_or(Value::Null)
+}
+
+fn has_credentials(body: &Value) -> bool {
+    body["login"] == CPF && body["password"] == PASSWORD
+}
+
+impl MockNubank {
+    fn new() -> MockNubank {
+        let rsa = Rsa::generate(2048).unwrap();
+        MockNubank {
+            ca_key: PKey::from_rsa(rsa).unwrap(),
+            refresh_tokens: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn discovery(&self) -> HttpResponse {
+        response(json!({
+            "token": format!("{}/api/token", BASE_URL),
+            "gen_certificate": format!("{}/api/gen-certificate", BASE_URL),
+        }))
+    }
+
+    // The first call answers with the code challenge in the WWW-Authenticate
+    // header; the second one, with the code, issues the certificate.
+    fn gen_certificate(&self, request: &HttpRequest) -> HttpResponse {
+        let body = json_body(request);
+        if !has_credentials(&body) {
+            return unauthorized();
+        }
+        match body.get("code") {
+            None => HttpResponse {
+                status: 401,
+                headers: HashMap::from([(
+                    "www-authenticate".to_string(),
+                    format!(
+                        "device-authorization encrypted-code=\"{}\", sent-to=\"{}\"",
+                        ENCRYPTED_CODE, SENT_TO
+                    ),
+                )]),
+                body: String::new(),
+            },
+            Some(code) if code == CODE && body["encrypted-code"] == ENCRYPTED_CODE => {
+                match body["public_key"].as_str() {
+                    Some(public_key) => response(json!({
+                        "certificate": self.issue_certificate(public_key),
+                    })),
+                    None => unauthorized(),
+                }
+            }
+            Some(_) => unauthorized(),
+        }
+    }
+
+    fn token(&self, request: &HttpRequest) -> HttpResponse {
+        let body = json_body(request);
+        let mut refresh_tokens = self.refresh_tokens.lock().unwrap();
+        let authorized = match body["grant_type"].as_str() {
+            Some("password") => has_credentials(&body),
+            Some("refresh_token") => body["refresh_token"]
+                .as_str()
+                .map_or(false, |token| refresh_tokens.remove(token)),
+            _ => false,
+        };
+        if request.certificate.is_none() || !authorized {
+            return unauthorized();
+        }
+        let refresh_token = Uuid::new_v4().to_string();
+        refresh_tokens.insert(refresh_token.clone());
+        let link = |path: &str| json!({ "href": format!("{}/api/{}", BASE_URL, path) });
+        response(json!({
+            "access_token": ACCESS_TOKEN,
+            "refresh_token": refresh_token,
+            "_links": {
+                "events": link("feed"),
+                "bills_summary": link("bills"),
+                "customer": link("customer"),
+                "ghostflame": link("query"),
+                "revoke_token": link("revoke"),
+            },
+        }))
+    }
+
+    fn feed(&self, request: &HttpRequest, feed: &str) -> HttpResponse {
+        match header(request, "Authorization") {
+            Some(authorization) if authorization == format!("Bearer {}", ACCESS_TOKEN) => {
+                HttpResponse {
+                    status: 200,
+                    headers: HashMap::new(),
+                    body: feed.to_string(),
+                }
+            }
+            _ => unauthorized(),
+        }
+    }
+
+    fn issue_certificate(&self, public_key_pem: &str) -> String {
+        let public_key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "Nubank Mock").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&public_key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+            .unwrap();
+        builder.sign(&self.ca_key, MessageDigest::sha256()).unwrap();
+
+        String::from_utf8(builder.build().to_pem().unwrap()).unwrap()
+    }
+}
+
+#[async_trait]
+impl NubankHttp for MockNubank {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, HttpError> {
+        let path = request.url.trim_start_matches(BASE_URL);
+        Ok(match (request.method, path) {
+            (Method::GET, "/api/discovery") => self.discovery(),
+            (Method::POST, "/api/gen-certificate") => self.gen_certificate(&request),
+            (Method::POST, "/api/token") => self.token(&request),
+            (Method::GET, "/api/feed") => self.feed(&request, CARD_FEED),
+            (Method::POST, "/api/query") => self.feed(&request, ACCOUNT_FEED),
+            _ => error(404, "Not Found"),
+        })
+    }
+}
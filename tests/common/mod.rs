@@ -0,0 +1,36 @@
+// Shared by every test, which each use only some of it.
+#![allow(dead_code)]
+
+pub mod mock_nubank;
+
+pub use cashtools::models::memory::new_account;
+use cashtools::{
+    database,
+    entities::user::{NewUser, UserModel},
+};
+use chrono::NaiveDateTime;
+use std::env;
+use uuid::Uuid;
+
+pub fn make_conn() -> database::DbPool {
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    database::establish_pooled_connection(database_url, &database::PoolConfig::default())
+}
+pub fn now() -> NaiveDateTime {
+    const NOW: i64 = 1665366563127;
+    NaiveDateTime::from_timestamp(NOW, 0)
+}
+
+pub const DEFAULT_MESSAGE: &str = "It's an integration test. If all the unit tests are passing but this one is not, there's probably a problem with the database. Make sure the instance is running. If you are trying to use the remote database, make sure the proxy is running too.";
+
+// Everything stored belongs to a user that has to exist.
+pub fn new_user(conn: &database::DbPool) -> Uuid {
+    let unique = Uuid::new_v4();
+    conn.create_user(NewUser {
+        name: "Test User".to_string(),
+        username: format!("test-{}", unique),
+        email: format!("test-{}@example.com", unique),
+    })
+    .expect(DEFAULT_MESSAGE)
+    .id
+}
@@ -0,0 +1,72 @@
+use cashtools::{
+    entities::{
+        account::{AccountModel, Earning, EarningIndex, NewAccount},
+        money::Money,
+        transaction::{NewTransaction, TransactionKind, TransactionModel},
+        user::{NewUser, UserModel},
+    },
+    services::earning,
+};
+use chrono::NaiveDate;
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn earnings_accrue_on_the_daily_balance_and_never_on_today() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Earning Test".to_string(),
+            username: format!("earning-{}", unique),
+            email: format!("earning-{}@example.com", unique),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    // 2022-03-01 is a Tuesday.
+    let day = |d: u32| NaiveDate::from_ymd(2022, 3, d);
+    let account = conn
+        .create_account(
+            user_id,
+            NewAccount {
+                time: day(1).and_hms(12, 0, 0),
+                earning: Some(Earning {
+                    rate: 10.0,
+                    index: EarningIndex::FIXED,
+                }),
+                ..common::new_account("Savings", Money::ZERO)
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: day(2),
+            entry_account_code: Some(account.id),
+            exit_account_code: None,
+            amount: Money::from_cents(100000),
+            description: None,
+            kind: TransactionKind::MANUAL,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+
+    // The deposit only yields from the 3rd on, and today, the 4th, has not
+    // ended yet.
+    let posted =
+        earning::accrue_earnings(&conn, &user_id, day(31), day(4)).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0].entry_date, day(3));
+    let daily = 1.1_f64.powf(1.0 / 252.0);
+    assert_eq!(posted[0].amount, Money::from_f64(1000.0 * (daily - 1.0)));
+
+    let posted =
+        earning::accrue_earnings(&conn, &user_id, day(4), day(4)).expect(common::DEFAULT_MESSAGE);
+    assert!(posted.is_empty());
+
+    // The 4th is paid once it is over.
+    let posted =
+        earning::accrue_earnings(&conn, &user_id, day(31), day(5)).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0].entry_date, day(4));
+}
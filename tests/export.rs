@@ -0,0 +1,109 @@
+use std::io::{Cursor, Read};
+
+use cashtools::{
+    entities::{
+        account::AccountModel,
+        money::Money,
+        session::{NewSession, SessionModel},
+        tag::{NewTag, TagModel},
+        transaction::{NewTransaction, TransactionKind, TransactionModel},
+        user::{NewUser, UserModel},
+    },
+    services::export::export_user_data,
+};
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn export_has_every_file_as_json_and_csv() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Export Test".to_string(),
+            username: format!("export-{}", unique),
+            email: format!("export-{}@example.com", unique),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    let account_name = format!("export account - {}", unique);
+    let account = conn
+        .create_account(
+            user_id,
+            common::new_account(&account_name, Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: common::now().date(),
+            entry_account_code: None,
+            exit_account_code: Some(account.id),
+            amount: Money::from_cents(1000),
+            description: Some("Groceries".to_string()),
+            kind: TransactionKind::MANUAL,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+    let tag = conn
+        .create_tag(
+            &user_id,
+            NewTag {
+                name: "Travel".to_string(),
+                color: None,
+                time: common::now(),
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.tag_account(&account.id, &tag.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let session = conn
+        .create_session(NewSession {
+            related_user: user_id,
+            refresh_token_hash: unique.to_string(),
+            device_name: Some("Export laptop".to_string()),
+            time: common::now(),
+        })
+        .expect(common::DEFAULT_MESSAGE);
+
+    let export = export_user_data(&conn, &user_id, common::now()).expect(common::DEFAULT_MESSAGE);
+    assert!(export.file_name.ends_with(".zip"));
+
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(export.content)).expect(common::DEFAULT_MESSAGE);
+    let mut read = |name: &str| {
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .expect(common::DEFAULT_MESSAGE)
+            .read_to_string(&mut content)
+            .expect(common::DEFAULT_MESSAGE);
+        content
+    };
+    for name in [
+        "profile",
+        "accounts",
+        "transactions",
+        "integrations",
+        "tags",
+        "account_tags",
+        "sessions",
+    ] {
+        for extension in ["json", "csv"] {
+            read(&format!("{}.{}", name, extension));
+        }
+    }
+    assert!(read("profile.csv").contains(&format!("export-{}@example.com", unique)));
+    assert!(read("accounts.json").contains(&account_name));
+    let transactions = read("transactions.csv");
+    assert!(transactions.contains("Groceries"));
+    assert!(transactions.contains(&account_name));
+    assert_eq!(read("integrations.json").trim(), "[]");
+    assert!(read("tags.csv").contains("Travel"));
+    assert!(read("account_tags.csv").contains(&format!("{},{}", account.id, tag.id)));
+    let sessions = read("sessions.json");
+    assert!(sessions.contains(&session.id.to_string()));
+    assert!(!sessions.contains(&unique.to_string()));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
@@ -0,0 +1,47 @@
+use cashtools::entities::{
+    integration::{
+        IntegrationModel, IntegrationStatus, NewUserIntegration, UpdatedIntegrationState,
+    },
+    user::UserModel,
+};
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn integration_state_is_persisted_per_user() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let created = conn
+        .create_integration(NewUserIntegration {
+            related_user: user_id,
+            name: "Nubank".to_string(),
+            time: common::now(),
+            status: IntegrationStatus::PENDING,
+            sent_to: Some("t***@example.com".to_string()),
+            state: Some(vec![1, 2, 3]),
+        })
+        .expect(common::DEFAULT_MESSAGE);
+
+    assert!(conn.get_integration(&created.id, &Uuid::new_v4()).is_err());
+
+    let updated = conn
+        .update_integration_state(
+            &created.id,
+            &user_id,
+            UpdatedIntegrationState {
+                status: IntegrationStatus::CONNECTED,
+                sent_to: created.sent_to.clone(),
+                state: Some(vec![4, 5, 6]),
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(updated.status, IntegrationStatus::CONNECTED);
+
+    let fetched = conn
+        .get_integration(&created.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(fetched.state, Some(vec![4, 5, 6]));
+    assert_eq!(fetched.sent_to, created.sent_to);
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
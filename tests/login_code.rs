@@ -0,0 +1,206 @@
+use cashtools::{
+    entities::{
+        user::{NewUser, UserModel},
+        Env, Language,
+    },
+    sendemail::InMemoryMailer,
+    services::user::{refresh_login_code, verify_login_code, UserServiceError, TEST_LOGIN_CODE},
+};
+use chrono::{Duration, Utc};
+mod common;
+use std::thread;
+use uuid::Uuid;
+
+const LOGIN_CODE: i32 = 123456;
+
+#[test]
+fn login_codes_are_single_use_and_locked_after_wrong_guesses() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("login-code-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Login Code Test".to_string(),
+            username: format!("login-code-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    let verify =
+        |code: i32| verify_login_code(&conn, &email, code, &Env::PROD, None, common::now());
+
+    conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(verify(LOGIN_CODE).expect(common::DEFAULT_MESSAGE), user_id);
+    assert!(matches!(
+        verify(LOGIN_CODE),
+        Err(UserServiceError::LoginCodeExpired)
+    ));
+
+    conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    for _ in 0..4 {
+        assert!(matches!(
+            verify(LOGIN_CODE + 1),
+            Err(UserServiceError::LoginCodeNotMatching)
+        ));
+    }
+    assert!(matches!(
+        verify(LOGIN_CODE + 1),
+        Err(UserServiceError::LoginLocked(_))
+    ));
+    assert!(matches!(
+        verify(LOGIN_CODE),
+        Err(UserServiceError::LoginLocked(_))
+    ));
+
+    // Once the lockout is over, only a new code works.
+    let later = common::now() + Duration::minutes(20);
+    assert!(matches!(
+        verify_login_code(&conn, &email, LOGIN_CODE, &Env::PROD, None, later),
+        Err(UserServiceError::LoginCodeExpired)
+    ));
+    conn.refresh_login_code(&email, LOGIN_CODE, later)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(
+        verify_login_code(&conn, &email, LOGIN_CODE, &Env::PROD, None, later)
+            .expect(common::DEFAULT_MESSAGE),
+        user_id
+    );
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn wrong_guesses_across_resent_codes_lock_the_login() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("login-resend-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Login Resend Test".to_string(),
+            username: format!("login-resend-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    let verify =
+        |code: i32| verify_login_code(&conn, &email, code, &Env::PROD, None, common::now());
+
+    for _ in 0..2 {
+        conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+            .expect(common::DEFAULT_MESSAGE);
+        for _ in 0..2 {
+            assert!(matches!(
+                verify(LOGIN_CODE + 1),
+                Err(UserServiceError::LoginCodeNotMatching)
+            ));
+        }
+    }
+    conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        verify(LOGIN_CODE + 1),
+        Err(UserServiceError::LoginLocked(_))
+    ));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn concurrent_attempts_use_a_code_only_once() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("login-race-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Login Race Test".to_string(),
+            username: format!("login-race-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+
+    let logins = thread::scope(|scope| {
+        // Collected so every attempt is running before the first join.
+        #[allow(clippy::needless_collect)]
+        let attempts: Vec<_> = (0..8)
+            .map(|_| {
+                scope.spawn(|| {
+                    verify_login_code(&conn, &email, LOGIN_CODE, &Env::PROD, None, common::now())
+                })
+            })
+            .collect();
+        attempts
+            .into_iter()
+            .map(|attempt| attempt.join().unwrap())
+            .filter(Result::is_ok)
+            .count()
+    });
+    assert_eq!(logins, 1);
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn magic_login_code_only_works_in_dev() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("magic-code-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Magic Code Test".to_string(),
+            username: format!("magic-code-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    let magic = Some(424242);
+
+    // No code was ever sent to this user.
+    assert_eq!(
+        verify_login_code(&conn, &email, 424242, &Env::DEV, magic, common::now())
+            .expect(common::DEFAULT_MESSAGE),
+        user_id
+    );
+    for env in [Env::TEST, Env::PROD] {
+        assert!(verify_login_code(&conn, &email, 424242, &env, magic, common::now()).is_err());
+    }
+    assert!(verify_login_code(&conn, &email, 424242, &Env::DEV, None, common::now()).is_err());
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn test_env_emails_a_known_code() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("test-env-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Test Env Test".to_string(),
+            username: format!("test-env-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+
+    let mailer = InMemoryMailer::default();
+    refresh_login_code(&conn, &mailer, &email, Language::EN, &Env::TEST)
+        .expect(common::DEFAULT_MESSAGE);
+    let sent = mailer.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, email);
+    assert!(sent[0].text.contains(&TEST_LOGIN_CODE.to_string()));
+
+    let now = Utc::now().naive_utc();
+    assert_eq!(
+        verify_login_code(&conn, &email, TEST_LOGIN_CODE, &Env::TEST, None, now)
+            .expect(common::DEFAULT_MESSAGE),
+        user_id
+    );
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
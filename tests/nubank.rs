@@ -0,0 +1,234 @@
+use cashtools::{
+    entities::{
+        account::AccountModel,
+        integration::{IntegrationModel, IntegrationStatus, SyncReport},
+        money::Money,
+        transaction::TransactionKind,
+        user::UserModel,
+    },
+    events::{Event, EventBus},
+    models::memory::InMemoryStore,
+    nubank::NubankClient,
+    services::nubank,
+};
+mod common;
+use common::mock_nubank;
+use futures::StreamExt;
+use uuid::Uuid;
+
+const SECRET: &str = "1234567890987654321";
+
+fn report_of(imported: i32, skipped: i32, failed: i32) -> SyncReport {
+    SyncReport {
+        imported,
+        skipped,
+        failed,
+    }
+}
+
+#[tokio::test]
+async fn client_onboards_and_reads_feeds_from_the_mock() {
+    let transport = mock_nubank::transport();
+    let client = NubankClient::new()
+        .request_code_to_gen_cert(&transport, mock_nubank::CPF, mock_nubank::PASSWORD)
+        .await
+        .unwrap();
+    assert_eq!(client.sent_to.as_deref(), Some(mock_nubank::SENT_TO));
+
+    assert!(client
+        .gen_certificate(&transport, mock_nubank::CPF, mock_nubank::PASSWORD, "WRONG")
+        .await
+        .is_err());
+    let client = client
+        .gen_certificate(
+            &transport,
+            mock_nubank::CPF,
+            mock_nubank::PASSWORD,
+            mock_nubank::CODE,
+        )
+        .await
+        .unwrap();
+    assert!(client.refresh(&transport).await.is_err());
+
+    assert!(client
+        .authenticate(&transport, mock_nubank::CPF, "wrong password")
+        .await
+        .is_err());
+    let client = client
+        .authenticate(&transport, mock_nubank::CPF, mock_nubank::PASSWORD)
+        .await
+        .unwrap();
+    // Refresh tokens are replaced every time they are used.
+    let refreshed = client.refresh(&transport).await.unwrap();
+    assert!(client.refresh(&transport).await.is_err());
+    let client = refreshed.refresh(&transport).await.unwrap();
+
+    let items = client.fetch_events(&transport).await.unwrap();
+    assert_eq!(items.len(), 4);
+    assert!(items.iter().all(|item| item.is_ok()));
+}
+
+#[tokio::test]
+async fn integration_syncs_the_mock_feed_into_the_linked_account() {
+    let conn = common::make_conn();
+    let transport = mock_nubank::transport();
+    let user_id = common::new_user(&conn);
+
+    let started = nubank::start_nubank_integration(
+        &conn,
+        &transport,
+        &user_id,
+        SECRET,
+        common::now(),
+        mock_nubank::CPF.to_string(),
+        mock_nubank::PASSWORD.to_string(),
+    )
+    .await
+    .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(started.status, IntegrationStatus::PENDING);
+    assert_eq!(started.sent_to.as_deref(), Some(mock_nubank::SENT_TO));
+
+    let confirmed = nubank::confirm_nubank_integration(
+        &conn,
+        &transport,
+        &user_id,
+        SECRET,
+        &started.id,
+        mock_nubank::CPF.to_string(),
+        mock_nubank::PASSWORD.to_string(),
+        mock_nubank::CODE.to_string(),
+    )
+    .await
+    .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(confirmed.status, IntegrationStatus::CONNECTED);
+    let stored = conn
+        .get_integration(&confirmed.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let state = nubank::open_state(&stored, SECRET).expect(common::DEFAULT_MESSAGE);
+    let state = serde_json::to_string(&state).unwrap();
+    assert!(!state.contains(mock_nubank::CPF) && !state.contains(mock_nubank::PASSWORD));
+    assert!(nubank::open_state(&stored, "another key").is_err());
+
+    let account = conn
+        .create_account(
+            user_id,
+            common::new_account("Nubank", Money::from_cents(100000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.link_integration_account(&confirmed.id, &user_id, Some(account.id))
+        .expect(common::DEFAULT_MESSAGE);
+
+    let events = EventBus::default();
+    let mut published = Box::pin(events.subscribe(user_id));
+    let report =
+        nubank::sync_integration(&conn, &transport, &events, &user_id, SECRET, &confirmed.id)
+            .await
+            .expect(common::DEFAULT_MESSAGE);
+    assert_eq!((report.imported, report.skipped, report.failed), (4, 0, 0));
+
+    let report =
+        nubank::sync_integration(&conn, &transport, &events, &user_id, SECRET, &confirmed.id)
+            .await
+            .expect(common::DEFAULT_MESSAGE);
+    assert_eq!((report.imported, report.skipped, report.failed), (0, 4, 0));
+
+    // The first sync: started, four imports, done and the new balance.
+    let first_sync: Vec<Event> = published
+        .by_ref()
+        .take(7)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert!(matches!(
+        &first_sync[0],
+        Event::IntegrationSyncProgress(progress) if progress.total == 4 && !progress.done
+    ));
+    assert!(first_sync[1..5].iter().all(|event| matches!(
+        event,
+        Event::TransactionCreated(created) if created.kind == TransactionKind::IMPORT
+    )));
+    assert!(matches!(
+        &first_sync[5],
+        Event::IntegrationSyncProgress(progress) if progress.report == report_of(4, 0, 0) && progress.done
+    ));
+    assert!(matches!(
+        &first_sync[6],
+        Event::AccountUpdated(updated) if updated.balance == Money::from_cents(96200)
+    ));
+    // The second one imports nothing, so the account is left alone.
+    let second_sync: Vec<Event> = published.take(2).map(Result::unwrap).collect().await;
+    assert!(matches!(
+        &second_sync[1],
+        Event::IntegrationSyncProgress(progress) if progress.report == report_of(0, 4, 0) && progress.done
+    ));
+
+    // 1000 - 12.50 - 45.00 + 100.00 - 80.50
+    let balance = conn
+        .get_account(&account.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .balance;
+    assert_eq!(balance, Money::from_cents(96200));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[tokio::test]
+async fn integration_flows_run_on_the_memory_store() {
+    let database = InMemoryStore::default();
+    let transport = mock_nubank::transport();
+    let user_id = Uuid::new_v4();
+
+    let started = nubank::start_nubank_integration(
+        &database,
+        &transport,
+        &user_id,
+        SECRET,
+        common::now(),
+        mock_nubank::CPF.to_string(),
+        mock_nubank::PASSWORD.to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(started.status, IntegrationStatus::PENDING);
+
+    let confirmed = nubank::confirm_nubank_integration(
+        &database,
+        &transport,
+        &user_id,
+        SECRET,
+        &started.id,
+        mock_nubank::CPF.to_string(),
+        mock_nubank::PASSWORD.to_string(),
+        mock_nubank::CODE.to_string(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(confirmed.status, IntegrationStatus::CONNECTED);
+
+    let account = database
+        .create_account(
+            user_id,
+            common::new_account("Nubank", Money::from_cents(100000)),
+        )
+        .unwrap();
+    database
+        .link_integration_account(&confirmed.id, &user_id, Some(account.id))
+        .unwrap();
+    let events = EventBus::default();
+    let sync = || {
+        nubank::sync_integration(
+            &database,
+            &transport,
+            &events,
+            &user_id,
+            SECRET,
+            &confirmed.id,
+        )
+    };
+    assert_eq!(sync().await.unwrap(), report_of(4, 0, 0));
+    assert_eq!(sync().await.unwrap(), report_of(0, 4, 0));
+    assert_eq!(
+        database.get_account(&account.id, &user_id).unwrap().balance,
+        Money::from_cents(96200)
+    );
+}
@@ -0,0 +1,136 @@
+use cashtools::{
+    auth::{self, AuthError},
+    entities::{
+        user::{NewUser, UserModel},
+        Env,
+    },
+    services::session::{self, SessionServiceError},
+};
+use chrono::Duration;
+mod common;
+use uuid::Uuid;
+
+const SECRET: &str = "1234567890987654321";
+const LOGIN_CODE: i32 = 123456;
+
+#[test]
+fn sessions_are_refreshed_and_revoked() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("session-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Session Test".to_string(),
+            username: format!("session-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+
+    // Codes are burned once used, so each login gets a new one.
+    let login = |device: &str| {
+        conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+            .expect(common::DEFAULT_MESSAGE);
+        session::login(
+            &conn,
+            &email,
+            LOGIN_CODE,
+            Some(device.to_string()),
+            SECRET,
+            &Env::PROD,
+            None,
+            common::now(),
+        )
+        .expect(common::DEFAULT_MESSAGE)
+    };
+    let phone = login("phone");
+    let laptop = login("laptop");
+    let claims = auth::verify_token(&conn, common::now(), &phone.access_token, SECRET)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(claims.user_id, user_id);
+    assert_eq!(claims.session, phone.session.id);
+
+    // A refresh token works once, and using it again revokes the session.
+    let later = common::now() + Duration::minutes(5);
+    let refreshed = session::refresh(&conn, &phone.refresh_token, SECRET, later)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(refreshed.session.id, phone.session.id);
+    assert_eq!(refreshed.session.last_used_at, later);
+    assert!(auth::verify_token(&conn, later, &refreshed.access_token, SECRET).is_ok());
+    assert!(matches!(
+        session::refresh(&conn, &phone.refresh_token, SECRET, later),
+        Err(SessionServiceError::InvalidRefreshToken)
+    ));
+    assert!(matches!(
+        auth::verify_token(&conn, later, &refreshed.access_token, SECRET),
+        Err(AuthError::RevokedSession)
+    ));
+    assert!(matches!(
+        session::refresh(&conn, &refreshed.refresh_token, SECRET, later),
+        Err(SessionServiceError::InvalidRefreshToken)
+    ));
+
+    let tablet = login("tablet");
+    let sessions = session::list_sessions(&conn, &user_id).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(sessions.len(), 2);
+
+    session::logout(&conn, &user_id, &laptop.session.id, later).expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        auth::verify_token(&conn, later, &laptop.access_token, SECRET),
+        Err(AuthError::RevokedSession)
+    ));
+    assert!(matches!(
+        session::refresh(&conn, &laptop.refresh_token, SECRET, later),
+        Err(SessionServiceError::InvalidRefreshToken)
+    ));
+    assert!(auth::verify_token(&conn, later, &tablet.access_token, SECRET).is_ok());
+
+    let revoked =
+        session::logout_everywhere(&conn, &user_id, later).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(revoked.len(), 1);
+    assert!(session::list_sessions(&conn, &user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .is_empty());
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn idle_sessions_cannot_be_refreshed() {
+    let conn = common::make_conn();
+    let unique = Uuid::new_v4();
+    let email = format!("idle-session-{}@example.com", unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Idle Session Test".to_string(),
+            username: format!("idle-session-{}", unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    conn.refresh_login_code(&email, LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+
+    let tokens = session::login(
+        &conn,
+        &email,
+        LOGIN_CODE,
+        None,
+        SECRET,
+        &Env::PROD,
+        None,
+        common::now(),
+    )
+    .expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        session::refresh(
+            &conn,
+            &tokens.refresh_token,
+            SECRET,
+            common::now() + Duration::days(31)
+        ),
+        Err(SessionServiceError::InvalidRefreshToken)
+    ));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
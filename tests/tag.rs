@@ -0,0 +1,64 @@
+use cashtools::entities::{
+    account::AccountModel,
+    money::Money,
+    tag::{NewTag, TagModel, TagModelError, UpdatedTag},
+    user::UserModel,
+};
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn tag_lifecycle() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let account = conn
+        .create_account(user_id, common::new_account("Wallet", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let new_tag = || NewTag {
+        name: "Investments".to_string(),
+        color: Some("#00FF00".to_string()),
+        time: common::now(),
+    };
+
+    let tag = conn
+        .create_tag(&user_id, new_tag())
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(matches!(
+        conn.create_tag(&user_id, new_tag()),
+        Err(TagModelError::TagNameAlreadyTaken)
+    ));
+
+    let renamed = conn
+        .edit_tag(
+            &tag.id,
+            &user_id,
+            UpdatedTag {
+                name: Some("Savings".to_string()),
+                color: None,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(renamed.name, "Savings");
+    assert_eq!(renamed.color, Some("#00FF00".to_string()));
+
+    conn.tag_account(&account.id, &tag.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    conn.tag_account(&account.id, &tag.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let account_tags = conn
+        .list_account_tags(&user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(account_tags.len(), 1);
+
+    assert!(conn
+        .tag_account(&account.id, &tag.id, &Uuid::new_v4())
+        .is_err());
+
+    conn.delete_tag(&tag.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let account_tags = conn
+        .list_account_tags(&user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(account_tags.is_empty());
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
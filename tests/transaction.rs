@@ -0,0 +1,309 @@
+use cashtools::entities::{
+    account::AccountModel,
+    money::Money,
+    transaction::{
+        NewTransaction, TransactionCursor, TransactionFilter, TransactionKind, TransactionModel,
+        TransactionPageRequest, UpdatedTransaction,
+    },
+    user::UserModel,
+    Order,
+};
+use chrono::Duration;
+mod common;
+use uuid::Uuid;
+
+#[test]
+fn create_transaction_updates_balances() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let from = conn
+        .create_account(
+            user_id,
+            common::new_account("Wallet", Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let to = conn
+        .create_account(user_id, common::new_account("Market", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+
+    conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: common::now().date(),
+            entry_account_code: Some(to.id),
+            exit_account_code: Some(from.id),
+            amount: Money::from_cents(4000),
+            description: None,
+            kind: TransactionKind::MANUAL,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+
+    let from = conn
+        .get_account(&from.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let to = conn
+        .get_account(&to.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(from.balance, Money::from_cents(6000));
+    assert_eq!(to.balance, Money::from_cents(4000));
+
+    let recalculated = conn
+        .recalculate_balances(&user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    let balance_of = |id: Uuid| recalculated.iter().find(|a| a.id == id).unwrap().balance;
+    assert_eq!(balance_of(from.id), Money::from_cents(6000));
+    assert_eq!(balance_of(to.id), Money::from_cents(4000));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn transaction_on_unknown_account_is_rolled_back() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let result = conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: common::now().date(),
+            entry_account_code: Some(Uuid::new_v4()),
+            exit_account_code: None,
+            amount: Money::from_cents(4000),
+            description: None,
+            kind: TransactionKind::MANUAL,
+        },
+    );
+    assert!(result.is_err());
+    let transactions = conn
+        .list_user_transactions(&user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(transactions.is_empty());
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn edit_void_and_delete_transaction_keep_balances() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let account = conn
+        .create_account(
+            user_id,
+            common::new_account("Wallet", Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let balance = |conn: &cashtools::database::DbPool| {
+        conn.get_account(&account.id, &user_id)
+            .expect(common::DEFAULT_MESSAGE)
+            .balance
+    };
+
+    let created = conn
+        .create_transaction(
+            &user_id,
+            NewTransaction {
+                entry_date: common::now().date(),
+                entry_account_code: None,
+                exit_account_code: Some(account.id),
+                amount: Money::from_cents(3000),
+                description: None,
+                kind: TransactionKind::MANUAL,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(balance(&conn), Money::from_cents(7000));
+
+    conn.edit_transaction(
+        &created.id,
+        &user_id,
+        UpdatedTransaction {
+            entry_date: None,
+            entry_account_code: None,
+            exit_account_code: None,
+            amount: Some(Money::from_cents(2500)),
+            description: None,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(balance(&conn), Money::from_cents(7500));
+
+    let voided = conn
+        .void_transaction(&created.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(voided.voided);
+    assert_eq!(balance(&conn), Money::from_cents(10000));
+    assert!(conn.void_transaction(&created.id, &user_id).is_err());
+
+    conn.delete_transaction(&created.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(balance(&conn), Money::from_cents(10000));
+    assert!(conn.delete_transaction(&created.id, &user_id).is_err());
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn edit_clears_accounts_and_description_and_may_change_nothing() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let from = conn
+        .create_account(
+            user_id,
+            common::new_account("Wallet", Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let to = conn
+        .create_account(user_id, common::new_account("Market", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let balance = |id: &Uuid| {
+        conn.get_account(id, &user_id)
+            .expect(common::DEFAULT_MESSAGE)
+            .balance
+    };
+    let unchanged = || UpdatedTransaction {
+        entry_date: None,
+        entry_account_code: None,
+        exit_account_code: None,
+        amount: None,
+        description: None,
+    };
+
+    let created = conn
+        .create_transaction(
+            &user_id,
+            NewTransaction {
+                entry_date: common::now().date(),
+                entry_account_code: Some(to.id),
+                exit_account_code: Some(from.id),
+                amount: Money::from_cents(3000),
+                description: Some("Rent".to_string()),
+                kind: TransactionKind::MANUAL,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+
+    let edited = conn
+        .edit_transaction(&created.id, &user_id, unchanged())
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(edited.description, Some("Rent".to_string()));
+    assert_eq!(balance(&to.id), Money::from_cents(3000));
+
+    let edited = conn
+        .edit_transaction(
+            &created.id,
+            &user_id,
+            UpdatedTransaction {
+                entry_account_code: Some(None),
+                description: Some(None),
+                ..unchanged()
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(edited.entry_account_code, None);
+    assert_eq!(edited.exit_account_code, Some(from.id));
+    assert_eq!(edited.description, None);
+    assert_eq!(balance(&from.id), Money::from_cents(7000));
+    assert_eq!(balance(&to.id), Money::ZERO);
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn filter_and_paginate_transactions() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let account = conn
+        .create_account(user_id, common::new_account("Market", Money::ZERO))
+        .expect(common::DEFAULT_MESSAGE);
+    let today = common::now().date();
+
+    for day in 0..5 {
+        conn.create_transaction(
+            &user_id,
+            NewTransaction {
+                entry_date: today + Duration::days(day),
+                entry_account_code: Some(account.id),
+                exit_account_code: None,
+                amount: Money::from_cents(1000 * (day + 1)),
+                description: Some(format!("Salary 100% #{}", day)),
+                kind: TransactionKind::MANUAL,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    }
+
+    let filter = TransactionFilter {
+        account: Some(account.id),
+        min_amount: Some(Money::from_cents(2000)),
+        description: Some("100%".to_string()),
+        ..TransactionFilter::default()
+    };
+    let first_page = conn
+        .filter_user_transactions(
+            &user_id,
+            &filter,
+            &TransactionPageRequest {
+                first: 2,
+                after: None,
+                order: Order::ASC,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let amounts: Vec<i64> = first_page.iter().map(|t| t.amount.cents()).collect();
+    assert_eq!(amounts, vec![2000, 3000, 4000]);
+
+    let last = &first_page[1];
+    let second_page = conn
+        .filter_user_transactions(
+            &user_id,
+            &filter,
+            &TransactionPageRequest {
+                first: 2,
+                after: Some(TransactionCursor {
+                    entry_date: last.entry_date,
+                    id: last.id,
+                }),
+                order: Order::ASC,
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let amounts: Vec<i64> = second_page.iter().map(|t| t.amount.cents()).collect();
+    assert_eq!(amounts, vec![4000, 5000]);
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn imported_transactions_are_deduplicated() {
+    let conn = common::make_conn();
+    let user_id = common::new_user(&conn);
+    let account = conn
+        .create_account(
+            user_id,
+            common::new_account("Wallet", Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    let imported = || NewTransaction {
+        entry_date: common::now().date(),
+        entry_account_code: None,
+        exit_account_code: Some(account.id),
+        amount: Money::from_cents(1250),
+        description: Some("Padaria".to_string()),
+        kind: TransactionKind::IMPORT,
+    };
+
+    let first = conn
+        .import_transaction(&user_id, "nubank:card:5f1c", imported())
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(first.is_some());
+    let second = conn
+        .import_transaction(&user_id, "nubank:card:5f1c", imported())
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(second.is_none());
+
+    let balance = conn
+        .get_account(&account.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .balance;
+    assert_eq!(balance, Money::from_cents(8750));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
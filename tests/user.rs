@@ -0,0 +1,265 @@
+use cashtools::{
+    entities::{
+        account::AccountModel,
+        integration::{IntegrationModel, IntegrationStatus, NewUserIntegration},
+        money::Money,
+        session::{NewSession, SessionModel},
+        tag::{NewTag, TagModel},
+        transaction::{NewTransaction, TransactionKind, TransactionModel},
+        user::{NewUser, UpdatedProfile, UserModel},
+        Env, Language,
+    },
+    sendemail::InMemoryMailer,
+    services::user::{
+        confirm_email_change, delete_user, purge_scheduled_deletions, update_profile,
+        verify_login_code, UserServiceError, TEST_LOGIN_CODE,
+    },
+};
+use chrono::Duration;
+mod common;
+use uuid::Uuid;
+
+fn create_user(conn: &cashtools::database::DbPool, prefix: &str) -> (Uuid, String) {
+    let unique = Uuid::new_v4();
+    let email = format!("{}-{}@example.com", prefix, unique);
+    let user_id = conn
+        .create_user(NewUser {
+            name: "Deletion Test".to_string(),
+            username: format!("{}-{}", prefix, unique),
+            email: email.clone(),
+        })
+        .expect(common::DEFAULT_MESSAGE)
+        .id;
+    (user_id, email)
+}
+
+#[test]
+fn deleting_a_user_deletes_everything_they_own() {
+    let conn = common::make_conn();
+    let (user_id, _) = create_user(&conn, "cascade");
+    let account = conn
+        .create_account(
+            user_id,
+            common::new_account("Wallet", Money::from_cents(10000)),
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_transaction(
+        &user_id,
+        NewTransaction {
+            entry_date: common::now().date(),
+            entry_account_code: None,
+            exit_account_code: Some(account.id),
+            amount: Money::from_cents(1000),
+            description: None,
+            kind: TransactionKind::MANUAL,
+        },
+    )
+    .expect(common::DEFAULT_MESSAGE);
+    let tag = conn
+        .create_tag(
+            &user_id,
+            NewTag {
+                name: "Cascade".to_string(),
+                color: None,
+                time: common::now(),
+            },
+        )
+        .expect(common::DEFAULT_MESSAGE);
+    conn.tag_account(&account.id, &tag.id, &user_id)
+        .expect(common::DEFAULT_MESSAGE);
+    conn.create_integration(NewUserIntegration {
+        related_user: user_id,
+        name: "Nubank".to_string(),
+        time: common::now(),
+        status: IntegrationStatus::CREATED,
+        sent_to: None,
+        state: None,
+    })
+    .expect(common::DEFAULT_MESSAGE);
+    conn.create_session(NewSession {
+        related_user: user_id,
+        refresh_token_hash: Uuid::new_v4().to_string(),
+        device_name: None,
+        time: common::now(),
+    })
+    .expect(common::DEFAULT_MESSAGE);
+
+    let deleted = delete_user(&conn, user_id, None, common::now()).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(deleted.id, user_id);
+    assert_eq!(deleted.integrations.len(), 1);
+
+    assert!(conn.get_user(user_id).is_err());
+    assert!(conn.get_account(&account.id, &user_id).is_err());
+    assert!(conn
+        .list_user_tags(&user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .is_empty());
+    assert!(conn
+        .list_user_integrations(&user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .is_empty());
+    assert!(conn
+        .list_active_sessions(&user_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .is_empty());
+}
+
+#[test]
+fn scheduled_deletion_is_cancelled_by_logging_in() {
+    let conn = common::make_conn();
+    let (user_id, email) = create_user(&conn, "grace");
+
+    assert!(delete_user(&conn, user_id, Some(0), common::now()).is_err());
+    let scheduled =
+        delete_user(&conn, user_id, Some(7), common::now()).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(
+        scheduled.deletion_scheduled_for,
+        Some(common::now() + Duration::days(7))
+    );
+
+    conn.refresh_login_code(&email, 123456, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    verify_login_code(&conn, &email, 123456, &Env::PROD, None, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    let restored = conn.get_user(user_id).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(restored.deletion_scheduled_for, None);
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn scheduled_deletion_runs_once_the_grace_period_is_over() {
+    let conn = common::make_conn();
+    let (user_id, _) = create_user(&conn, "purge");
+
+    delete_user(&conn, user_id, Some(7), common::now()).expect(common::DEFAULT_MESSAGE);
+    purge_scheduled_deletions(&conn, common::now() + Duration::days(6))
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(conn.get_user(user_id).is_ok());
+
+    purge_scheduled_deletions(&conn, common::now() + Duration::days(7))
+        .expect(common::DEFAULT_MESSAGE);
+    assert!(conn.get_user(user_id).is_err());
+}
+
+fn profile(
+    name: Option<&str>,
+    username: Option<&str>,
+    payday: Option<Option<i32>>,
+) -> UpdatedProfile {
+    UpdatedProfile {
+        name: name.map(|x| x.to_string()),
+        username: username.map(|x| x.to_string()),
+        payday,
+    }
+}
+
+#[test]
+fn profile_is_updated_only_when_every_field_is_valid() {
+    let conn = common::make_conn();
+    let mailer = InMemoryMailer::default();
+    let (user_id, _) = create_user(&conn, "profile");
+    let (other_id, _) = create_user(&conn, "profile-other");
+    let other_username = conn
+        .get_user(other_id)
+        .expect(common::DEFAULT_MESSAGE)
+        .username;
+    let update = |profile: UpdatedProfile| {
+        update_profile(
+            &conn,
+            &mailer,
+            user_id,
+            profile,
+            None,
+            Language::EN,
+            &Env::TEST,
+            common::now(),
+        )
+    };
+
+    let new_username = format!("renamed-{}", Uuid::new_v4());
+    let updated = update(profile(
+        Some("New Name"),
+        Some(&new_username),
+        Some(Some(5)),
+    ))
+    .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(updated.name, "New Name");
+    assert_eq!(updated.username, new_username);
+    assert_eq!(updated.payday, Some(5));
+
+    assert!(matches!(
+        update(profile(Some("Other Name"), Some(&other_username), None)),
+        Err(UserServiceError::UsernameNotAvailable(_))
+    ));
+    assert!(matches!(
+        update(profile(Some("Other Name"), None, Some(Some(32)))),
+        Err(UserServiceError::InvalidPayday(32))
+    ));
+    let user = conn.get_user(user_id).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(user.name, "New Name");
+    assert_eq!(user.payday, Some(5));
+
+    // Keeping the current username is fine.
+    assert!(update(profile(None, Some(&new_username), None)).is_ok());
+
+    let updated = update(profile(None, None, Some(None))).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(updated.payday, None);
+    assert_eq!(updated.name, "New Name");
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&other_id).expect(common::DEFAULT_MESSAGE);
+}
+
+#[test]
+fn email_changes_once_the_new_address_is_confirmed() {
+    let conn = common::make_conn();
+    let mailer = InMemoryMailer::default();
+    let (user_id, old_email) = create_user(&conn, "email-change");
+    let (other_id, other_email) = create_user(&conn, "email-change-other");
+    let change_email = |email: &str| {
+        update_profile(
+            &conn,
+            &mailer,
+            user_id,
+            profile(None, None, None),
+            Some(email.to_string()),
+            Language::EN,
+            &Env::TEST,
+            common::now(),
+        )
+    };
+
+    assert!(matches!(
+        change_email(&other_email),
+        Err(UserServiceError::EmailNotAvailable(_))
+    ));
+    assert!(matches!(
+        confirm_email_change(&conn, user_id, TEST_LOGIN_CODE, common::now()),
+        Err(UserServiceError::NoPendingEmailChange)
+    ));
+
+    let new_email = format!("new-{}@example.com", Uuid::new_v4());
+    let pending = change_email(&new_email).expect(common::DEFAULT_MESSAGE);
+    assert_eq!(pending.email, old_email);
+    assert_eq!(pending.pending_email, Some(new_email.clone()));
+    let sent = mailer.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, new_email);
+    assert!(sent[0].text.contains(&TEST_LOGIN_CODE.to_string()));
+
+    assert!(matches!(
+        confirm_email_change(&conn, user_id, TEST_LOGIN_CODE + 1, common::now()),
+        Err(UserServiceError::LoginCodeNotMatching)
+    ));
+    let confirmed = confirm_email_change(&conn, user_id, TEST_LOGIN_CODE, common::now())
+        .expect(common::DEFAULT_MESSAGE);
+    assert_eq!(confirmed.email, new_email);
+    assert_eq!(confirmed.pending_email, None);
+    assert!(conn
+        .check_if_email_available(&old_email)
+        .expect(common::DEFAULT_MESSAGE));
+
+    conn.delete_user(&user_id).expect(common::DEFAULT_MESSAGE);
+    conn.delete_user(&other_id).expect(common::DEFAULT_MESSAGE);
+}